@@ -1,11 +1,13 @@
 import { useState, useMemo, useRef } from "react";
-import { Container, Cpu } from "lucide-react";
+import { Container, Cpu, Gauge, Info, TriangleAlert } from "lucide-react";
 import devData from "../data/test.json";
 import type { ImageInfo, FileEntry } from "@/types";
 import { formatBytes } from "@/lib/format";
 import { LayerList } from "@/components/LayerList";
 import { FilePanel } from "@/components/FilePanel";
 import { Toolbar, type ViewMode, type FileViewMode } from "@/components/Toolbar";
+import { ConfigDialog } from "@/components/ConfigDialog";
+import { EfficiencyDialog, scoreColor } from "@/components/EfficiencyDialog";
 import { useSectionFocus } from "@/hooks/useSectionFocus";
 
 function loadData(): ImageInfo {
@@ -21,6 +23,8 @@ function App() {
   const [viewMode, setViewMode] = useState<ViewMode>("layer");
   const [fileViewMode, setFileViewMode] = useState<FileViewMode>("tree");
   const [filter, setFilter] = useState("");
+  const [showConfig, setShowConfig] = useState(false);
+  const [showEfficiency, setShowEfficiency] = useState(false);
 
   const toolbarRef = useRef<HTMLDivElement>(null);
   const layerRef = useRef<HTMLDivElement>(null);
@@ -40,10 +44,16 @@ function App() {
     if (viewMode === "layer") {
       return image.layers[selectedLayer].files;
     }
-    // Accumulated: all files from layer 0 through selected
+    // Accumulated: the filesystem as of the selected layer, i.e. every
+    // entry up to it that no layer up to it replaced or deleted
+    const shadowed = (later: number | null) => later !== null && later <= selectedLayer;
     const all: FileEntry[] = [];
     for (let i = 0; i <= selectedLayer; i++) {
-      all.push(...image.layers[i].files);
+      for (const f of image.layers[i].files) {
+        if (!f.is_whiteout && !shadowed(f.overwritten_in) && !shadowed(f.deleted_in)) {
+          all.push(f);
+        }
+      }
     }
     return all;
   }, [selectedLayer, viewMode]);
@@ -64,15 +74,46 @@ function App() {
             {image.architecture && (
               <span className="flex items-center gap-1">
                 <Cpu className="size-3.5" />
-                {image.architecture}
+                {[image.os, image.architecture, image.variant].filter(Boolean).join("/")}
               </span>
             )}
             <span>{formatBytes(image.total_size)}</span>
             <span>{image.layers.length} layers</span>
+            {image.created && <span title={image.created}>{image.created.slice(0, 10)}</span>}
+            <button
+              className="flex items-center gap-1 px-1.5 py-0.5 rounded hover:bg-muted hover:text-foreground transition-colors"
+              onClick={() => setShowConfig(true)}
+              title="View image config"
+            >
+              <Info className="size-3.5" />
+              Config
+            </button>
+            <button
+              className="flex items-center gap-1 px-1.5 py-0.5 rounded hover:bg-muted hover:text-foreground transition-colors"
+              onClick={() => setShowEfficiency(true)}
+              title="View wasted space"
+            >
+              <Gauge className="size-3.5" />
+              <span className={scoreColor(image.efficiency.score)}>
+                {(image.efficiency.score * 100).toFixed(1)}%
+              </span>
+              efficient
+            </button>
           </div>
         </div>
+        {image.warnings.map((warning) => (
+          <p key={warning} className="flex items-center gap-1.5 mt-1 text-xs text-amber-600">
+            <TriangleAlert className="size-3.5 shrink-0" />
+            {warning}
+          </p>
+        ))}
       </div>
 
+      {showConfig && <ConfigDialog image={image} onClose={() => setShowConfig(false)} />}
+      {showEfficiency && (
+        <EfficiencyDialog efficiency={image.efficiency} onClose={() => setShowEfficiency(false)} />
+      )}
+
       {/* Toolbar */}
       <Toolbar
         viewMode={viewMode}
@@ -90,6 +131,7 @@ function App() {
         <div className="w-80 shrink-0 border-r">
           <LayerList
             layers={image.layers}
+            history={image.history}
             selectedIndex={selectedLayer}
             onSelect={setSelectedLayer}
             viewMode={viewMode}
@@ -0,0 +1,127 @@
+import { useEffect, type ReactNode } from "react";
+import { X } from "lucide-react";
+import type { ImageInfo } from "@/types";
+import { formatDuration } from "@/lib/format";
+
+/** A Docker-style exec form, e.g. `["python", "app.py"]`. */
+function execForm(args: string[]): string {
+  return JSON.stringify(args);
+}
+
+function Row({ label, children }: { label: string; children: ReactNode }) {
+  return (
+    <>
+      <dt className="text-muted-foreground whitespace-nowrap">{label}</dt>
+      <dd className="font-mono text-xs min-w-0 break-all">{children}</dd>
+    </>
+  );
+}
+
+function Lines({ items }: { items: string[] }) {
+  return items.map((item) => <div key={item}>{item}</div>);
+}
+
+export function ConfigDialog({
+  image,
+  onClose,
+}: {
+  image: ImageInfo;
+  onClose: () => void;
+}) {
+  const { config } = image;
+  const health = config.healthcheck;
+  const labels = Object.entries(config.labels).map(([k, v]) => `${k}=${v}`);
+
+  useEffect(() => {
+    function onKey(e: KeyboardEvent) {
+      if (e.key === "Escape") onClose();
+    }
+    document.addEventListener("keydown", onKey);
+    return () => document.removeEventListener("keydown", onKey);
+  }, [onClose]);
+
+  return (
+    <div
+      role="dialog"
+      className="fixed inset-0 z-50 flex items-center justify-center bg-black/50"
+      onClick={onClose}
+    >
+      <div
+        className="bg-background border rounded-lg shadow-lg max-w-3xl w-full mx-4 max-h-[80vh] flex flex-col"
+        onClick={(e) => e.stopPropagation()}
+      >
+        {/* Header */}
+        <div className="flex items-center justify-between px-4 py-3 border-b shrink-0">
+          <h2 className="text-sm font-medium">Image Config</h2>
+          <button
+            className="p-1.5 rounded hover:bg-muted text-muted-foreground hover:text-foreground transition-colors"
+            onClick={onClose}
+            title="Close"
+          >
+            <X className="size-4" />
+          </button>
+        </div>
+
+        {/* Body */}
+        <dl className="overflow-auto p-4 grid grid-cols-[auto_1fr] gap-x-4 gap-y-1.5 text-sm">
+          <Row label="ID">{image.id}</Row>
+          {image.created && <Row label="Created">{image.created}</Row>}
+          {image.repo_tags.length > 0 && (
+            <Row label="Tags">
+              <Lines items={image.repo_tags} />
+            </Row>
+          )}
+          {image.repo_digests.length > 0 && (
+            <Row label="Digests">
+              <Lines items={image.repo_digests} />
+            </Row>
+          )}
+          {image.architecture && (
+            <Row label="Platform">
+              {[image.os, image.architecture, image.variant].filter(Boolean).join("/")}
+            </Row>
+          )}
+          {config.entrypoint && <Row label="Entrypoint">{execForm(config.entrypoint)}</Row>}
+          {config.cmd && <Row label="Cmd">{execForm(config.cmd)}</Row>}
+          {config.user && <Row label="User">{config.user}</Row>}
+          {config.working_dir && <Row label="WorkingDir">{config.working_dir}</Row>}
+          {config.env.length > 0 && (
+            <Row label="Env">
+              <Lines items={config.env} />
+            </Row>
+          )}
+          {config.exposed_ports.length > 0 && (
+            <Row label="Ports">{config.exposed_ports.join(", ")}</Row>
+          )}
+          {config.volumes.length > 0 && (
+            <Row label="Volumes">
+              <Lines items={config.volumes} />
+            </Row>
+          )}
+          {labels.length > 0 && (
+            <Row label="Labels">
+              <Lines items={labels} />
+            </Row>
+          )}
+          {config.stop_signal && <Row label="StopSignal">{config.stop_signal}</Row>}
+          {health && (
+            <Row label="Healthcheck">
+              <div>{execForm(health.test)}</div>
+              <div className="text-muted-foreground">
+                {[
+                  health.interval && `interval ${formatDuration(health.interval)}`,
+                  health.timeout && `timeout ${formatDuration(health.timeout)}`,
+                  health.start_period && `start period ${formatDuration(health.start_period)}`,
+                  health.start_interval && `start interval ${formatDuration(health.start_interval)}`,
+                  health.retries && `${health.retries} retries`,
+                ]
+                  .filter(Boolean)
+                  .join(", ")}
+              </div>
+            </Row>
+          )}
+        </dl>
+      </div>
+    </div>
+  );
+}
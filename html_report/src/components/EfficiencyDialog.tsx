@@ -0,0 +1,114 @@
+import { useEffect } from "react";
+import { X } from "lucide-react";
+import type { Efficiency, Waste } from "@/types";
+import { formatBytes } from "@/lib/format";
+import { cn } from "@/lib/utils";
+
+/** Text color for an efficiency score, from 0 to 1. */
+export function scoreColor(score: number): string {
+  if (score >= 0.95) return "text-green-600";
+  if (score >= 0.8) return "text-amber-600";
+  return "text-red-500";
+}
+
+const WASTE_LABELS: Record<Waste, string> = {
+  overwritten: "overwritten",
+  deleted: "deleted",
+  same_size_rewrite: "same-size rewrite",
+};
+
+export function EfficiencyDialog({
+  efficiency,
+  onClose,
+}: {
+  efficiency: Efficiency;
+  onClose: () => void;
+}) {
+  useEffect(() => {
+    function onKey(e: KeyboardEvent) {
+      if (e.key === "Escape") onClose();
+    }
+    document.addEventListener("keydown", onKey);
+    return () => document.removeEventListener("keydown", onKey);
+  }, [onClose]);
+
+  const breakdown = [
+    { label: "Overwritten", bytes: efficiency.overwritten_bytes },
+    { label: "Deleted", bytes: efficiency.deleted_bytes },
+    { label: "Same-size rewrite", bytes: efficiency.same_size_rewrite_bytes },
+  ];
+
+  return (
+    <div
+      role="dialog"
+      className="fixed inset-0 z-50 flex items-center justify-center bg-black/50"
+      onClick={onClose}
+    >
+      <div
+        className="bg-background border rounded-lg shadow-lg max-w-3xl w-full mx-4 max-h-[80vh] flex flex-col"
+        onClick={(e) => e.stopPropagation()}
+      >
+        {/* Header */}
+        <div className="flex items-center justify-between px-4 py-3 border-b shrink-0">
+          <h2 className="text-sm font-medium">Wasted Space</h2>
+          <button
+            className="p-1.5 rounded hover:bg-muted text-muted-foreground hover:text-foreground transition-colors"
+            onClick={onClose}
+            title="Close"
+          >
+            <X className="size-4" />
+          </button>
+        </div>
+
+        {/* Summary */}
+        <div className="flex items-baseline gap-6 px-4 py-3 border-b shrink-0 text-sm">
+          <div>
+            <span className={cn("text-2xl font-bold", scoreColor(efficiency.score))}>
+              {(efficiency.score * 100).toFixed(1)}%
+            </span>
+            <span className="ml-1.5 text-muted-foreground">efficient</span>
+          </div>
+          <div className="text-muted-foreground">
+            <span className="font-medium text-foreground">{formatBytes(efficiency.wasted_bytes)}</span> wasted
+            of {formatBytes(efficiency.total_bytes)}
+          </div>
+          {breakdown.map(({ label, bytes }) => (
+            <div key={label} className="text-xs text-muted-foreground">
+              {label} <span className="text-foreground tabular-nums">{formatBytes(bytes)}</span>
+            </div>
+          ))}
+        </div>
+
+        {/* Offenders */}
+        <div className="overflow-auto p-2">
+          {efficiency.offenders.length === 0 ? (
+            <p className="p-4 text-center text-sm text-muted-foreground">Nothing wasted</p>
+          ) : (
+            <table className="w-full text-sm">
+              <thead className="text-xs text-muted-foreground">
+                <tr>
+                  <th className="px-2 py-1 text-right font-medium">Wasted</th>
+                  <th className="px-2 py-1 text-right font-medium">Copies</th>
+                  <th className="px-2 py-1 text-left font-medium">Reason</th>
+                  <th className="px-2 py-1 text-left font-medium">Path</th>
+                </tr>
+              </thead>
+              <tbody>
+                {efficiency.offenders.map((offender) => (
+                  <tr key={offender.path} className="hover:bg-muted/50">
+                    <td className="px-2 py-0.5 text-right tabular-nums whitespace-nowrap">
+                      {formatBytes(offender.wasted_bytes)}
+                    </td>
+                    <td className="px-2 py-0.5 text-right tabular-nums">{offender.copies}</td>
+                    <td className="px-2 py-0.5 text-xs text-muted-foreground">{WASTE_LABELS[offender.waste]}</td>
+                    <td className="px-2 py-0.5 font-mono text-xs break-all">{offender.path}</td>
+                  </tr>
+                ))}
+              </tbody>
+            </table>
+          )}
+        </div>
+      </div>
+    </div>
+  );
+}
@@ -1,6 +1,7 @@
 import { useState, useMemo, useRef, useCallback, useEffect, type RefObject } from "react";
 import { File, ArrowUp, ArrowDown, ArrowUpDown } from "lucide-react";
-import { formatBytes } from "@/lib/format";
+import type { Change, FileEntry } from "@/types";
+import { formatBytes, formatMode, formatTime } from "@/lib/format";
 import { cn } from "@/lib/utils";
 import { usePagination } from "@/hooks/usePagination";
 import { Pagination } from "./Pagination";
@@ -11,6 +12,57 @@ export interface FileListItem {
   name: string;
   size: number;
   is_whiteout: boolean;
+  entry: FileEntry | null;
+}
+
+/** Tooltip with the metadata that doesn't fit in a row. */
+function describe(entry: FileEntry): string {
+  const lines = [
+    `${formatMode(entry.kind, entry.mode)} ${entry.uid}:${entry.gid}`,
+    `modified ${formatTime(entry.mtime)}`,
+  ];
+  if (entry.link_target !== null) {
+    lines.push(`${entry.kind === "hardlink" ? "link to" : "→"} ${entry.link_target}`);
+  }
+  for (const [name, value] of Object.entries(entry.xattrs)) {
+    lines.push(`${name}=${value}`);
+  }
+  return lines.join("\n");
+}
+
+/** What a later layer did to the entry, if anything. */
+function Fate({ entry }: { entry: FileEntry }) {
+  const layer = entry.deleted_in ?? entry.overwritten_in;
+  if (layer === null) return null;
+  const label = entry.deleted_in !== null ? "deleted" : "overwritten";
+  return (
+    <span className="shrink-0 text-[10px] text-amber-600 relative" title={`${label} in layer ${layer + 1}`}>
+      {label} in {layer + 1}
+    </span>
+  );
+}
+
+const CHANGE_MARKS = {
+  added: { mark: "A", className: "text-green-600" },
+  modified: { mark: "M", className: "text-amber-600" },
+  deleted: { mark: "D", className: "text-red-500" },
+} as const;
+
+/** Dive-style A/M/D mark for how the entry changes the layers below. */
+function ChangeMark({ change }: { change: Change | null }) {
+  if (change === null) return <span className="w-3 shrink-0" />;
+  const { mark, className } = CHANGE_MARKS[change.type];
+  const detail =
+    change.type === "modified"
+      ? ` (${change.size_delta < 0 ? "-" : "+"}${formatBytes(Math.abs(change.size_delta))})`
+      : change.type === "deleted"
+        ? ` (${formatBytes(change.removed_size)} removed)`
+        : "";
+  return (
+    <span className={cn("w-3 shrink-0 font-mono text-xs font-bold relative", className)} title={change.type + detail}>
+      {mark}
+    </span>
+  );
 }
 
 type SortKey = "name" | "size";
@@ -153,9 +205,13 @@ export function FileList({
         ) : (
           pageItems.map((file, i) => {
             const pct = (file.size / maxSize) * 100;
-            const displayName = file.is_whiteout
-              ? file.name.replace(/^\.wh\./, "")
-              : file.name;
+            // `.wh..wh..opq` marks a directory whose lower contents are hidden
+            const opaque = file.name === ".wh..wh..opq";
+            const displayName = opaque
+              ? "(earlier contents)"
+              : file.is_whiteout
+                ? file.name.replace(/^\.wh\./, "")
+                : file.name;
             return (
               <div
                 key={`${file.name}-${page}-${i}`}
@@ -168,6 +224,7 @@ export function FileList({
                   className="absolute inset-y-0 left-0 bg-primary/5 rounded"
                   style={{ width: `${pct}%` }}
                 />
+                <ChangeMark change={file.entry?.change ?? null} />
                 <File className="size-3.5 shrink-0 text-muted-foreground relative" />
                 <span
                   className={cn(
@@ -176,9 +233,26 @@ export function FileList({
                   )}
                 >
                   {displayName}
-                  {file.is_whiteout && " (deleted)"}
+                  {file.is_whiteout && (opaque ? " (replaced)" : " (deleted)")}
+                  {file.entry?.kind === "symlink" && (
+                    <span className="text-muted-foreground"> → {file.entry.link_target}</span>
+                  )}
                 </span>
-                <span className="ml-auto shrink-0 text-xs text-muted-foreground tabular-nums relative">
+                {file.entry && <Fate entry={file.entry} />}
+                {file.entry && !file.is_whiteout && (
+                  <span
+                    className="ml-auto shrink-0 font-mono text-xs text-muted-foreground relative"
+                    title={describe(file.entry)}
+                  >
+                    {formatMode(file.entry.kind, file.entry.mode)} {file.entry.uid}:{file.entry.gid}
+                  </span>
+                )}
+                <span
+                  className={cn(
+                    "shrink-0 text-xs text-muted-foreground tabular-nums relative",
+                    (!file.entry || file.is_whiteout) && "ml-auto"
+                  )}
+                >
                   {formatBytes(file.size)}
                 </span>
               </div>
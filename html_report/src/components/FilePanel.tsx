@@ -35,11 +35,15 @@ export function FilePanel({
     return <FileTreeSplit files={filtered} treeRef={treeRef} fileRef={fileRef} />;
   }
 
-  const items = filtered.map((f) => ({
-    name: f.path,
-    size: f.size,
-    is_whiteout: f.is_whiteout,
-  }));
+  // Directories show up in the tree view; the flat list is files only
+  const items = filtered
+    .filter((f) => f.kind !== "dir")
+    .map((f) => ({
+      name: f.path,
+      size: f.size,
+      is_whiteout: f.is_whiteout,
+      entry: f,
+    }));
 
   return <FileList items={items} sectionRef={fileRef} />;
 }
@@ -1,6 +1,6 @@
-import { useState, useEffect, type RefObject } from "react";
+import { useState, useEffect, useMemo, type RefObject } from "react";
 import { Layers, Terminal } from "lucide-react";
-import type { LayerInfo } from "@/types";
+import type { HistoryStep, LayerChanges, LayerInfo } from "@/types";
 import type { ViewMode } from "./Toolbar";
 import { formatBytes } from "@/lib/format";
 import { cn } from "@/lib/utils";
@@ -8,14 +8,49 @@ import { CommandDialog } from "./CommandDialog";
 
 const focusRing = "focus:outline-none focus:bg-primary/10";
 
+/** History steps that added no layer, shown between the layers around them. */
+function ConfigSteps({ steps }: { steps: HistoryStep[] }) {
+  return steps.map((step, i) => (
+    <p
+      key={i}
+      className="pl-10 pr-3 text-[10px] text-muted-foreground/70 font-mono truncate leading-tight py-0.5"
+      title={step.created ?? undefined}
+    >
+      {step.empty_layer ? "" : "(unmatched) "}
+      {step.created_by ?? "(unknown step)"}
+    </p>
+  ));
+}
+
+/** Bytes the layer added, changed and removed, where non-zero. */
+function ChangeTotals({ changes }: { changes: LayerChanges }) {
+  const parts = [
+    { bytes: changes.bytes_added, sign: "+", className: "text-green-600", label: "added" },
+    { bytes: changes.bytes_changed, sign: "~", className: "text-amber-600", label: "changed" },
+    { bytes: changes.bytes_removed, sign: "-", className: "text-red-500", label: "removed" },
+  ].filter((part) => part.bytes > 0);
+  return (
+    <span className="ml-auto flex gap-1.5 text-[10px] tabular-nums">
+      {parts.map((part) => (
+        <span key={part.label} className={part.className} title={`${formatBytes(part.bytes)} ${part.label}`}>
+          {part.sign}
+          {formatBytes(part.bytes)}
+        </span>
+      ))}
+    </span>
+  );
+}
+
 export function LayerList({
   layers,
+  history,
   selectedIndex,
   onSelect,
   viewMode,
   sectionRef,
 }: {
   layers: LayerInfo[];
+  history: HistoryStep[];
   selectedIndex: number;
   onSelect: (i: number) => void;
   viewMode: ViewMode;
@@ -23,6 +58,24 @@ export function LayerList({
 }) {
   const [commandLayer, setCommandLayer] = useState<number | null>(null);
 
+  // Group the config-only steps by the layer they precede; whatever comes
+  // after the last layer is listed at the end
+  const { stepsBefore, trailingSteps, layerSteps } = useMemo(() => {
+    const stepsBefore = new Map<number, HistoryStep[]>();
+    const layerSteps = new Map<number, HistoryStep>();
+    let pending: HistoryStep[] = [];
+    for (const step of history) {
+      if (step.layer === null) {
+        pending.push(step);
+      } else {
+        stepsBefore.set(step.layer, pending);
+        layerSteps.set(step.layer, step);
+        pending = [];
+      }
+    }
+    return { stepsBefore, trailingSteps: pending, layerSteps };
+  }, [history]);
+
   // Scroll selected layer into view on keyboard nav
   useEffect(() => {
     const el = sectionRef.current?.querySelector(
@@ -60,68 +113,78 @@ export function LayerList({
         const selected = i === selectedIndex;
         // In accumulated mode, highlight all layers up to selected
         const included = viewMode === "accumulated" && i <= selectedIndex;
+        const created = layerSteps.get(i)?.created;
 
         return (
-          <button
-            key={layer.digest}
-            data-layer-index={i}
-            tabIndex={-1}
-            className={cn(
-              "w-full text-left px-3 py-2 flex items-start gap-2 transition-colors",
-              selected
-                ? "bg-primary/10 border-l-2 border-primary"
-                : included
-                  ? "bg-muted/40 border-l-2 border-primary/30"
-                  : "border-l-2 border-transparent hover:bg-muted/50"
-            )}
-            onClick={() => onSelect(i)}
-          >
-            <span
+          <div key={layer.digest}>
+            <ConfigSteps steps={stepsBefore.get(i) ?? []} />
+            <button
+              data-layer-index={i}
+              tabIndex={-1}
               className={cn(
-                "flex items-center justify-center size-5 rounded-full text-[10px] font-bold shrink-0 mt-0.5",
+                "w-full text-left px-3 py-2 flex items-start gap-2 transition-colors",
                 selected
-                  ? "bg-primary text-primary-foreground"
-                  : "bg-muted text-muted-foreground"
+                  ? "bg-primary/10 border-l-2 border-primary"
+                  : included
+                    ? "bg-muted/40 border-l-2 border-primary/30"
+                    : "border-l-2 border-transparent hover:bg-muted/50"
               )}
+              onClick={() => onSelect(i)}
             >
-              {i + 1}
-            </span>
-            <div className="min-w-0 flex-1">
-              <div className="flex items-center gap-1.5">
-                <Layers className="size-3 text-muted-foreground shrink-0" />
-                <code className="text-xs text-muted-foreground">
-                  {layer.digest.slice(7, 19)}
-                </code>
-              </div>
-              <div className="flex items-center gap-2 mt-0.5 text-xs">
-                <span className="font-medium">{formatBytes(layer.size)}</span>
-                <span className="text-muted-foreground">
-                  {layer.files.length.toLocaleString()} files
-                </span>
-              </div>
-              {layer.created_by && (
-                <div className="flex items-center gap-1 mt-0.5">
-                  <p className="text-[10px] text-muted-foreground font-mono truncate leading-tight flex-1 min-w-0">
-                    {layer.created_by}
-                  </p>
-                  <span
-                    role="button"
-                    tabIndex={-1}
-                    className="shrink-0 p-0.5 rounded hover:bg-muted text-muted-foreground hover:text-foreground transition-colors"
-                    title="View full command"
-                    onClick={(e) => {
-                      e.stopPropagation();
-                      setCommandLayer(i);
-                    }}
-                  >
-                    <Terminal className="size-3" />
+              <span
+                className={cn(
+                  "flex items-center justify-center size-5 rounded-full text-[10px] font-bold shrink-0 mt-0.5",
+                  selected
+                    ? "bg-primary text-primary-foreground"
+                    : "bg-muted text-muted-foreground"
+                )}
+              >
+                {i + 1}
+              </span>
+              <div className="min-w-0 flex-1">
+                <div className="flex items-center gap-1.5">
+                  <Layers className="size-3 text-muted-foreground shrink-0" />
+                  <code className="text-xs text-muted-foreground">
+                    {layer.digest.slice(7, 19)}
+                  </code>
+                  {created && (
+                    <span className="ml-auto text-[10px] text-muted-foreground" title={created}>
+                      {created.slice(0, 10)}
+                    </span>
+                  )}
+                </div>
+                <div className="flex items-center gap-2 mt-0.5 text-xs">
+                  <span className="font-medium">{formatBytes(layer.size)}</span>
+                  <span className="text-muted-foreground">
+                    {layer.files.filter((f) => f.kind !== "dir").length.toLocaleString()} files
                   </span>
+                  <ChangeTotals changes={layer.changes} />
                 </div>
-              )}
-            </div>
-          </button>
+                {layer.created_by && (
+                  <div className="flex items-center gap-1 mt-0.5">
+                    <p className="text-[10px] text-muted-foreground font-mono truncate leading-tight flex-1 min-w-0">
+                      {layer.created_by}
+                    </p>
+                    <span
+                      role="button"
+                      tabIndex={-1}
+                      className="shrink-0 p-0.5 rounded hover:bg-muted text-muted-foreground hover:text-foreground transition-colors"
+                      title="View full command"
+                      onClick={(e) => {
+                        e.stopPropagation();
+                        setCommandLayer(i);
+                      }}
+                    >
+                      <Terminal className="size-3" />
+                    </span>
+                  </div>
+                )}
+              </div>
+            </button>
+          </div>
         );
       })}
+      <ConfigSteps steps={trailingSteps} />
 
       {commandLayer !== null && layers[commandLayer].created_by && (
         <CommandDialog
@@ -1,3 +1,5 @@
+import type { FileKind } from "@/types";
+
 const UNITS = ["B", "KB", "MB", "GB", "TB"] as const;
 
 export function formatBytes(bytes: number): string {
@@ -6,3 +8,46 @@ export function formatBytes(bytes: number): string {
   const value = bytes / Math.pow(1024, i);
   return `${value.toFixed(i === 0 ? 0 : 1)} ${UNITS[i]}`;
 }
+
+const KIND_CHARS: Record<FileKind, string> = {
+  file: "-",
+  dir: "d",
+  symlink: "l",
+  hardlink: "-",
+  char_device: "c",
+  block_device: "b",
+  fifo: "p",
+  socket: "s",
+};
+
+/** `ls -l` style mode string, e.g. "-rwsr-xr-x". */
+export function formatMode(kind: FileKind, mode: number): string {
+  const bits = "rwxrwxrwx"
+    .split("")
+    .map((c, i) => (mode & (0o400 >> i) ? c : "-"));
+  const special = (i: number, flag: number, set: string) => {
+    if (mode & flag) bits[i] = bits[i] === "-" ? set.toUpperCase() : set;
+  };
+  special(2, 0o4000, "s");
+  special(5, 0o2000, "s");
+  special(8, 0o1000, "t");
+  return KIND_CHARS[kind] + bits.join("");
+}
+
+export function formatTime(seconds: number): string {
+  return new Date(seconds * 1000).toISOString().replace("T", " ").slice(0, 19);
+}
+
+/** A Go duration in nanoseconds, e.g. "1m30s". */
+export function formatDuration(ns: number): string {
+  let seconds = Math.round(ns / 1e9);
+  if (seconds === 0) return `${ns / 1e6}ms`;
+  const parts: string[] = [];
+  for (const [unit, size] of [["h", 3600], ["m", 60], ["s", 1]] as const) {
+    if (seconds >= size) {
+      parts.push(`${Math.floor(seconds / size)}${unit}`);
+      seconds %= size;
+    }
+  }
+  return parts.join("");
+}
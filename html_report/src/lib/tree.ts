@@ -7,6 +7,7 @@ export function buildTree(files: FileEntry[]): TreeNode {
     is_whiteout: false,
     children: new Map(),
     isFile: false,
+    entry: null,
   };
 
   for (const file of files) {
@@ -24,14 +25,19 @@ export function buildTree(files: FileEntry[]): TreeNode {
           is_whiteout: false,
           children: new Map(),
           isFile: false,
+          entry: null,
         });
       }
 
       const child = current.children.get(part)!;
       if (isLast) {
-        child.size = file.size;
-        child.is_whiteout = file.is_whiteout;
-        child.isFile = true;
+        child.entry = file;
+        // Directory entries only carry metadata; the node stays a directory
+        if (file.kind !== "dir") {
+          child.size = file.size;
+          child.is_whiteout = file.is_whiteout;
+          child.isFile = true;
+        }
       }
 
       current = child;
@@ -1,22 +1,141 @@
 export interface ImageInfo {
   name: string;
   tag: string | null;
+  /** Config digest, `sha256:...` */
+  id: string;
+  /** Every `name:tag` pointing at this image */
+  repo_tags: string[];
+  /** `name@sha256:...` of the manifests the image was pulled as */
+  repo_digests: string[];
+  created: string | null;
+  config: RuntimeConfig;
+  os: string | null;
   architecture: string | null;
+  variant: string | null;
   total_size: number;
   layers: LayerInfo[];
+  /** The root filesystem the layers add up to, sorted by path */
+  filesystem: MergedEntry[];
+  /** Space wasted on files later layers replace or delete */
+  efficiency: Efficiency;
+  history: HistoryStep[];
+  warnings: string[];
+}
+
+export interface RuntimeConfig {
+  env: string[];
+  entrypoint: string[] | null;
+  cmd: string[] | null;
+  user: string | null;
+  working_dir: string | null;
+  exposed_ports: string[];
+  volumes: string[];
+  labels: Record<string, string>;
+  stop_signal: string | null;
+  healthcheck: Healthcheck | null;
+}
+
+/** Durations are in nanoseconds, as in the image config. */
+export interface Healthcheck {
+  test: string[];
+  interval: number | null;
+  timeout: number | null;
+  start_period: number | null;
+  start_interval: number | null;
+  retries: number | null;
+}
+
+export interface HistoryStep {
+  created: string | null;
+  created_by: string | null;
+  author: string | null;
+  comment: string | null;
+  empty_layer: boolean;
+  /** Index into `layers` of the layer this step added */
+  layer: number | null;
 }
 
 export interface LayerInfo {
   digest: string;
   created_by: string | null;
   size: number;
+  /** What the layer did to the filesystem below it, in bytes */
+  changes: LayerChanges;
+  compression: "uncompressed" | "gzip" | "zstd" | null;
+  compressed_size: number | null;
   files: FileEntry[];
 }
 
+export interface LayerChanges {
+  bytes_added: number;
+  bytes_changed: number;
+  bytes_removed: number;
+}
+
+/** How a layer entry changes the filesystem of the layers below it. */
+export type Change =
+  | { type: "added" }
+  | { type: "modified"; size_delta: number }
+  | { type: "deleted"; removed_size: number };
+
+export type FileKind =
+  | "file"
+  | "dir"
+  | "symlink"
+  | "hardlink"
+  | "char_device"
+  | "block_device"
+  | "fifo"
+  | "socket";
+
 export interface FileEntry {
   path: string;
   size: number;
   is_whiteout: boolean;
+  kind: FileKind;
+  mode: number;
+  uid: number;
+  gid: number;
+  mtime: number;
+  link_target: string | null;
+  xattrs: Record<string, string>;
+  change: Change | null;
+  /** Index of the later layer that put something else at this path */
+  overwritten_in: number | null;
+  /** Index of the later layer that deleted this path */
+  deleted_in: number | null;
+}
+
+export interface Efficiency {
+  /** Share of the layers' bytes that end up in the final filesystem, 0 to 1 */
+  score: number;
+  total_bytes: number;
+  wasted_bytes: number;
+  overwritten_bytes: number;
+  deleted_bytes: number;
+  /** Replaced by a later file of the same size; contents aren't compared */
+  same_size_rewrite_bytes: number;
+  /** The paths wasting the most, largest first */
+  offenders: WastedPath[];
+}
+
+export type Waste = "overwritten" | "deleted" | "same_size_rewrite";
+
+export interface WastedPath {
+  path: string;
+  wasted_bytes: number;
+  /** How many layers hold a wasted copy */
+  copies: number;
+  /** What made the largest copy wasted */
+  waste: Waste;
+}
+
+export interface MergedEntry {
+  path: string;
+  kind: FileKind;
+  size: number;
+  /** Index into `layers` of the layer the path comes from */
+  layer: number;
 }
 
 export interface TreeNode {
@@ -25,4 +144,6 @@ export interface TreeNode {
   is_whiteout: boolean;
   children: Map<string, TreeNode>;
   isFile: boolean;
+  /** The layer entry for this path, if the listing has one */
+  entry: FileEntry | null;
 }
@@ -0,0 +1,151 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use crossterm::style::{self, Stylize};
+use serde::Serialize;
+
+use super::inspect::{format_bytes, image_platform, inspect_image, open_inspector, print_runtime_summary};
+use crate::config;
+use crate::inspector::{Efficiency, ImageInfo, Platform, Waste};
+
+/// How many of the worst offenders the text report lists.
+const TOP_OFFENDERS: usize = 20;
+
+/// An image's wasted-space analysis, as `peel analyze --json` writes it.
+#[derive(Serialize)]
+struct Analysis {
+    name: String,
+    tag: Option<String>,
+    platform: Option<String>,
+    #[serde(flatten)]
+    efficiency: Efficiency,
+}
+
+/// Command-line options for `peel analyze`.
+pub struct AnalyzeOptions<'a> {
+    pub use_oci: bool,
+    pub remote: bool,
+    pub json: Option<&'a str>,
+    pub runtime: Option<String>,
+    pub no_sudo: bool,
+    /// Platform to read from multi-platform images (default: the host's)
+    pub platform: Option<Platform>,
+    /// Analyze every platform of a multi-platform image
+    pub all_platforms: bool,
+    /// Check every layer against the digests the image records
+    pub verify: bool,
+}
+
+/// `peel analyze`: how much of an image's layers is wasted on files that
+/// later layers overwrite or delete, and which paths waste the most.
+pub fn run(image: &str, opts: AnalyzeOptions) -> Result<()> {
+    let AnalyzeOptions { use_oci, remote, json, runtime, no_sudo, platform, all_platforms, verify } = opts;
+    config::init_from_cli(json.is_some(), runtime)?;
+    let (image, mut inspector) = open_inspector(image, use_oci, remote, no_sudo)?;
+    print_runtime_summary(config::get());
+    if let Some(platform) = &platform {
+        inspector.select_platform(platform);
+    }
+    if verify {
+        inspector.enable_verify();
+    }
+
+    let mut infos = Vec::new();
+    let platforms = if all_platforms { inspector.platforms(image)? } else { Vec::new() };
+    if platforms.is_empty() {
+        infos.push(inspect_image(inspector.as_mut(), image, verify)?);
+    }
+    for platform in &platforms {
+        eprintln!("{} {platform}", "Platform".dim());
+        inspector.select_platform(platform);
+        infos.push(inspect_image(inspector.as_mut(), image, verify)?);
+    }
+
+    if let Some(dest) = json {
+        let analyses: Vec<Analysis> = infos
+            .into_iter()
+            .map(|info| Analysis {
+                platform: image_platform(&info).map(|p| p.to_string()),
+                name: info.name,
+                tag: info.tag,
+                efficiency: info.efficiency,
+            })
+            .collect();
+        // One platform writes one object, `--all-platforms` an array
+        let output = if all_platforms {
+            serde_json::to_string_pretty(&analyses)?
+        } else {
+            serde_json::to_string_pretty(&analyses[0])?
+        };
+        if dest == "-" {
+            println!("{output}");
+        } else {
+            fs::write(dest, &output)
+                .with_context(|| format!("Failed to write JSON to {dest}"))?;
+            eprintln!("{} Wrote {dest}", "✔".green());
+        }
+        return Ok(());
+    }
+
+    for (i, info) in infos.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        print_analysis(info, all_platforms);
+    }
+    Ok(())
+}
+
+/// Print one image's analysis, naming its platform when several are shown.
+fn print_analysis(info: &ImageInfo, show_platform: bool) {
+    let efficiency = &info.efficiency;
+    let name = match &info.tag {
+        Some(tag) => format!("{}:{tag}", info.name),
+        None => info.name.clone(),
+    };
+    let score = format!("{:.1}%", efficiency.score * 100.0);
+    let score = if efficiency.score >= 0.95 {
+        score.green()
+    } else if efficiency.score >= 0.8 {
+        score.yellow()
+    } else {
+        score.red()
+    };
+    println!("{}", style::style(name).bold());
+    if show_platform && let Some(platform) = image_platform(info) {
+        println!("  platform: {platform}");
+    }
+    println!("  efficiency: {score}");
+    println!(
+        "  wasted: {} of {} in {} layers",
+        format_bytes(efficiency.wasted_bytes),
+        format_bytes(efficiency.total_bytes),
+        info.layers.len()
+    );
+    println!("    overwritten:       {}", format_bytes(efficiency.overwritten_bytes));
+    println!("    deleted:           {}", format_bytes(efficiency.deleted_bytes));
+    println!("    same-size rewrite: {}", format_bytes(efficiency.same_size_rewrite_bytes));
+
+    if efficiency.offenders.is_empty() {
+        return;
+    }
+    println!();
+    println!("  {}", format!("{:>10}  {:>6}  {:<17}  Path", "Wasted", "Copies", "Reason").dim());
+    for offender in efficiency.offenders.iter().take(TOP_OFFENDERS) {
+        let reason = match offender.waste {
+            Waste::Overwritten => "overwritten",
+            Waste::Deleted => "deleted",
+            Waste::SameSizeRewrite => "same-size rewrite",
+        };
+        println!(
+            "  {:>10}  {:>6}  {reason:<17}  {}",
+            format_bytes(offender.wasted_bytes),
+            offender.copies,
+            offender.path.display()
+        );
+    }
+    let more = efficiency.offenders.len().saturating_sub(TOP_OFFENDERS);
+    if more > 0 {
+        println!("  {}", format!("... and {more} more (see --json)").dim());
+    }
+}
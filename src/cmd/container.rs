@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use crossterm::style::{self, Stylize};
+use serde::Serialize;
+
+use super::inspect::{format_bytes, maybe_escalate, storage_inspector};
+use crate::config;
+use crate::inspector::docker_container::{self, DockerContainer};
+use crate::inspector::{FileKind, LayerInfo};
+use crate::probe::RuntimeKind;
+
+/// A container and what it changed on top of its image.
+#[derive(Serialize)]
+struct ContainerDiff {
+    #[serde(flatten)]
+    container: DockerContainer,
+    /// The container's read-write layer
+    layer: LayerInfo,
+}
+
+/// `peel container`: list the files a container added, changed or deleted,
+/// read straight from its read-write layer (like `docker diff`, with sizes).
+pub fn run(query: &str, json: Option<&str>, runtime: Option<String>, no_sudo: bool) -> Result<()> {
+    config::init_from_cli(json.is_some(), runtime)?;
+    let cfg = config::get();
+
+    let rt = cfg
+        .probe
+        .default
+        .map(|i| &cfg.probe.runtimes[i])
+        .filter(|rt| rt.kind.matches(&RuntimeKind::Docker))
+        .or_else(|| cfg.probe.runtimes.iter().find(|rt| rt.kind.matches(&RuntimeKind::Docker)))
+        .ok_or_else(|| anyhow::anyhow!("Inspecting containers needs Docker; no Docker storage detected"))?;
+    if !rt.can_read {
+        if no_sudo {
+            anyhow::bail!("Cannot read {} without root", rt.storage_root.display());
+        }
+        maybe_escalate(rt)?;
+    }
+
+    let container = docker_container::find(&rt.storage_root, query)?;
+    let mut layer = storage_inspector(rt)?.container_layer(&container.id)?;
+    layer.files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+
+    if let Some(dest) = json {
+        let output = serde_json::to_string_pretty(&ContainerDiff { container, layer })?;
+        if dest == "-" {
+            println!("{output}");
+        } else {
+            fs::write(dest, &output)
+                .with_context(|| format!("Failed to write JSON to {dest}"))?;
+            eprintln!("{} Wrote {dest}", "✔".green());
+        }
+        return Ok(());
+    }
+
+    let state = if container.running { "running".green() } else { "exited".dim() };
+    println!(
+        "{} {} ({state})",
+        style::style(&container.name).bold(),
+        &container.id[..container.id.len().min(12)]
+    );
+    println!("  image: {}", container.image);
+    let files = layer.files.iter().filter(|f| f.kind != FileKind::Dir).count();
+    println!("  changed: {} in {files} file{}", format_bytes(layer.size), if files == 1 { "" } else { "s" });
+    println!();
+    for file in &layer.files {
+        if file.kind == FileKind::Dir {
+            continue;
+        }
+        if file.is_opaque() {
+            let dir = file.path.parent().unwrap_or(Path::new(""));
+            println!("  {:>10}  {}/ {}", "", dir.display(), "(replaced)".red());
+        } else if file.is_whiteout {
+            println!("  {:>10}  {} {}", "", file.deleted_path().display(), "(deleted)".red());
+        } else {
+            println!("  {:>10}  {}", format_bytes(file.size), file.path.display());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use crossterm::style::{self, Stylize};
+use serde::Serialize;
+
+use super::inspect::{format_bytes, maybe_escalate, storage_inspector};
+use crate::config;
+use crate::inspector::StoredImage;
+use crate::probe::{RuntimeInfo, RuntimeKind};
+
+/// Images found in one runtime's storage.
+#[derive(Serialize)]
+struct RuntimeImages {
+    runtime: RuntimeKind,
+    storage_root: PathBuf,
+    /// Why the storage couldn't be listed, if it couldn't
+    error: Option<String>,
+    images: Vec<ImageSummary>,
+}
+
+/// One row of `peel images`.
+#[derive(Serialize)]
+struct ImageSummary {
+    /// Config digest
+    id: String,
+    tags: Vec<String>,
+    layers: usize,
+    /// Total size across all layers, in bytes
+    size: u64,
+    /// Size of the layers no other image uses, in bytes
+    unique_size: u64,
+}
+
+pub fn run(json: bool, runtime: Option<String>, no_sudo: bool) -> Result<()> {
+    let only_default = runtime.is_some();
+    config::init_from_cli(json, runtime)?;
+    let cfg = config::get();
+
+    let runtimes: Vec<&RuntimeInfo> = if only_default {
+        cfg.probe.default.map(|i| &cfg.probe.runtimes[i]).into_iter().collect()
+    } else {
+        cfg.probe.runtimes.iter().collect()
+    };
+    if runtimes.is_empty() {
+        anyhow::bail!("No container runtime detected. Install Docker or Podman.");
+    }
+    if !no_sudo && let Some(rt) = runtimes.iter().find(|rt| !rt.can_read) {
+        maybe_escalate(rt)?;
+    }
+
+    let listings: Vec<RuntimeImages> = runtimes.into_iter().map(list_runtime).collect();
+
+    if cfg.json {
+        println!("{}", serde_json::to_string_pretty(&listings)?);
+        return Ok(());
+    }
+
+    for listing in &listings {
+        println!(
+            "{} {}",
+            style::style(&listing.runtime).bold(),
+            style::style(listing.storage_root.display()).dim()
+        );
+        if let Some(error) = &listing.error {
+            println!("  {} {error}", "!".yellow().bold());
+            println!();
+            continue;
+        }
+        if listing.images.is_empty() {
+            println!("  No images");
+            println!();
+            continue;
+        }
+        println!("  {:<12}  {:>6}  {:>9}  {:>9}  TAGS", "IMAGE ID", "LAYERS", "SIZE", "UNIQUE");
+        for image in &listing.images {
+            let short_id = image.id.strip_prefix("sha256:").unwrap_or(&image.id);
+            let tags = if image.tags.is_empty() {
+                "<none>".to_string()
+            } else {
+                image.tags.join(", ")
+            };
+            println!(
+                "  {:<12}  {:>6}  {:>9}  {:>9}  {tags}",
+                &short_id[..short_id.len().min(12)],
+                image.layers,
+                format_bytes(image.size),
+                format_bytes(image.unique_size)
+            );
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+/// List a runtime's images straight from its storage.
+fn list_runtime(rt: &RuntimeInfo) -> RuntimeImages {
+    let stored = if rt.can_read {
+        storage_inspector(rt).and_then(|mut inspector| inspector.list_images())
+    } else {
+        Err(anyhow::anyhow!("Cannot read {} without root", rt.storage_root.display()))
+    };
+
+    let (images, error) = match stored {
+        Ok(stored) => (summarize(stored), None),
+        Err(e) => (Vec::new(), Some(format!("{e:#}"))),
+    };
+    RuntimeImages {
+        runtime: rt.kind.clone(),
+        storage_root: rt.storage_root.clone(),
+        error,
+        images,
+    }
+}
+
+/// Total and unique sizes per image, tagged images first.
+fn summarize(stored: Vec<StoredImage>) -> Vec<ImageSummary> {
+    let mut users: HashMap<&str, usize> = HashMap::new();
+    for image in &stored {
+        for (layer_id, _) in &image.layers {
+            *users.entry(layer_id.as_str()).or_default() += 1;
+        }
+    }
+
+    let mut summaries: Vec<ImageSummary> = stored
+        .iter()
+        .map(|image| ImageSummary {
+            id: image.id.clone(),
+            tags: image.tags.clone(),
+            layers: image.layers.len(),
+            size: image.layers.iter().map(|(_, size)| size).sum(),
+            unique_size: image
+                .layers
+                .iter()
+                .filter(|(layer_id, _)| users[layer_id.as_str()] == 1)
+                .map(|(_, size)| size)
+                .sum(),
+        })
+        .collect();
+    summaries.sort_by(|a, b| (a.tags.is_empty(), &a.tags).cmp(&(b.tags.is_empty(), &b.tags)).then(a.id.cmp(&b.id)));
+    summaries
+}
@@ -6,64 +6,60 @@ use anyhow::{Context, Result};
 use crossterm::style::{self, Stylize};
 
 use crate::config;
-use crate::inspector::{self, Inspector};
-use crate::probe::{RuntimeInfo, StorageDriver};
+use crate::inspector::{self, Change, DigestCheck, FileKind, ImageInfo, Inspector, LayerInfo, Platform};
+use crate::probe::{RuntimeInfo, RuntimeKind, StorageDriver};
 use crate::progress::Spinner;
 
-pub fn run(image: &str, use_oci: bool, json: Option<&str>, runtime: Option<String>, web: bool, no_sudo: bool) -> Result<()> {
+/// Command-line options for `peel inspect`.
+pub struct InspectOptions<'a> {
+    pub use_oci: bool,
+    pub remote: bool,
+    pub json: Option<&'a str>,
+    pub runtime: Option<String>,
+    pub web: bool,
+    pub no_sudo: bool,
+    /// Platform to read from multi-platform images (default: the host's)
+    pub platform: Option<Platform>,
+    /// Inspect every platform of a multi-platform image
+    pub all_platforms: bool,
+    /// Check every layer against the digests the image records
+    pub verify: bool,
+    /// List every layer's added, modified and deleted files
+    pub changes: bool,
+}
+
+pub fn run(image: &str, opts: InspectOptions) -> Result<()> {
+    let InspectOptions { use_oci, remote, json, runtime, web, no_sudo, platform, all_platforms, verify, changes } =
+        opts;
     config::init_from_cli(json.is_some(), runtime)?;
     let cfg = config::get();
 
-    // If the image looks like a tar file, use the archive inspector directly
-    let mut inspector: Box<dyn Inspector> = if looks_like_archive(image) {
-        Box::new(inspector::docker_archive::DockerArchiveInspector::new(
-            image.into(),
-        ))
-    } else if use_oci {
-        // Use OCI/runtime API path
-        let cmd = cfg
-            .probe
-            .default
-            .map(|i| cfg.probe.runtimes[i].binary_path.display().to_string())
-            .unwrap_or_else(|| "docker".to_string());
-        Box::new(inspector::oci::OciInspector::new(cmd))
-    } else {
-        // Direct storage access — may need sudo
-        if let Some(idx) = cfg.probe.default {
-            let rt = &cfg.probe.runtimes[idx];
-            if !rt.can_read {
-                maybe_escalate(rt, no_sudo)?;
-            }
-            match rt.storage_driver {
-                #[cfg(target_os = "linux")]
-                StorageDriver::Overlay2 | StorageDriver::Fuse | StorageDriver::Vfs => {
-                    Box::new(inspector::overlay2::Overlay2Inspector::new(
-                        rt.storage_root.clone(),
-                    ))
-                }
-                _ => {
-                    // Unsupported storage driver for direct access, fall back to OCI
-                    Box::new(inspector::oci::OciInspector::new(
-                        rt.binary_path.display().to_string(),
-                    ))
-                }
-            }
-        } else {
-            anyhow::bail!("No container runtime detected. Install Docker or Podman, or use a tar archive.");
-        }
-    };
-
+    let (image, mut inspector) = open_inspector(image, use_oci, remote, no_sudo)?;
     print_runtime_summary(cfg);
 
-    let spinner = Spinner::new("Resolving image metadata...");
-    let mut info = inspector.inspect(image)?;
+    if let Some(platform) = &platform {
+        inspector.select_platform(platform);
+    }
+    if verify {
+        inspector.enable_verify();
+    }
+    if all_platforms {
+        return run_all_platforms(inspector.as_mut(), image, json, verify);
+    }
 
-    let num_layers = info.layers.len();
-    for (i, layer) in info.layers.iter_mut().enumerate() {
-        spinner.set_message(format!("Reading layer {}/{} ...", i + 1, num_layers));
-        layer.files = inspector.list_files(layer)?;
+    let info = inspect_image(inspector.as_mut(), image, verify)?;
+
+    // Local stores hold one platform per image, whatever was asked for
+    if let Some(wanted) = &platform
+        && let Some(actual) = image_platform(&info)
+        && !wanted.matches(&actual)
+    {
+        eprintln!(
+            "{} {} is {actual}, not {wanted}; this source only holds one platform",
+            "!".yellow().bold(),
+            info.name
+        );
     }
-    spinner.finish(format!("Inspected {} layers", num_layers));
 
     if web {
         let json_str = serde_json::to_string_pretty(&info)?;
@@ -111,25 +107,346 @@ pub fn run(image: &str, use_oci: bool, json: Option<&str>, runtime: Option<Strin
         }
     } else {
         println!("{}", info.name);
-        if let Some(arch) = &info.architecture {
-            println!("  arch: {arch}");
+        if let Some(platform) = image_platform(&info) {
+            println!("  platform: {platform}");
         }
         println!("  total size: {} bytes", info.total_size);
+        let files = info.filesystem.iter().filter(|f| f.kind != FileKind::Dir);
+        let (count, size) = files.fold((0, 0), |(count, size), f| (count + 1, size + f.size));
+        println!("  filesystem: {count} files, {size} bytes");
+        println!();
+        print_layers(&info, changes);
+    }
+
+    Ok(())
+}
+
+/// Pick the inspector for an image argument: a registry with `remote` (or
+/// `registry://`), an archive or OCI layout when it names one, otherwise
+/// the default runtime's storage or API. Returns the image reference with
+/// any `registry://` prefix stripped.
+pub fn open_inspector(image: &str, use_oci: bool, remote: bool, no_sudo: bool) -> Result<(&str, Box<dyn Inspector>)> {
+    let cfg = config::get();
+
+    // `registry://<ref>` is shorthand for `--remote <ref>`
+    let (image, remote) = match image.strip_prefix("registry://") {
+        Some(reference) => (reference, true),
+        None => (image, remote),
+    };
+
+    // If the image looks like a tar file or an OCI layout dir, read it directly
+    let inspector: Box<dyn Inspector> = if remote {
+        Box::new(inspector::registry::RegistryInspector::new()?)
+    } else if looks_like_archive(image) {
+        Box::new(inspector::docker_archive::DockerArchiveInspector::new(
+            image.into(),
+        ))
+    } else if let Some((dir, reference)) = inspector::oci_layout::OciLayoutInspector::parse_arg(image) {
+        Box::new(inspector::oci_layout::OciLayoutInspector::new(dir, reference))
+    } else if use_oci {
+        // Use OCI/runtime API path
+        runtime_inspector(cfg.probe.default.map(|i| &cfg.probe.runtimes[i]))?
+    } else {
+        // Direct storage access — may need sudo
+        if let Some(idx) = cfg.probe.default {
+            let rt = &cfg.probe.runtimes[idx];
+            if !rt.can_read && no_sudo {
+                eprintln!(
+                    "{} Cannot read {} without root, going through {} instead",
+                    "!".yellow().bold(),
+                    style::style(rt.storage_root.display()).dim(),
+                    rt.kind
+                );
+                runtime_inspector(Some(rt))?
+            } else {
+                if !rt.can_read {
+                    maybe_escalate(rt)?;
+                }
+                storage_inspector(rt)?
+            }
+        } else {
+            anyhow::bail!("No container runtime detected. Install Docker or Podman, or use a tar archive.");
+        }
+    };
+    Ok((image, inspector))
+}
+
+/// Print each layer with the history step that created it, and the steps
+/// that only changed the config where they ran. With `changes`, list what
+/// each layer added, modified and deleted.
+fn print_layers(info: &ImageInfo, changes: bool) {
+    let print_layer = |i: usize| {
+        let layer = &info.layers[i];
+        println!("{}", layer.digest);
+        if let Some(cmd) = &layer.created_by {
+            println!("  {cmd}");
+        }
+        let created = info.history.iter().find(|step| step.layer == Some(i)).and_then(|step| step.created.as_ref());
+        if let Some(created) = created {
+            println!("  created: {created}");
+        }
+        println!("  size: {} bytes", layer.size);
+        let totals = &layer.changes;
+        println!(
+            "  added: {} bytes, changed: {} bytes, removed: {} bytes",
+            totals.bytes_added, totals.bytes_changed, totals.bytes_removed
+        );
+        if changes {
+            print_changes(layer, i);
+        }
+        println!();
+    };
+
+    let mut next_layer = 0;
+    let mut config_steps = false;
+    for step in &info.history {
+        match step.layer {
+            Some(i) => {
+                if config_steps {
+                    println!();
+                    config_steps = false;
+                }
+                (next_layer..=i).for_each(print_layer);
+                next_layer = i + 1;
+            }
+            None => {
+                // A step that should have added a layer but had none left
+                // to pair with is flagged in `info.warnings`
+                let label = if step.empty_layer { "(no layer)" } else { "(unmatched)" };
+                let cmd = step.created_by.as_deref().unwrap_or("(unknown step)");
+                println!("{} {}", label.dim(), cmd.dim());
+                config_steps = true;
+            }
+        }
+    }
+    if config_steps {
         println!();
-        for layer in &info.layers {
-            println!("{}", layer.digest);
-            if let Some(cmd) = &layer.created_by {
-                println!("  {cmd}");
+    }
+    (next_layer..info.layers.len()).for_each(print_layer);
+}
+
+/// Dive-style A/M/D listing of the files a layer changed, directories
+/// left out.
+fn print_changes(layer: &LayerInfo, index: usize) {
+    for file in &layer.files {
+        // Listed again later in the layer, which is the entry that counts
+        if file.kind == FileKind::Dir || file.overwritten_in == Some(index) {
+            continue;
+        }
+        match file.change {
+            Some(Change::Added) => {
+                println!("    {}  {:>10}  {}", "A".green(), format_bytes(file.size), file.path.display());
+            }
+            Some(Change::Modified { size_delta }) => {
+                let sign = if size_delta < 0 { '-' } else { '+' };
+                println!(
+                    "    {}  {:>10}  {} {}",
+                    "M".yellow(),
+                    format_bytes(file.size),
+                    file.path.display(),
+                    format!("({sign}{})", format_bytes(size_delta.unsigned_abs())).dim()
+                );
             }
-            println!("  size: {} bytes", layer.size);
-            println!();
+            Some(Change::Deleted { removed_size }) => {
+                let path = if file.is_opaque() {
+                    let dir = file.path.parent().unwrap_or(Path::new(""));
+                    format!("{}/ (replaced)", dir.display())
+                } else {
+                    file.deleted_path().display().to_string()
+                };
+                println!("    {}  {:>10}  {}", "D".red(), format_bytes(removed_size), path.red());
+            }
+            None => {}
+        }
+    }
+}
+
+/// Read an image's metadata and the file listing of every layer, and with
+/// `verify`, check each layer's digests.
+pub fn inspect_image(inspector: &mut dyn Inspector, image: &str, verify: bool) -> Result<ImageInfo> {
+    let spinner = Spinner::new("Resolving image metadata...");
+    let mut info = inspector.inspect(image)?;
+
+    let num_layers = info.layers.len();
+    for (i, layer) in info.layers.iter_mut().enumerate() {
+        spinner.set_message(format!("Reading layer {}/{} ...", i + 1, num_layers));
+        layer.files = inspector.list_files(layer)?;
+        // Size every layer the same way, whatever its store reported
+        layer.size = layer.files.iter().map(|f| f.size).sum();
+    }
+    info.total_size = info.layers.iter().map(|l| l.size).sum();
+    info.filesystem = inspector::merge::merge_layers(&mut info.layers);
+    info.efficiency = inspector::efficiency::analyze(&info.layers);
+    spinner.finish(format!("Inspected {} layers", num_layers));
+    for warning in &info.warnings {
+        eprintln!("{} {warning}", "!".yellow().bold());
+    }
+
+    if verify {
+        verify_image(inspector, image, &info)?;
+    }
+    Ok(info)
+}
+
+/// `--verify`: recompute the config's and every layer's digests from what
+/// is stored and fail if any differs from what the image records.
+fn verify_image(inspector: &mut dyn Inspector, image: &str, info: &ImageInfo) -> Result<()> {
+    let spinner = Spinner::new("Verifying layers...");
+    let num_layers = info.layers.len();
+    // Checks paired with their layer's index, none for the config
+    let mut checks: Vec<(Option<usize>, DigestCheck)> = inspector
+        .verify_config(image)
+        .context("Failed to verify the image config")?
+        .into_iter()
+        .map(|check| (None, check))
+        .collect();
+    for (i, layer) in info.layers.iter().enumerate() {
+        spinner.set_message(format!("Verifying layer {}/{} ...", i + 1, num_layers));
+        let layer_checks = inspector
+            .verify_layer(layer)
+            .with_context(|| format!("Failed to verify layer {}", layer.digest))?;
+        checks.extend(layer_checks.into_iter().map(|check| (Some(i), check)));
+    }
+
+    let failed: Vec<&(Option<usize>, DigestCheck)> = checks.iter().filter(|(_, check)| !check.passed()).collect();
+    if failed.is_empty() {
+        spinner.finish(format!("Verified {} digests across {num_layers} layers", checks.len()));
+        return Ok(());
+    }
+
+    spinner.fail(format!("{} failed verification", info.name));
+    for (i, check) in &failed {
+        match i {
+            Some(i) => eprintln!("  layer {} ({}): {} mismatch", i + 1, info.layers[*i].digest, check.kind),
+            None => eprintln!("  image {}: {} mismatch", info.name, check.kind),
         }
+        eprintln!("    expected {}", check.expected);
+        eprintln!("    actual   {}", check.actual.as_str().red());
+    }
+    anyhow::bail!(
+        "{} of {} digests don't match; the stored image is corrupted or was modified",
+        failed.len(),
+        checks.len()
+    )
+}
+
+/// `--all-platforms`: inspect each platform of the image in turn and
+/// print them side by side (or as a JSON array). Single-platform sources
+/// are inspected once.
+fn run_all_platforms(inspector: &mut dyn Inspector, image: &str, json: Option<&str>, verify: bool) -> Result<()> {
+    let platforms = inspector.platforms(image)?;
+    let mut infos = Vec::with_capacity(platforms.len().max(1));
+    if platforms.is_empty() {
+        infos.push(inspect_image(inspector, image, verify)?);
+    }
+    for platform in &platforms {
+        eprintln!("{} {platform}", "Platform".dim());
+        inspector.select_platform(platform);
+        infos.push(inspect_image(inspector, image, verify)?);
     }
 
+    if let Some(dest) = json {
+        let output = serde_json::to_string_pretty(&infos)?;
+        if dest == "-" {
+            println!("{output}");
+        } else {
+            fs::write(dest, &output)
+                .with_context(|| format!("Failed to write JSON to {dest}"))?;
+            eprintln!("{} Wrote {dest}", "✔".green());
+        }
+        return Ok(());
+    }
+
+    println!("{}", infos.first().map_or(image, |i| i.name.as_str()));
+    for info in &infos {
+        let platform = image_platform(info).map_or_else(|| "unknown".to_string(), |p| p.to_string());
+        println!(
+            "  {platform:<20} {:>3} layers  {:>10}",
+            info.layers.len(),
+            format_bytes(info.total_size)
+        );
+    }
     Ok(())
 }
 
-fn print_runtime_summary(cfg: &config::AppConfig) {
+/// The platform an inspected image was built for, if its config says.
+pub fn image_platform(info: &ImageInfo) -> Option<Platform> {
+    Some(Platform {
+        os: info.os.clone().unwrap_or_else(|| "linux".to_string()),
+        architecture: info.architecture.clone()?,
+        variant: info.variant.clone(),
+    })
+}
+
+/// Pick the direct-storage inspector for a runtime's storage driver.
+pub fn storage_inspector(rt: &RuntimeInfo) -> Result<Box<dyn Inspector>> {
+    Ok(match (&rt.kind, &rt.storage_driver) {
+        #[cfg(target_os = "linux")]
+        (RuntimeKind::Podman, StorageDriver::Overlay2 | StorageDriver::Fuse) => {
+            Box::new(inspector::containers_storage::ContainersStorageInspector::new(
+                rt.storage_root.clone(),
+            ))
+        }
+        #[cfg(target_os = "linux")]
+        (RuntimeKind::Podman, StorageDriver::Vfs) => {
+            Box::new(inspector::containers_storage::ContainersStorageInspector::vfs(
+                rt.storage_root.clone(),
+            ))
+        }
+        #[cfg(target_os = "linux")]
+        (RuntimeKind::Containerd, StorageDriver::Overlay2) => {
+            Box::new(inspector::containerd::ContainerdInspector::new(
+                rt.storage_root.clone(),
+                std::env::var("CONTAINERD_NAMESPACE").ok(),
+            ))
+        }
+        #[cfg(target_os = "linux")]
+        (RuntimeKind::Docker, StorageDriver::ContainerdSnapshotter) => {
+            Box::new(inspector::containerd::ContainerdInspector::for_docker(
+                &rt.storage_root,
+            ))
+        }
+        #[cfg(target_os = "linux")]
+        (RuntimeKind::Docker, StorageDriver::Overlay2 | StorageDriver::Fuse) => {
+            Box::new(inspector::overlay2::Overlay2Inspector::new(
+                rt.storage_root.clone(),
+            ))
+        }
+        #[cfg(target_os = "linux")]
+        (RuntimeKind::Docker, StorageDriver::Vfs) => {
+            Box::new(inspector::vfs::VfsInspector::new(rt.storage_root.clone()))
+        }
+        #[cfg(target_os = "linux")]
+        (RuntimeKind::Docker, StorageDriver::Btrfs) => {
+            Box::new(inspector::btrfs::BtrfsInspector::new(rt.storage_root.clone()))
+        }
+        _ => {
+            // Unsupported storage driver for direct access, go through the runtime
+            runtime_inspector(Some(rt))?
+        }
+    })
+}
+
+/// Pick an inspector that goes through the runtime instead of its storage:
+/// the Docker Engine API when its socket is reachable, otherwise `<runtime> save`.
+fn runtime_inspector(rt: Option<&RuntimeInfo>) -> Result<Box<dyn Inspector>> {
+    let is_docker = rt.is_none_or(|rt| rt.kind.matches(&RuntimeKind::Docker));
+    if is_docker {
+        let endpoint = inspector::docker_api::Endpoint::from_env().ok();
+        if let Some(endpoint) = endpoint.filter(|e| e.is_reachable()) {
+            return Ok(Box::new(inspector::docker_api::DockerApiInspector::new(
+                endpoint,
+            )?));
+        }
+    }
+
+    let cmd = rt
+        .map(|rt| rt.binary_path.display().to_string())
+        .unwrap_or_else(|| "docker".to_string());
+    Ok(Box::new(inspector::oci::OciInspector::new(cmd)))
+}
+
+pub fn print_runtime_summary(cfg: &config::AppConfig) {
     let mut stderr = io::stderr();
 
     if cfg.probe.runtimes.is_empty() {
@@ -165,7 +482,7 @@ fn print_runtime_summary(cfg: &config::AppConfig) {
     let _ = writeln!(stderr);
 }
 
-fn format_bytes(bytes: u64) -> String {
+pub fn format_bytes(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
     let mut size = bytes as f64;
     for unit in UNITS {
@@ -181,12 +498,21 @@ fn format_bytes(bytes: u64) -> String {
     format!("{:.1} TB", size)
 }
 
+/// `-` (stdin), an existing file whose content is a (compressed) tarball,
+/// or a path with an archive extension (so a typo reports a missing file
+/// instead of an unknown image).
 fn looks_like_archive(image: &str) -> bool {
+    if image == "-" {
+        return true;
+    }
     let p = Path::new(image);
+    if p.is_file() && inspector::docker_archive::is_archive_file(p) {
+        return true;
+    }
     matches!(
         p.extension().and_then(|e| e.to_str()),
-        Some("tar" | "gz" | "tgz")
-    ) || image.ends_with(".tar.gz")
+        Some("tar" | "gz" | "tgz" | "zst" | "tzst" | "xz" | "txz")
+    )
 }
 
 /// Re-execute the current process under sudo, setting PEEL_ESCALATED to prevent loops.
@@ -201,8 +527,8 @@ fn escalate_with_sudo() -> Result<()> {
     std::process::exit(status.code().unwrap_or(1));
 }
 
-/// Auto-escalate to sudo unless --no-sudo is set.
-fn maybe_escalate(rt: &RuntimeInfo, no_sudo: bool) -> Result<()> {
+/// Auto-escalate to sudo. Callers handle --no-sudo before getting here.
+pub fn maybe_escalate(rt: &RuntimeInfo) -> Result<()> {
     let already_escalated = std::env::var("PEEL_ESCALATED").is_ok();
 
     if already_escalated {
@@ -220,7 +546,7 @@ fn maybe_escalate(rt: &RuntimeInfo, no_sudo: bool) -> Result<()> {
         stderr,
         "  {} Reading layers directly via {} — much faster,",
         "▶".green().bold(),
-        style::style("overlay2").bold()
+        style::style(&rt.storage_driver).bold()
     )?;
     writeln!(
         stderr,
@@ -234,17 +560,11 @@ fn maybe_escalate(rt: &RuntimeInfo, no_sudo: bool) -> Result<()> {
     writeln!(
         stderr,
         "  {}",
-        "Can't sudo? Use --no-sudo to fall back to the OCI API.".dim()
+        "Can't sudo? Use --no-sudo to stream the image via the runtime CLI instead.".dim()
     )?;
     writeln!(stderr, "  {}", bar.dim())?;
     writeln!(stderr)?;
 
-    if no_sudo {
-        anyhow::bail!(
-            "Cannot read storage without root. Remove --no-sudo or use --use-oci."
-        );
-    }
-
     escalate_with_sudo()?;
 
     unreachable!()
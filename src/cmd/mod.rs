@@ -1,3 +1,6 @@
+pub mod analyze;
+pub mod container;
+pub mod images;
 pub mod inspect;
 pub mod probe;
 pub mod report;
@@ -0,0 +1,111 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use oci_spec::image::{Descriptor, ImageIndex, ImageManifest, MediaType};
+
+use super::layer_blob::{self, LayerListing};
+use super::platform::{self, Platform};
+use super::Compression;
+
+/// A content-addressed blob directory (`blobs/<algorithm>/<hex>`), as found
+/// in OCI image layouts and containerd's content store.
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl BlobStore {
+    /// `root` is the directory containing `blobs/`.
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn blob_path(&self, descriptor: &Descriptor) -> PathBuf {
+        let digest = descriptor.digest();
+        self.root
+            .join("blobs")
+            .join(digest.algorithm().as_ref())
+            .join(digest.digest())
+    }
+
+    pub fn has_blob(&self, descriptor: &Descriptor) -> bool {
+        self.blob_path(descriptor).is_file()
+    }
+
+    pub fn read_blob(&self, descriptor: &Descriptor) -> Result<Vec<u8>> {
+        let path = self.blob_path(descriptor);
+        fs::read(&path).with_context(|| format!("Failed to read blob {}", path.display()))
+    }
+
+    /// Follow a manifest or index descriptor (through any nested indexes)
+    /// down to the image manifest for `platform`.
+    pub fn resolve_manifest(&self, descriptor: &Descriptor, platform: &Platform) -> Result<ImageManifest> {
+        let data = self.read_blob(descriptor)?;
+        if !is_index(descriptor.media_type()) {
+            return ImageManifest::from_reader(data.as_slice())
+                .with_context(|| format!("Failed to parse manifest {}", descriptor.digest()));
+        }
+
+        let index = ImageIndex::from_reader(data.as_slice())
+            .with_context(|| format!("Failed to parse index {}", descriptor.digest()))?;
+        let candidates: Vec<&Descriptor> = index.manifests().iter().collect();
+        let child = self
+            .select(&candidates, platform)
+            .with_context(|| format!("No usable image manifest in index {}", descriptor.digest()))?;
+        self.resolve_manifest(child, platform)
+    }
+
+    /// Pick the entry for `platform` among descriptors present in the store.
+    /// Platforms that were never pulled have no blobs and are skipped.
+    pub fn select<'a>(&self, descriptors: &[&'a Descriptor], platform: &Platform) -> Result<&'a Descriptor> {
+        platform::select(descriptors, platform, |d| self.has_blob(d))
+    }
+
+    /// Every platform reachable from `descriptors` with its blobs present,
+    /// looking inside nested indexes.
+    pub fn platforms(&self, descriptors: &[&Descriptor]) -> Result<Vec<Platform>> {
+        let mut platforms = platform::list(descriptors, |d| self.has_blob(d));
+        for descriptor in descriptors {
+            if !is_index(descriptor.media_type()) || !self.has_blob(descriptor) {
+                continue;
+            }
+            let data = self.read_blob(descriptor)?;
+            let index = ImageIndex::from_reader(data.as_slice())
+                .with_context(|| format!("Failed to parse index {}", descriptor.digest()))?;
+            let children: Vec<&Descriptor> = index.manifests().iter().collect();
+            for platform in self.platforms(&children)? {
+                if !platforms.contains(&platform) {
+                    platforms.push(platform);
+                }
+            }
+        }
+        Ok(platforms)
+    }
+
+    /// Detect a blob's compression from its first bytes, if it's present.
+    pub fn sniff(&self, descriptor: &Descriptor) -> Option<Compression> {
+        let mut head = Vec::with_capacity(512);
+        File::open(self.blob_path(descriptor))
+            .ok()?
+            .take(512)
+            .read_to_end(&mut head)
+            .ok()?;
+        layer_blob::sniff(&head)
+    }
+
+    /// List the files in a layer blob, detecting its compression, and
+    /// hash it if asked.
+    pub fn list_layer(&self, descriptor: &Descriptor, hash: bool) -> Result<LayerListing> {
+        let path = self.blob_path(descriptor);
+        let file = File::open(&path)
+            .with_context(|| format!("Failed to open layer {}", path.display()))?;
+        layer_blob::list(BufReader::new(file), hash)
+            .with_context(|| format!("Failed to list layer {}", descriptor.digest()))
+    }
+}
+
+pub fn is_index(media_type: &MediaType) -> bool {
+    matches!(media_type, MediaType::ImageIndex)
+        || media_type.to_string() == "application/vnd.docker.distribution.manifest.list.v2+json"
+}
@@ -0,0 +1,370 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Magic number at the start of every bbolt meta page.
+const MAGIC: u32 = 0xED0C_DAED;
+
+/// Page size assumed when the first meta page, which records the real
+/// one, is damaged.
+const DEFAULT_PAGE_SIZE: usize = 4096;
+
+/// How deep a tree of branch pages may go. Real trees are a few levels
+/// deep; a long enough chain of corrupted branch pages would overflow the
+/// stack.
+const MAX_DEPTH: usize = 32;
+
+/// Bytes of the meta page the checksum covers, from the magic to the txid.
+const META_CHECKSUM_OFFSET: usize = 56;
+
+const PAGE_HEADER_SIZE: usize = 16;
+const ELEMENT_SIZE: usize = 16;
+const BUCKET_HEADER_SIZE: usize = 16;
+
+const BRANCH_PAGE: u16 = 0x01;
+const LEAF_PAGE: u16 = 0x02;
+const BUCKET_LEAF_FLAG: u32 = 0x01;
+
+/// A minimal read-only reader for bbolt databases, the key/value store
+/// containerd keeps its metadata in (`meta.db`, snapshotter `metadata.db`).
+///
+/// The whole file is loaded into memory and walked directly; only the
+/// structures needed to look up nested buckets and keys are understood.
+pub struct BoltDb {
+    data: Vec<u8>,
+    page_size: usize,
+    root_pgid: u64,
+    /// First page ID past the end of the data, from the meta page
+    high_water: u64,
+}
+
+/// The fields of a meta page that checked out.
+struct Meta {
+    page_size: usize,
+    root_pgid: u64,
+    high_water: u64,
+    txid: u64,
+}
+
+/// A bucket: either a tree rooted at a page, or an inline bucket whose
+/// single leaf page is stored inside its parent's value.
+#[derive(Clone, Copy)]
+pub struct Bucket<'a> {
+    db: &'a BoltDb,
+    root: BucketRoot<'a>,
+}
+
+#[derive(Clone, Copy)]
+enum BucketRoot<'a> {
+    Page(u64),
+    Inline(&'a [u8]),
+}
+
+/// The value stored under a key.
+pub enum Value<'a> {
+    Data(&'a [u8]),
+    Bucket(Bucket<'a>),
+}
+
+impl BoltDb {
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+        // bbolt writes the two meta pages in turn, so one may be torn by a
+        // crash mid-commit: use the newest that checks out
+        let first = read_meta(&data, 0);
+        let page_size = first.as_ref().map_or(DEFAULT_PAGE_SIZE, |meta| meta.page_size);
+        let second = read_meta(&data, page_size);
+        let meta = [first, second]
+            .into_iter()
+            .flatten()
+            .max_by_key(|meta| meta.txid)
+            .with_context(|| format!("{} is not a bolt database or has no valid meta page", path.display()))?;
+
+        Ok(Self {
+            data,
+            page_size: meta.page_size,
+            root_pgid: meta.root_pgid,
+            high_water: meta.high_water,
+        })
+    }
+
+    /// The top-level bucket.
+    pub fn root(&self) -> Bucket<'_> {
+        Bucket {
+            db: self,
+            root: BucketRoot::Page(self.root_pgid),
+        }
+    }
+
+    fn page(&self, pgid: u64) -> Option<&[u8]> {
+        // The meta pages are never part of a tree
+        if pgid < 2 || pgid >= self.high_water {
+            return None;
+        }
+        let start = usize::try_from(pgid).ok()?.checked_mul(self.page_size)?;
+        let overflow = read_u32(&self.data, start + 12)? as usize;
+        let end = (overflow + 1).checked_mul(self.page_size)?.checked_add(start)?;
+        self.data.get(start..end.min(self.data.len()))
+    }
+}
+
+impl<'a> Bucket<'a> {
+    /// Follow a path of nested bucket names.
+    pub fn path(&self, names: &[&[u8]]) -> Option<Bucket<'a>> {
+        names.iter().try_fold(*self, |bucket, name| bucket.bucket(name))
+    }
+
+    pub fn bucket(&self, name: &[u8]) -> Option<Bucket<'a>> {
+        match self.get_value(name)? {
+            Value::Bucket(bucket) => Some(bucket),
+            Value::Data(_) => None,
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&'a [u8]> {
+        match self.get_value(key)? {
+            Value::Data(data) => Some(data),
+            Value::Bucket(_) => None,
+        }
+    }
+
+    fn get_value(&self, key: &[u8]) -> Option<Value<'a>> {
+        self.entries()
+            .into_iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// All key/value pairs in key order.
+    pub fn entries(&self) -> Vec<(&'a [u8], Value<'a>)> {
+        let mut out = Vec::new();
+        match self.root {
+            BucketRoot::Page(pgid) => self.collect_page(pgid, 0, &mut HashSet::new(), &mut out),
+            BucketRoot::Inline(page) => self.collect_leaf(page, &mut out),
+        }
+        out
+    }
+
+    /// Names of the nested buckets directly under this one.
+    pub fn bucket_names(&self) -> Vec<&'a [u8]> {
+        self.entries()
+            .into_iter()
+            .filter(|(_, v)| matches!(v, Value::Bucket(_)))
+            .map(|(k, _)| k)
+            .collect()
+    }
+
+    /// Collect the entries under page `pgid`, skipping pages already
+    /// `seen`: in an intact tree every page has a single parent.
+    fn collect_page(&self, pgid: u64, depth: usize, seen: &mut HashSet<u64>, out: &mut Vec<(&'a [u8], Value<'a>)>) {
+        let db: &'a BoltDb = self.db;
+        if depth > MAX_DEPTH || !seen.insert(pgid) {
+            return;
+        }
+        let Some(page) = db.page(pgid) else { return };
+        let flags = read_u16(page, 8).unwrap_or(0);
+        if flags & BRANCH_PAGE != 0 {
+            let count = read_u16(page, 10).unwrap_or(0) as usize;
+            for i in 0..count {
+                let elem = PAGE_HEADER_SIZE + i * ELEMENT_SIZE;
+                if let Some(child) = read_u64(page, elem + 8) {
+                    self.collect_page(child, depth + 1, seen, out);
+                }
+            }
+        } else if flags & LEAF_PAGE != 0 {
+            self.collect_leaf(page, out);
+        }
+    }
+
+    fn collect_leaf(&self, page: &'a [u8], out: &mut Vec<(&'a [u8], Value<'a>)>) {
+        let count = read_u16(page, 10).unwrap_or(0) as usize;
+        for i in 0..count {
+            let elem = PAGE_HEADER_SIZE + i * ELEMENT_SIZE;
+            let (Some(flags), Some(pos), Some(ksize), Some(vsize)) = (
+                read_u32(page, elem),
+                read_u32(page, elem + 4),
+                read_u32(page, elem + 8),
+                read_u32(page, elem + 12),
+            ) else {
+                return;
+            };
+            let key_start = elem + pos as usize;
+            let value_start = key_start + ksize as usize;
+            let (Some(key), Some(value)) = (
+                page.get(key_start..value_start),
+                page.get(value_start..value_start + vsize as usize),
+            ) else {
+                return;
+            };
+
+            let value = if flags & BUCKET_LEAF_FLAG != 0 {
+                let root = match read_u64(value, 0) {
+                    Some(0) => BucketRoot::Inline(&value[BUCKET_HEADER_SIZE.min(value.len())..]),
+                    Some(pgid) => BucketRoot::Page(pgid),
+                    None => continue,
+                };
+                Value::Bucket(Bucket { db: self.db, root })
+            } else {
+                Value::Data(value)
+            };
+            out.push((key, value));
+        }
+    }
+}
+
+/// Read the meta page at `offset`, if its magic and checksum are intact.
+fn read_meta(data: &[u8], offset: usize) -> Option<Meta> {
+    let meta = data.get(offset + PAGE_HEADER_SIZE..)?;
+    if read_u32(meta, 0)? != MAGIC {
+        return None;
+    }
+    let checksum = read_u64(meta, META_CHECKSUM_OFFSET)?;
+    if fnv64a(&meta[..META_CHECKSUM_OFFSET]) != checksum {
+        return None;
+    }
+    Some(Meta {
+        page_size: read_u32(meta, 8).filter(|&size| size >= 512)? as usize,
+        root_pgid: read_u64(meta, 16)?,
+        high_water: read_u64(meta, 40)?,
+        txid: read_u64(meta, 48)?,
+    })
+}
+
+/// FNV-1a, 64-bit: what bbolt checksums its meta pages with.
+fn fnv64a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+/// Decode a Go `binary.Uvarint`.
+pub fn uvarint(data: &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (i, &byte) in data.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Decode a Go `binary.Varint` (zig-zag encoded).
+pub fn varint(data: &[u8]) -> Option<i64> {
+    let ux = uvarint(data)?;
+    let x = (ux >> 1) as i64;
+    Some(if ux & 1 != 0 { !x } else { x })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: usize = 512;
+
+    fn page_header(pgid: u64, flags: u16, count: u16) -> Vec<u8> {
+        let mut page = pgid.to_le_bytes().to_vec();
+        page.extend(flags.to_le_bytes());
+        page.extend(count.to_le_bytes());
+        page.extend(0u32.to_le_bytes());
+        page
+    }
+
+    /// A meta page pointing at `root`, with `high_water` pages in use.
+    fn meta(pgid: u64, root: u64, high_water: u64, txid: u64) -> Vec<u8> {
+        let mut page = page_header(pgid, 0x04, 0);
+        let mut fields = Vec::new();
+        for value in [MAGIC, 2, PAGE as u32, 0] {
+            fields.extend(value.to_le_bytes());
+        }
+        for value in [root, 0, 0, high_water, txid] {
+            fields.extend(value.to_le_bytes());
+        }
+        let checksum = fnv64a(&fields);
+        page.extend(fields);
+        page.extend(checksum.to_le_bytes());
+        page
+    }
+
+    /// A leaf page holding one key/value pair.
+    fn leaf(pgid: u64, key: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut page = page_header(pgid, LEAF_PAGE, 1);
+        for field in [0, ELEMENT_SIZE as u32, key.len() as u32, value.len() as u32] {
+            page.extend(field.to_le_bytes());
+        }
+        page.extend(key);
+        page.extend(value);
+        page
+    }
+
+    /// A branch page whose elements point at `children`.
+    fn branch(pgid: u64, children: &[u64]) -> Vec<u8> {
+        let mut page = page_header(pgid, BRANCH_PAGE, children.len() as u16);
+        for &child in children {
+            page.extend(0u32.to_le_bytes());
+            page.extend(0u32.to_le_bytes());
+            page.extend(child.to_le_bytes());
+        }
+        page
+    }
+
+    fn open(pages: Vec<Vec<u8>>) -> BoltDb {
+        let data: Vec<u8> = pages
+            .into_iter()
+            .flat_map(|mut page| {
+                page.resize(PAGE, 0);
+                page
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("peel-boltdb-{}-{:x}", std::process::id(), fnv64a(&data)));
+        fs::write(&path, data).unwrap();
+        let db = BoltDb::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        db
+    }
+
+    #[test]
+    fn newest_intact_meta_page_wins() {
+        let pages = |second_meta: Vec<u8>| {
+            vec![meta(0, 2, 4, 1), second_meta, leaf(2, b"k", b"old"), leaf(3, b"k", b"new")]
+        };
+        let db = open(pages(meta(1, 3, 4, 2)));
+        assert_eq!(db.root().get(b"k"), Some(&b"new"[..]));
+
+        // A commit torn halfway through its meta page
+        let mut torn = meta(1, 3, 4, 2);
+        torn[PAGE_HEADER_SIZE + 16] ^= 0xff;
+        let db = open(pages(torn));
+        assert_eq!(db.root().get(b"k"), Some(&b"old"[..]));
+    }
+
+    #[test]
+    fn corrupted_page_references_are_skipped() {
+        // The root branch points at itself, at its leaf twice, past the
+        // high water mark and at a meta page
+        let db = open(vec![
+            meta(0, 2, 4, 1),
+            meta(1, 2, 4, 1),
+            branch(2, &[2, 3, 3, 9, 0]),
+            leaf(3, b"k", b"v"),
+        ]);
+        let entries = db.root().entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, b"k");
+    }
+}
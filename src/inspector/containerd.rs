@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use oci_spec::image::{Descriptor, DescriptorBuilder, MediaType};
+
+use super::blob_store::BlobStore;
+use super::boltdb::{self, BoltDb, Bucket};
+use super::docker_archive::ArchiveLayers;
+use super::image_config::ImageConfig;
+use super::reference::{did_you_mean, id_prefix, Reference};
+use super::{
+    layer_dir, split_repo_tag, DigestCheck, Efficiency, FileEntry, ImageInfo, Inspector, LayerChanges, LayerInfo,
+    Platform, StoredImage,
+};
+
+/// Metadata database, relative to the containerd root.
+const META_DB: &str = "io.containerd.metadata.v1.bolt/meta.db";
+
+/// Content store, relative to the containerd root.
+const CONTENT_DIR: &str = "io.containerd.content.v1.content";
+
+/// Overlayfs snapshotter state, relative to the containerd root.
+const OVERLAYFS_DIR: &str = "io.containerd.snapshotter.v1.overlayfs";
+
+/// Root of a system-wide containerd installation.
+const SYSTEM_ROOT: &str = "/var/lib/containerd";
+
+/// Namespace Docker stores its images in when using containerd.
+const DOCKER_NAMESPACE: &str = "moby";
+
+/// Namespaces searched first, in order. Any others follow alphabetically.
+const PREFERRED_NAMESPACES: &[&str] = &["default", "k8s.io", "moby"];
+
+/// Reads layers directly from containerd's content store and overlayfs
+/// snapshots (`/var/lib/containerd`). Images are resolved through the bolt
+/// metadata database, across namespaces (`default`, `k8s.io`, `moby`, ...).
+pub struct ContainerdInspector {
+    root: PathBuf,
+    /// Only search this namespace (e.g. from `CONTAINERD_NAMESPACE`)
+    namespace: Option<String>,
+    /// Platform to pick from multi-platform images
+    platform: Platform,
+    blobs: BlobStore,
+    /// Whether to hash layer blobs during `inspect`
+    verify: bool,
+    /// Unpacked overlayfs snapshot directories, keyed by diff ID
+    snapshot_dirs: HashMap<String, PathBuf>,
+    /// Listings of layer blobs, for images that were pulled but never
+    /// unpacked, and digest checks of the blobs when verifying
+    layers: ArchiveLayers,
+}
+
+/// A committed overlayfs snapshot.
+struct Snapshot {
+    dir: PathBuf,
+    /// Disk usage recorded at commit time, if any
+    size: Option<u64>,
+}
+
+/// An image record resolved from the metadata database.
+struct ImageRecord {
+    namespace: String,
+    name: String,
+    target: Descriptor,
+}
+
+impl ContainerdInspector {
+    pub fn new(root: PathBuf, namespace: Option<String>) -> Self {
+        Self {
+            blobs: BlobStore::new(root.join(CONTENT_DIR)),
+            root,
+            namespace,
+            platform: Platform::host(),
+            verify: false,
+            snapshot_dirs: HashMap::new(),
+            layers: ArchiveLayers::default(),
+        }
+    }
+
+    /// Inspector for Docker's containerd image store. Docker either runs an
+    /// embedded containerd under `<docker root>/containerd/daemon` or uses
+    /// the system one; either way its images live in the `moby` namespace.
+    pub fn for_docker(docker_root: &Path) -> Self {
+        let embedded = docker_root.join("containerd/daemon");
+        let root = if embedded.join(META_DB).is_file() {
+            embedded
+        } else {
+            PathBuf::from(SYSTEM_ROOT)
+        };
+        Self::new(root, Some(DOCKER_NAMESPACE.to_string()))
+    }
+
+    /// Namespaces to search, in preference order.
+    fn namespaces(&self, v1: &Bucket) -> Vec<String> {
+        if let Some(ns) = &self.namespace {
+            return vec![ns.clone()];
+        }
+        let mut all: Vec<String> = v1
+            .bucket_names()
+            .into_iter()
+            .map(|n| String::from_utf8_lossy(n).into_owned())
+            .collect();
+        all.sort_by_key(|ns| {
+            PREFERRED_NAMESPACES
+                .iter()
+                .position(|p| p == ns)
+                .unwrap_or(PREFERRED_NAMESPACES.len())
+        });
+        all
+    }
+
+    /// Find an image by reference, or by a prefix of its target digest
+    /// (the ID `nerdctl images` shows).
+    fn resolve_image(&self, meta: &BoltDb, image: &str) -> Result<ImageRecord> {
+        let v1 = meta
+            .root()
+            .bucket(b"v1")
+            .context("containerd metadata has no v1 bucket")?;
+        let namespaces = self.namespaces(&v1);
+        let reference = Reference::parse(image);
+        let candidates = reference.as_ref().map(Reference::candidate_names).unwrap_or_default();
+
+        for candidate in &candidates {
+            for ns in &namespaces {
+                let Some(record) = v1.path(&[ns.as_bytes(), b"images", candidate.as_bytes()]) else {
+                    continue;
+                };
+                return read_record(ns, candidate, &record);
+            }
+        }
+
+        if let Some(prefix) = id_prefix(image) {
+            let mut matches = Vec::new();
+            for ns in &namespaces {
+                let Some(images) = v1.path(&[ns.as_bytes(), b"images"]) else {
+                    continue;
+                };
+                for name in images.bucket_names() {
+                    let Some(record) = images.bucket(name) else {
+                        continue;
+                    };
+                    let record = read_record(ns, &String::from_utf8_lossy(name), &record)?;
+                    // Several names (tag, digest, other namespace) may share a target
+                    let target = record.target.digest().clone();
+                    if target.digest().starts_with(prefix)
+                        && !matches.iter().any(|m: &ImageRecord| *m.target.digest() == target)
+                    {
+                        matches.push(record);
+                    }
+                }
+            }
+            match matches.len() {
+                0 => {}
+                1 => return Ok(matches.remove(0)),
+                n => anyhow::bail!("Image ID '{image}' is ambiguous ({n} images match)"),
+            }
+        }
+
+        reference?;
+        let mut known = Vec::new();
+        for ns in &namespaces {
+            if let Some(images) = v1.path(&[ns.as_bytes(), b"images"]) {
+                known.extend(images.bucket_names().into_iter().map(|n| String::from_utf8_lossy(n).into_owned()));
+            }
+        }
+        anyhow::bail!(
+            "Image '{image}' not found in containerd namespaces {} (tried {}){}",
+            namespaces.join(", "),
+            candidates.join(", "),
+            did_you_mean(image, known.iter().map(String::as_str))
+        )
+    }
+
+    /// Every name in the record's namespace that points at the same target,
+    /// as (tags, repo digests). A tag's repository is also reachable by the
+    /// target digest itself.
+    fn references(&self, meta: &BoltDb, record: &ImageRecord) -> Result<(Vec<String>, Vec<String>)> {
+        let mut tags = Vec::new();
+        let mut digests = Vec::new();
+        if let Some(images) = meta.root().path(&[b"v1", record.namespace.as_bytes(), b"images"]) {
+            for name in images.bucket_names() {
+                let Some(bucket) = images.bucket(name) else {
+                    continue;
+                };
+                let other = read_record(&record.namespace, &String::from_utf8_lossy(name), &bucket)?;
+                if other.target.digest() != record.target.digest() {
+                    continue;
+                }
+                if other.name.contains('@') {
+                    digests.push(other.name);
+                } else {
+                    let (repository, _) = split_repo_tag(&other.name);
+                    digests.push(format!("{repository}@{}", record.target.digest()));
+                    tags.push(other.name);
+                }
+            }
+        }
+        tags.sort();
+        digests.sort();
+        digests.dedup();
+        Ok((tags, digests))
+    }
+
+    /// Map a committed snapshot key (the layer's chain ID) to its directory,
+    /// through the namespace's snapshot record and the overlayfs metadata.
+    fn find_snapshot(
+        &self,
+        meta: &BoltDb,
+        snapshots: Option<&BoltDb>,
+        namespace: &str,
+        chain_id: &str,
+    ) -> Option<Snapshot> {
+        let record = meta.root().path(&[
+            b"v1",
+            namespace.as_bytes(),
+            b"snapshots",
+            b"overlayfs",
+            chain_id.as_bytes(),
+        ])?;
+        let backend_key = record.get(b"name")?;
+        let snapshot = snapshots?
+            .root()
+            .path(&[b"v1", b"snapshots", backend_key])?;
+        let id = boltdb::uvarint(snapshot.get(b"id")?)?;
+        let size = snapshot
+            .get(b"size")
+            .and_then(boltdb::varint)
+            .and_then(|s| u64::try_from(s).ok());
+
+        let dir = self
+            .root
+            .join(OVERLAYFS_DIR)
+            .join("snapshots")
+            .join(id.to_string())
+            .join("fs");
+        dir.is_dir().then_some(Snapshot { dir, size })
+    }
+}
+
+impl Inspector for ContainerdInspector {
+    fn inspect(&mut self, image: &str) -> Result<ImageInfo> {
+        let meta = BoltDb::open(&self.root.join(META_DB))?;
+        let snapshots = BoltDb::open(&self.root.join(OVERLAYFS_DIR).join("metadata.db")).ok();
+
+        let record = self.resolve_image(&meta, image)?;
+        let manifest = self.blobs.resolve_manifest(&record.target, &self.platform)?;
+        let config_data = self.blobs.read_blob(manifest.config())?;
+        let config = ImageConfig::parse(&config_data)
+            .with_context(|| format!("Failed to parse image config {}", manifest.config().digest()))?;
+        self.snapshot_dirs.clear();
+        self.layers = ArchiveLayers::default();
+        self.layers.config = Some(DigestCheck::new("config", manifest.config().digest().to_string(), &config.id));
+
+        let chain_ids = config.chain_ids();
+        let history = config.history();
+        let mut layers = Vec::with_capacity(manifest.layers().len());
+        let mut total_size = 0u64;
+
+        for (i, layer_desc) in manifest.layers().iter().enumerate() {
+            let digest = config
+                .rootfs
+                .diff_ids
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| layer_desc.digest().to_string());
+
+            // Snapshots can't be hashed, but the content store keeps the
+            // blob they were unpacked from
+            let listing = if self.verify {
+                if !self.blobs.has_blob(layer_desc) {
+                    anyhow::bail!("Layer blob {} is not in the content store, can't verify it", layer_desc.digest());
+                }
+                let listing = self.blobs.list_layer(layer_desc, true)?;
+                if let Some(digests) = &listing.digests {
+                    let expected_diff_id = config.rootfs.diff_ids.get(i).map(String::as_str);
+                    let checks = digests.checks(Some(layer_desc.digest().as_ref()), expected_diff_id);
+                    self.layers.checks.insert(digest.clone(), checks);
+                }
+                Some(listing)
+            } else {
+                None
+            };
+
+            let snapshot = chain_ids
+                .get(i)
+                .and_then(|id| self.find_snapshot(&meta, snapshots.as_ref(), &record.namespace, id));
+            let (size, compression) = match (snapshot, listing) {
+                (Some(Snapshot { dir, size }), _) => {
+                    let size = match size {
+                        Some(size) => size,
+                        None => layer_dir::list_entries(&dir)?.iter().map(|f| f.size).sum(),
+                    };
+                    self.snapshot_dirs.insert(digest.clone(), dir);
+                    (size, None)
+                }
+                (None, listing) => {
+                    let listing = match listing {
+                        Some(listing) => listing,
+                        None => self.blobs.list_layer(layer_desc, false)?,
+                    };
+                    let size = listing.files.iter().map(|f| f.size).sum();
+                    self.layers.files.insert(digest.clone(), listing.files);
+                    (size, Some(listing.compression))
+                }
+            };
+
+            // The content store keeps the compressed blob even once unpacked
+            total_size += size;
+            layers.push(LayerInfo {
+                digest: digest.clone(),
+                created_by: history.created_by(i),
+                size,
+                changes: LayerChanges::default(),
+                compression: compression.or_else(|| self.blobs.sniff(layer_desc)),
+                compressed_size: Some(layer_desc.size()),
+                files: Vec::new(),
+            });
+        }
+
+        let (repo_tags, repo_digests) = self.references(&meta, &record)?;
+        let (name, tag) = split_repo_tag(&record.name);
+        Ok(ImageInfo {
+            name,
+            tag,
+            id: config.id,
+            repo_tags,
+            repo_digests,
+            created: config.created,
+            config: config.config,
+            os: config.os,
+            architecture: config.architecture,
+            variant: config.variant,
+            total_size,
+            layers,
+            filesystem: Vec::new(),
+            efficiency: Efficiency::default(),
+            history: history.steps,
+            warnings: history.warnings,
+        })
+    }
+
+    fn list_files(&mut self, layer: &LayerInfo) -> Result<Vec<FileEntry>> {
+        if let Some(dir) = self.snapshot_dirs.get(&layer.digest) {
+            return layer_dir::list_entries(dir);
+        }
+        self.layers
+            .files
+            .get(&layer.digest)
+            .cloned()
+            .with_context(|| format!("Layer {} not found in containerd", layer.digest))
+    }
+
+    fn enable_verify(&mut self) {
+        self.verify = true;
+    }
+
+    fn verify_layer(&mut self, layer: &LayerInfo) -> Result<Vec<DigestCheck>> {
+        self.layers.checks(layer)
+    }
+
+    fn verify_config(&mut self, _image: &str) -> Result<Vec<DigestCheck>> {
+        Ok(self.layers.config_checks())
+    }
+
+    fn list_images(&mut self) -> Result<Vec<StoredImage>> {
+        let meta = BoltDb::open(&self.root.join(META_DB))?;
+        let snapshots = BoltDb::open(&self.root.join(OVERLAYFS_DIR).join("metadata.db")).ok();
+        let v1 = meta
+            .root()
+            .bucket(b"v1")
+            .context("containerd metadata has no v1 bucket")?;
+
+        let mut images: Vec<StoredImage> = Vec::new();
+        // Sizes by chain ID, for layers several images share
+        let mut sizes: HashMap<String, u64> = HashMap::new();
+        for ns in self.namespaces(&v1) {
+            let Some(records) = v1.path(&[ns.as_bytes(), b"images"]) else {
+                continue;
+            };
+            for name in records.bucket_names() {
+                let Some(record) = records.bucket(name) else {
+                    continue;
+                };
+                let record = read_record(&ns, &String::from_utf8_lossy(name), &record)?;
+                // Skip images whose platform for this machine was never pulled
+                let Ok(manifest) = self.blobs.resolve_manifest(&record.target, &self.platform) else {
+                    continue;
+                };
+                let id = manifest.config().digest().to_string();
+                let tag = Some(record.name).filter(|n| !n.contains('@'));
+
+                if let Some(image) = images.iter_mut().find(|i| i.id == id) {
+                    image.tags.extend(tag);
+                    continue;
+                }
+
+                let config_data = self.blobs.read_blob(manifest.config())?;
+                let config = ImageConfig::parse(&config_data)
+                    .with_context(|| format!("Failed to parse image config {id}"))?;
+                let mut layers = Vec::with_capacity(manifest.layers().len());
+                for (chain_id, layer_desc) in config.chain_ids().into_iter().zip(manifest.layers()) {
+                    // Sized by its files, like `inspect` does, rather than
+                    // by the snapshot's disk usage or the compressed blob
+                    let size = match sizes.get(&chain_id) {
+                        Some(&size) => size,
+                        None => {
+                            let files = match self.find_snapshot(&meta, snapshots.as_ref(), &ns, &chain_id) {
+                                Some(snapshot) => layer_dir::list_entries(&snapshot.dir)?,
+                                None => self.blobs.list_layer(layer_desc, false)?.files,
+                            };
+                            let size = files.iter().map(|f| f.size).sum();
+                            sizes.insert(chain_id.clone(), size);
+                            size
+                        }
+                    };
+                    layers.push((chain_id, size));
+                }
+                images.push(StoredImage {
+                    id,
+                    tags: tag.into_iter().collect(),
+                    layers,
+                });
+            }
+        }
+        Ok(images)
+    }
+
+    fn select_platform(&mut self, platform: &Platform) {
+        self.platform = platform.clone();
+    }
+
+    fn platforms(&mut self, image: &str) -> Result<Vec<Platform>> {
+        let meta = BoltDb::open(&self.root.join(META_DB))?;
+        let record = self.resolve_image(&meta, image)?;
+        self.blobs.platforms(&[&record.target])
+    }
+}
+
+/// Read an image record: its name and the descriptor in its `target` bucket.
+fn read_record(namespace: &str, name: &str, record: &Bucket) -> Result<ImageRecord> {
+    let target = record
+        .bucket(b"target")
+        .with_context(|| format!("Image {name} has no target"))?;
+    Ok(ImageRecord {
+        namespace: namespace.to_string(),
+        name: name.to_string(),
+        target: read_target(&target)?,
+    })
+}
+
+/// Read an image's `target` bucket (digest, mediatype, size) as a descriptor.
+fn read_target(target: &Bucket) -> Result<Descriptor> {
+    let digest = target.get(b"digest").context("Image target has no digest")?;
+    let media_type = target.get(b"mediatype").unwrap_or_default();
+    let size = target
+        .get(b"size")
+        .and_then(boltdb::varint)
+        .unwrap_or(0)
+        .max(0) as u64;
+
+    let descriptor = DescriptorBuilder::default()
+        .media_type(MediaType::from(String::from_utf8_lossy(media_type).as_ref()))
+        .digest(
+            String::from_utf8_lossy(digest)
+                .parse::<oci_spec::image::Digest>()
+                .context("Invalid image target digest")?,
+        )
+        .size(size)
+        .build()?;
+    Ok(descriptor)
+}
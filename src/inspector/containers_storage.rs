@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Deserialize;
+
+use super::image_config::ImageConfig;
+use super::reference::{did_you_mean, id_prefix, Reference};
+use super::{
+    layer_dir, snapshot_diff, split_repo_tag, tar_split, DigestCheck, Efficiency, FileEntry, ImageInfo, Inspector, LayerChanges,
+    LayerInfo, StoredImage,
+};
+
+/// Reads layers directly from containers/storage (Podman, Buildah, CRI-O)
+/// with the overlay or vfs driver. Works for rootless storage under
+/// `~/.local/share/containers/storage` as well as `/var/lib/containers/storage`.
+pub struct ContainersStorageInspector {
+    storage_root: PathBuf,
+    /// Storage driver name, which prefixes its metadata directories
+    /// (e.g. "overlay" for `overlay-layers`)
+    driver: &'static str,
+    /// Layer records keyed by diff digest, filled by each `inspect`
+    layers_by_digest: HashMap<String, StorageLayer>,
+    /// The image config's diff ID for each layer, keyed the same way
+    config_diff_ids: HashMap<String, String>,
+}
+
+/// An entry of `<driver>-images/images.json`.
+#[derive(Deserialize)]
+struct StorageImage {
+    id: String,
+    #[serde(default)]
+    digest: Option<String>,
+    /// Every manifest digest the image was pulled or pushed as
+    #[serde(default)]
+    digests: Vec<String>,
+    #[serde(default)]
+    names: Vec<String>,
+    /// ID of the topmost layer
+    #[serde(default)]
+    layer: Option<String>,
+}
+
+/// An entry of `<driver>-layers/layers.json`.
+#[derive(Deserialize, Clone)]
+struct StorageLayer {
+    id: String,
+    #[serde(default)]
+    parent: Option<String>,
+    #[serde(rename = "diff-digest", default)]
+    diff_digest: Option<String>,
+    #[serde(rename = "diff-size", default)]
+    diff_size: Option<u64>,
+}
+
+impl ContainersStorageInspector {
+    pub fn new(storage_root: PathBuf) -> Self {
+        Self {
+            storage_root,
+            driver: "overlay",
+            layers_by_digest: HashMap::new(),
+            config_diff_ids: HashMap::new(),
+        }
+    }
+
+    /// Storage kept by the vfs driver, where every layer is a full copy of
+    /// the filesystem (rootless setups without overlay support).
+    pub fn vfs(storage_root: PathBuf) -> Self {
+        Self {
+            driver: "vfs",
+            ..Self::new(storage_root)
+        }
+    }
+
+    fn read_images(&self) -> Result<Vec<StorageImage>> {
+        let path = self.storage_root.join(format!("{}-images/images.json", self.driver));
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&data).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Read `layers.json` plus `volatile-layers.json`, which newer versions
+    /// use for layers that don't need to survive a crash.
+    fn read_layers(&self) -> Result<HashMap<String, StorageLayer>> {
+        let mut layers = HashMap::new();
+        for file in ["layers.json", "volatile-layers.json"] {
+            let path = self.storage_root.join(format!("{}-layers", self.driver)).join(file);
+            if !path.exists() {
+                continue;
+            }
+            let data = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let parsed: Vec<StorageLayer> = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            layers.extend(parsed.into_iter().map(|l| (l.id.clone(), l)));
+        }
+        Ok(layers)
+    }
+
+    /// Find the image for a user reference, returning it with the matched name.
+    fn resolve_image(&self, image: &str) -> Result<(StorageImage, Option<String>)> {
+        let images = self.read_images()?;
+        let reference = Reference::parse(image);
+
+        let mut found = None;
+        if let Ok(reference) = &reference {
+            'outer: for candidate in reference.candidate_names() {
+                for (i, img) in images.iter().enumerate() {
+                    if img.names.contains(&candidate) {
+                        found = Some((i, Some(candidate)));
+                        break 'outer;
+                    }
+                }
+            }
+
+            // Images pulled by tag are also reachable by their manifest digest
+            if found.is_none()
+                && let Some(digest) = &reference.digest
+            {
+                found = images.iter().enumerate().find_map(|(i, img)| {
+                    if !img.digests.iter().chain(&img.digest).any(|d| d == digest) {
+                        return None;
+                    }
+                    img.names
+                        .iter()
+                        .find(|n| Reference::parse(n).is_ok_and(|r| r.name() == reference.name()))
+                        .map(|n| (i, Some(n.clone())))
+                });
+            }
+        }
+
+        // Fall back to an image ID (prefix) or manifest digest
+        if found.is_none() {
+            let by_id: Vec<usize> = images
+                .iter()
+                .enumerate()
+                .filter(|(_, img)| {
+                    id_prefix(image).is_some_and(|id| img.id.starts_with(id)) || img.digest.as_deref() == Some(image)
+                })
+                .map(|(i, _)| i)
+                .collect();
+            match by_id.as_slice() {
+                [i] => found = Some((*i, None)),
+                [] => {}
+                _ => anyhow::bail!("Image ID '{image}' is ambiguous"),
+            }
+        }
+
+        let Some((index, matched)) = found else {
+            reference?;
+            let known = images.iter().flat_map(|img| &img.names).map(String::as_str);
+            anyhow::bail!(
+                "Image '{image}' not found in {}{}",
+                self.storage_root.display(),
+                did_you_mean(image, known)
+            );
+        };
+        let mut images = images;
+        Ok((images.swap_remove(index), matched))
+    }
+
+    /// Read an image's config, stored as the big-data item keyed by the image ID.
+    fn read_image_config(&self, image: &StorageImage) -> Result<ImageConfig> {
+        let key = format!("sha256:{}", image.id);
+        let path = self
+            .storage_root
+            .join(format!("{}-images", self.driver))
+            .join(&image.id)
+            .join(format!("={}", BASE64.encode(key.as_bytes())));
+        let data = fs::read(&path)
+            .with_context(|| format!("Failed to read image config {}", path.display()))?;
+        ImageConfig::parse(&data).context("Failed to parse image config")
+    }
+
+    /// The storage layer an inspected layer was read from.
+    fn storage_layer(&self, layer: &LayerInfo) -> Result<&StorageLayer> {
+        self.layers_by_digest
+            .get(&layer.digest)
+            .with_context(|| format!("Layer {} not found in layers.json", layer.digest))
+    }
+
+    /// The unpacked files of the storage layer with this ID:
+    /// `overlay/<layer-id>/diff`, or `vfs/dir/<layer-id>` with vfs.
+    fn layer_dir(&self, id: &str) -> Result<PathBuf> {
+        let dir = match self.driver {
+            "vfs" => self.storage_root.join("vfs/dir").join(id),
+            driver => self.storage_root.join(driver).join(id).join("diff"),
+        };
+        if !dir.exists() {
+            anyhow::bail!("Layer directory not found: {}", dir.display());
+        }
+        Ok(dir)
+    }
+
+    /// The files a storage layer added, changed or deleted. A vfs layer
+    /// holds the whole filesystem, so its own files are found by diffing it
+    /// against its parent.
+    fn layer_files(&self, layer: &StorageLayer) -> Result<Vec<FileEntry>> {
+        let dir = self.layer_dir(&layer.id)?;
+        if self.driver != "vfs" {
+            return layer_dir::list_entries(&dir);
+        }
+        let parent_dir = match &layer.parent {
+            Some(parent) => Some(self.layer_dir(parent)?),
+            None => None,
+        };
+        snapshot_diff::diff_dirs(parent_dir.as_deref(), &dir)
+    }
+}
+
+impl Inspector for ContainersStorageInspector {
+    fn inspect(&mut self, image: &str) -> Result<ImageInfo> {
+        // Only the layers of the image inspected last can be listed
+        self.layers_by_digest.clear();
+        self.config_diff_ids.clear();
+        let (storage_image, matched) = self.resolve_image(image)?;
+        let config = self.read_image_config(&storage_image)?;
+        let all_layers = self.read_layers()?;
+
+        // Walk from the top layer down to the base, then flip to base-first
+        let mut chain = Vec::new();
+        let mut next = storage_image.layer.clone();
+        while let Some(id) = next {
+            let layer = all_layers
+                .get(&id)
+                .with_context(|| format!("Layer {id} not found in layers.json"))?;
+            next = layer.parent.clone();
+            chain.push(layer.clone());
+        }
+        chain.reverse();
+
+        let history = config.history();
+        let mut layers = Vec::with_capacity(chain.len());
+        let mut total_size = 0u64;
+
+        for (i, layer) in chain.into_iter().enumerate() {
+            let digest = layer
+                .diff_digest
+                .clone()
+                .or_else(|| config.rootfs.diff_ids.get(i).cloned())
+                .unwrap_or_else(|| layer.id.clone());
+            let size = layer.diff_size.unwrap_or(0);
+            total_size += size;
+            layers.push(LayerInfo {
+                digest: digest.clone(),
+                created_by: history.created_by(i),
+                size,
+                changes: LayerChanges::default(),
+                compression: None,
+                compressed_size: None,
+                files: Vec::new(),
+            });
+            if let Some(diff_id) = config.rootfs.diff_ids.get(i) {
+                self.config_diff_ids.insert(digest.clone(), diff_id.clone());
+            }
+            self.layers_by_digest.insert(digest, layer);
+        }
+
+        let display_name = matched.or_else(|| storage_image.names.first().cloned());
+        let (name, tag) = match display_name {
+            Some(full_name) => split_repo_tag(&full_name),
+            None => (image.to_string(), None),
+        };
+
+        // Like `podman inspect`: every repository the image is named in,
+        // with each manifest digest it was pulled or pushed as
+        let (mut repo_digests, repo_tags): (Vec<String>, Vec<String>) =
+            storage_image.names.iter().cloned().partition(|n| n.contains('@'));
+        for repo_tag in &repo_tags {
+            let (repository, _) = split_repo_tag(repo_tag);
+            for digest in storage_image.digests.iter().chain(&storage_image.digest) {
+                repo_digests.push(format!("{repository}@{digest}"));
+            }
+        }
+        repo_digests.sort();
+        repo_digests.dedup();
+
+        Ok(ImageInfo {
+            name,
+            tag,
+            id: config.id,
+            repo_tags,
+            repo_digests,
+            created: config.created,
+            config: config.config,
+            os: config.os,
+            architecture: config.architecture,
+            variant: config.variant,
+            total_size,
+            layers,
+            filesystem: Vec::new(),
+            efficiency: Efficiency::default(),
+            history: history.steps,
+            warnings: history.warnings,
+        })
+    }
+
+    fn list_images(&mut self) -> Result<Vec<StoredImage>> {
+        let all_layers = self.read_layers()?;
+        // diff-size counts tar headers and padding, so size layers by their
+        // files, once however many images share them
+        let mut sizes: HashMap<String, u64> = HashMap::new();
+        let mut images = Vec::new();
+        for image in self.read_images()? {
+            let mut layers = Vec::new();
+            let mut next = image.layer.clone();
+            while let Some(id) = next {
+                let layer = all_layers
+                    .get(&id)
+                    .with_context(|| format!("Layer {id} not found in layers.json"))?;
+                let size = match sizes.get(&layer.id) {
+                    Some(&size) => size,
+                    None => {
+                        let size = self.layer_files(layer)?.iter().map(|f| f.size).sum();
+                        sizes.insert(layer.id.clone(), size);
+                        size
+                    }
+                };
+                layers.push((layer.id.clone(), size));
+                next = layer.parent.clone();
+            }
+            layers.reverse();
+
+            images.push(StoredImage {
+                id: format!("sha256:{}", image.id),
+                tags: image.names.into_iter().filter(|n| !n.contains('@')).collect(),
+                layers,
+            });
+        }
+        Ok(images)
+    }
+
+    fn list_files(&mut self, layer: &LayerInfo) -> Result<Vec<FileEntry>> {
+        self.layer_files(self.storage_layer(layer)?)
+    }
+
+    fn verify_layer(&mut self, layer: &LayerInfo) -> Result<Vec<DigestCheck>> {
+        // A vfs layer's full copy still holds its own files as unpacked
+        let storage_layer = self.storage_layer(layer)?;
+        let diff_dir = self.layer_dir(&storage_layer.id)?;
+        let tar_split = self
+            .storage_root
+            .join(format!("{}-layers", self.driver))
+            .join(format!("{}.tar-split.gz", storage_layer.id));
+        let expected = self
+            .config_diff_ids
+            .get(&layer.digest)
+            .map_or("(missing from config)", String::as_str);
+        Ok(vec![DigestCheck::new("diff ID", expected, tar_split::diff_id(&tar_split, &diff_dir)?)])
+    }
+
+    fn verify_config(&mut self, image: &str) -> Result<Vec<DigestCheck>> {
+        // The image ID is the digest of its config
+        let (storage_image, _) = self.resolve_image(image)?;
+        let config = self.read_image_config(&storage_image)?;
+        Ok(vec![DigestCheck::new("config", format!("sha256:{}", storage_image.id), config.id)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+    use crate::inspector::FileKind;
+
+    /// A scratch directory removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("peel-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Write `contents` to `path` with a fixed mtime, as a copy made by
+    /// vfs would keep it.
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        File::options().write(true).open(path).unwrap().set_modified(mtime).unwrap();
+    }
+
+    #[test]
+    fn vfs_layers_are_diffed_against_their_parent() {
+        let tmp = TempDir::new("storage-vfs");
+        let root = &tmp.0;
+        let config = serde_json::json!({
+            "architecture": "amd64",
+            "os": "linux",
+            "rootfs": {"type": "layers", "diff_ids": ["sha256:base", "sha256:top"]},
+        })
+        .to_string();
+        let id = format!("{:x}", Sha256::digest(&config));
+        let key = BASE64.encode(format!("sha256:{id}"));
+        write(&root.join(format!("vfs-images/{id}/={key}")), &config);
+        let images = serde_json::json!([{"id": id, "names": ["localhost/app:1.0"], "layer": "top"}]);
+        write(&root.join("vfs-images/images.json"), &images.to_string());
+        let layers = serde_json::json!([
+            {"id": "base", "diff-digest": "sha256:base", "diff-size": 3072},
+            {"id": "top", "parent": "base", "diff-digest": "sha256:top", "diff-size": 4096},
+        ]);
+        write(&root.join("vfs-layers/layers.json"), &layers.to_string());
+
+        // Each layer directory is the whole filesystem as of that layer
+        write(&root.join("vfs/dir/base/etc/os-release"), "ID=test\n");
+        write(&root.join("vfs/dir/base/tmp/scratch"), "x");
+        write(&root.join("vfs/dir/top/etc/os-release"), "ID=test\n");
+        write(&root.join("vfs/dir/top/app/run"), "#!/bin/sh\n");
+        fs::create_dir_all(root.join("vfs/dir/top/tmp")).unwrap();
+
+        let mut inspector = ContainersStorageInspector::vfs(root.clone());
+        let info = inspector.inspect("localhost/app:1.0").unwrap();
+        assert_eq!(info.id, format!("sha256:{id}"));
+        let listed: Vec<Vec<String>> = info
+            .layers
+            .iter()
+            .map(|layer| {
+                let files = inspector.list_files(layer).unwrap();
+                let mut paths: Vec<String> = files
+                    .iter()
+                    .filter(|f| f.kind != FileKind::Dir)
+                    .map(|f| f.path.display().to_string())
+                    .collect();
+                paths.sort();
+                paths
+            })
+            .collect();
+        assert_eq!(listed, [vec!["etc/os-release", "tmp/scratch"], vec!["app/run", "tmp/.wh.scratch"]]);
+
+        // Sized by the files each layer adds, not by the full copies
+        let stored = inspector.list_images().unwrap();
+        assert_eq!(stored[0].layers, [("base".to_string(), 9), ("top".to_string(), 10)]);
+    }
+}
@@ -0,0 +1,508 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+
+use super::docker_archive::{ArchiveLayers, SavedArchive};
+use super::http::{self, ChannelReader, Connection};
+use super::reference::{did_you_mean, Reference};
+use super::{
+    split_repo_tag, timestamp, DigestCheck, Efficiency, FileEntry, HistoryStep, ImageInfo, Inspector, LayerChanges,
+    LayerInfo, Platform, RuntimeConfig,
+};
+
+/// Default Docker Engine socket when `DOCKER_HOST` is unset.
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+/// Where the Docker Engine API listens.
+#[derive(Debug, Clone)]
+pub enum Endpoint {
+    #[cfg(unix)]
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl Endpoint {
+    /// Resolve the endpoint from `DOCKER_HOST` (`unix://...` or `tcp://...`),
+    /// defaulting to the local unix socket.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("DOCKER_HOST") {
+            Ok(host) if !host.is_empty() => Self::parse(&host),
+            _ => Self::parse(&format!("unix://{DEFAULT_SOCKET}")),
+        }
+    }
+
+    fn parse(host: &str) -> Result<Self> {
+        if let Some(path) = host.strip_prefix("unix://") {
+            #[cfg(unix)]
+            return Ok(Endpoint::Unix(PathBuf::from(path)));
+            #[cfg(not(unix))]
+            anyhow::bail!("unix sockets are not supported on this platform ({path})");
+        }
+        if let Some(addr) = host.strip_prefix("tcp://") {
+            let addr = addr.trim_end_matches('/');
+            return Ok(Endpoint::Tcp(addr.to_string()));
+        }
+        anyhow::bail!("Unsupported DOCKER_HOST '{host}' (expected unix:// or tcp://)")
+    }
+
+    /// Whether the endpoint looks usable without making a request.
+    /// For unix sockets this checks the socket can actually be opened,
+    /// which fails for users outside the `docker` group.
+    pub fn is_reachable(&self) -> bool {
+        match self {
+            #[cfg(unix)]
+            Endpoint::Unix(path) => std::os::unix::net::UnixStream::connect(path).is_ok(),
+            Endpoint::Tcp(_) => true,
+        }
+    }
+
+    fn host_header(&self) -> &str {
+        match self {
+            #[cfg(unix)]
+            Endpoint::Unix(_) => "docker",
+            Endpoint::Tcp(addr) => addr,
+        }
+    }
+
+    async fn connect(&self) -> Result<Box<dyn Connection>> {
+        match self {
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                let stream = tokio::net::UnixStream::connect(path)
+                    .await
+                    .with_context(|| format!("Failed to connect to {}", path.display()))?;
+                Ok(Box::new(stream))
+            }
+            Endpoint::Tcp(addr) => {
+                let stream = tokio::net::TcpStream::connect(addr.as_str())
+                    .await
+                    .with_context(|| format!("Failed to connect to {addr}"))?;
+                Ok(Box::new(stream))
+            }
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<http::Response> {
+        let conn = self.connect().await?;
+        let response = http::send(conn, "GET", self.host_header(), path, &[], &[]).await?;
+        if response.is_success() {
+            return Ok(response);
+        }
+
+        let status = response.status;
+        let body = response.bytes().await.unwrap_or_default();
+        let message = serde_json::from_slice::<ApiError>(&body)
+            .map(|e| e.message)
+            .unwrap_or_else(|_| String::from_utf8_lossy(&body).trim().to_string());
+        anyhow::bail!("Docker API {path} returned {status}: {message}")
+    }
+
+    async fn get_json<T: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<T> {
+        let body = self.get(path).await?.bytes().await?;
+        serde_json::from_slice(&body).with_context(|| format!("Failed to parse response of {path}"))
+    }
+
+    /// "did you mean" hint from the daemon's image list, for an image it
+    /// doesn't have. Empty if nothing is close.
+    async fn tag_hint(&self, image: &str) -> String {
+        let Ok(summaries) = self.get_json::<Vec<ImageSummary>>("/images/json").await else {
+            return String::new();
+        };
+        let known = summaries.iter().flat_map(|s| s.repo_tags.iter().flatten()).map(String::as_str);
+        did_you_mean(image, known)
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+/// Response of `GET /images/{name}/json`.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ImageInspect {
+    id: String,
+    #[serde(default, deserialize_with = "super::null_as_default")]
+    repo_tags: Vec<String>,
+    #[serde(default, deserialize_with = "super::null_as_default")]
+    repo_digests: Vec<String>,
+    created: Option<String>,
+    #[serde(default, deserialize_with = "super::null_as_default")]
+    config: RuntimeConfig,
+    os: Option<String>,
+    architecture: Option<String>,
+    variant: Option<String>,
+    #[serde(rename = "RootFS")]
+    root_fs: RootFs,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RootFs {
+    #[serde(default)]
+    layers: Vec<String>,
+}
+
+/// One entry of `GET /images/json`.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ImageSummary {
+    repo_tags: Option<Vec<String>>,
+}
+
+/// One entry of `GET /images/{name}/history`, newest first.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HistoryItem {
+    /// Unix seconds
+    created: i64,
+    created_by: String,
+    #[serde(default)]
+    comment: String,
+    size: u64,
+}
+
+/// Reads layers through the Docker Engine HTTP API (`/var/run/docker.sock`
+/// or `DOCKER_HOST`). No root and no `docker` binary needed, only access
+/// to the socket. Metadata comes from `/json` and `/history`; file listings
+/// stream out of `/get` the first time they are needed.
+pub struct DockerApiInspector {
+    endpoint: Endpoint,
+    runtime: Runtime,
+    /// Image ID resolved during `inspect`
+    image_id: Option<String>,
+    /// Whether to hash layer blobs while reading `/get`
+    verify: bool,
+    /// Layers read from `/get`
+    layers: Option<ArchiveLayers>,
+}
+
+impl DockerApiInspector {
+    pub fn new(endpoint: Endpoint) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            endpoint,
+            runtime,
+            image_id: None,
+            verify: false,
+            layers: None,
+        })
+    }
+
+    /// Stream `/images/{id}/get` through the archive parser.
+    async fn fetch_archive(endpoint: &Endpoint, image_id: &str, hash: bool) -> Result<SavedArchive> {
+        let response = endpoint.get(&format!("/images/{image_id}/get")).await?;
+
+        let (tx, rx) = mpsc::channel(16);
+        let parser = tokio::task::spawn_blocking(move || SavedArchive::read(ChannelReader::new(rx), hash));
+        let download = response.pipe_to(tx).await;
+        let archive = parser.await?;
+
+        // A broken download surfaces as a truncated tar; report the root cause
+        download.context("Failed to download image archive")?;
+        archive.context("Failed to read image archive from the Docker API")
+    }
+
+    fn load_archive(&mut self, image: &str) -> Result<(ImageInfo, ArchiveLayers)> {
+        let image_id = self.image_id.as_deref().unwrap_or(image);
+        let archive = self.runtime.block_on(Self::fetch_archive(&self.endpoint, image_id, self.verify))?;
+        archive.image_info(image, None, &Platform::host())
+    }
+
+    /// The layers from `/get`, fetched the first time they are needed.
+    fn archive_layers(&mut self) -> Result<&ArchiveLayers> {
+        if self.layers.is_none() {
+            let image_id = self.image_id.clone().context("inspect() must run before list_files()")?;
+            let (_, layers) = self.load_archive(&image_id)?;
+            self.layers = Some(layers);
+        }
+        Ok(self.layers.as_ref().expect("layers were just loaded"))
+    }
+}
+
+impl Inspector for DockerApiInspector {
+    fn inspect(&mut self, image: &str) -> Result<ImageInfo> {
+        let endpoint = &self.endpoint;
+        let (inspect, history) = self.runtime.block_on(async {
+            let inspect: ImageInspect = match endpoint.get_json(&format!("/images/{image}/json")).await {
+                Ok(inspect) => inspect,
+                Err(e) => {
+                    let hint = endpoint.tag_hint(image).await;
+                    return Err(if hint.is_empty() { e } else { e.context(format!("Image '{image}' not found{hint}")) });
+                }
+            };
+            let history: Vec<HistoryItem> = endpoint
+                .get_json(&format!("/images/{}/history", inspect.id))
+                .await?;
+            anyhow::Ok((inspect, history))
+        })?;
+        self.image_id = Some(inspect.id.clone());
+
+        // History is newest-first and only reports a size for non-empty steps.
+        // Zero-byte layers make the pairing ambiguous; take the exact answer
+        // from the image config inside the archive in that case.
+        let layer_steps: Vec<&HistoryItem> = history.iter().rev().filter(|h| h.size > 0).collect();
+        if layer_steps.len() != inspect.root_fs.layers.len() {
+            let (info, layers) = self.load_archive(image)?;
+            self.layers = Some(layers);
+            // The archive only knows the tag it was saved under
+            return Ok(ImageInfo {
+                repo_tags: inspect.repo_tags,
+                repo_digests: inspect.repo_digests,
+                ..info
+            });
+        }
+
+        let layers: Vec<LayerInfo> = inspect
+            .root_fs
+            .layers
+            .iter()
+            .zip(&layer_steps)
+            .map(|(digest, step)| LayerInfo {
+                digest: digest.clone(),
+                created_by: Some(step.created_by.clone()).filter(|c| !c.is_empty()),
+                size: step.size,
+                changes: LayerChanges::default(),
+                compression: None,
+                compressed_size: None,
+                files: Vec::new(),
+            })
+            .collect();
+        let total_size = layers.iter().map(|l| l.size).sum();
+
+        // The API has no empty-layer flag, but with the sizes lining up
+        // exactly, the zero-size steps are the config-only ones
+        let mut next_layer = 0;
+        let steps = history
+            .iter()
+            .rev()
+            .map(|item| {
+                let empty_layer = item.size == 0;
+                let layer = (!empty_layer).then(|| {
+                    next_layer += 1;
+                    next_layer - 1
+                });
+                HistoryStep {
+                    created: Some(timestamp::format_rfc3339(item.created)),
+                    created_by: Some(item.created_by.clone()).filter(|c| !c.is_empty()),
+                    author: None,
+                    comment: Some(item.comment.clone()).filter(|c| !c.is_empty()),
+                    empty_layer,
+                    layer,
+                }
+            })
+            .collect();
+
+        // Prefer the tag the user asked for, otherwise the first one
+        let wanted = Reference::parse(image).ok();
+        let repo_tag = inspect
+            .repo_tags
+            .iter()
+            .find(|t| wanted.as_ref().is_some_and(|w| w.is_same_image(t)))
+            .or(inspect.repo_tags.first());
+        let (name, tag) = match repo_tag {
+            Some(repo_tag) => split_repo_tag(repo_tag),
+            None => (image.to_string(), None),
+        };
+
+        Ok(ImageInfo {
+            name,
+            tag,
+            id: inspect.id,
+            repo_tags: inspect.repo_tags,
+            repo_digests: inspect.repo_digests,
+            created: inspect.created,
+            config: inspect.config,
+            os: inspect.os,
+            architecture: inspect.architecture,
+            variant: inspect.variant,
+            total_size,
+            layers,
+            filesystem: Vec::new(),
+            efficiency: Efficiency::default(),
+            history: steps,
+            warnings: Vec::new(),
+        })
+    }
+
+    fn list_files(&mut self, layer: &LayerInfo) -> Result<Vec<FileEntry>> {
+        self.archive_layers()?
+            .files
+            .get(&layer.digest)
+            .cloned()
+            .with_context(|| format!("Layer {} not found in image archive", layer.digest))
+    }
+
+    fn enable_verify(&mut self) {
+        self.verify = true;
+    }
+
+    fn verify_layer(&mut self, layer: &LayerInfo) -> Result<Vec<DigestCheck>> {
+        self.archive_layers()?.checks(layer)
+    }
+
+    fn verify_config(&mut self, _image: &str) -> Result<Vec<DigestCheck>> {
+        Ok(self.archive_layers()?.config_checks())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+    use crate::inspector::test_http::{self, Reply, Request};
+
+    /// Paths requested from the stand-in daemon
+    type Requests = Arc<Mutex<Vec<String>>>;
+
+    fn sha256(data: &[u8]) -> String {
+        format!("sha256:{:x}", Sha256::digest(data))
+    }
+
+    fn append(tar: &mut tar::Builder<Vec<u8>>, path: &str, data: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, path, data).unwrap();
+    }
+
+    /// A `docker save` of a two-layer image whose second layer is empty,
+    /// with the diff IDs of its layers.
+    fn saved_image() -> (Vec<u8>, Vec<String>) {
+        let mut layer = tar::Builder::new(Vec::new());
+        append(&mut layer, "app/hello", b"hello world");
+        let layer = layer.into_inner().unwrap();
+        let empty = tar::Builder::new(Vec::new()).into_inner().unwrap();
+        let diff_ids = vec![sha256(&layer), sha256(&empty)];
+
+        let config = json!({
+            "architecture": "amd64",
+            "os": "linux",
+            "rootfs": {"type": "layers", "diff_ids": diff_ids},
+            "history": [
+                {"created_by": "COPY hello /app/"},
+                {"created_by": "ENV A=1", "empty_layer": true},
+                {"created_by": "RUN true"},
+            ],
+        })
+        .to_string();
+        let config_name = format!("{}.json", &sha256(config.as_bytes())[7..]);
+        let manifest = json!([{
+            "Config": config_name,
+            "RepoTags": ["app:1.0"],
+            "Layers": ["1/layer.tar", "2/layer.tar"],
+        }]);
+
+        let mut archive = tar::Builder::new(Vec::new());
+        append(&mut archive, "1/layer.tar", &layer);
+        append(&mut archive, "2/layer.tar", &empty);
+        append(&mut archive, &config_name, config.as_bytes());
+        append(&mut archive, "manifest.json", manifest.to_string().as_bytes());
+        (archive.into_inner().unwrap(), diff_ids)
+    }
+
+    /// A stand-in daemon on a unix socket, serving `history` for an image
+    /// whose `/json` lists `layers`, and recording the paths requested.
+    fn daemon(name: &str, layers: &[String], history: serde_json::Value) -> (DockerApiInspector, Requests) {
+        let socket = std::env::temp_dir().join(format!("peel-{name}-{}.sock", std::process::id()));
+        let (archive, _) = saved_image();
+        let inspect = json!({
+            "Id": "sha256:abc123",
+            "RepoTags": ["app:1.0", "app:latest"],
+            "RepoDigests": null,
+            "Created": "2024-01-02T03:04:05Z",
+            "Config": null,
+            "Os": "linux",
+            "Architecture": "amd64",
+            "RootFS": {"Type": "layers", "Layers": layers},
+        });
+
+        let requests: Requests = Arc::default();
+        let recorded = requests.clone();
+        test_http::serve_unix(&socket, move |request: &Request| {
+            recorded.lock().unwrap().push(request.path.clone());
+            match request.path.as_str() {
+                "/images/app:1.0/json" => Reply::json(&inspect),
+                "/images/sha256:abc123/history" => Reply::json(&history),
+                "/images/sha256:abc123/get" => Reply::ok(archive.clone()).chunked(),
+                "/images/json" => Reply::json(&json!([{"RepoTags": ["app:1.0"]}, {"RepoTags": null}])),
+                path => Reply::status(404).body(json!({"message": format!("No such image: {path}")}).to_string()),
+            }
+        });
+        (DockerApiInspector::new(Endpoint::Unix(socket)).unwrap(), requests)
+    }
+
+    fn paths(files: &[FileEntry]) -> Vec<&str> {
+        files.iter().map(|f| f.path.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn metadata_from_json_and_history() {
+        let (_, diff_ids) = saved_image();
+        let history = json!([
+            {"Created": 1704164645, "CreatedBy": "ENV A=1", "Size": 0},
+            {"Created": 1704164645, "CreatedBy": "COPY hello /app/", "Size": 11, "Comment": "buildkit"},
+        ]);
+        let (mut inspector, requests) = daemon("api-history", &diff_ids[..1], history);
+
+        let info = inspector.inspect("app:1.0").unwrap();
+        assert_eq!((info.name.as_str(), info.tag.as_deref()), ("app", Some("1.0")));
+        assert_eq!(info.repo_tags, ["app:1.0", "app:latest"]);
+        assert_eq!(info.layers.len(), 1);
+        assert_eq!(info.layers[0].size, 11);
+        assert_eq!(info.layers[0].created_by.as_deref(), Some("COPY hello /app/"));
+        let empty: Vec<bool> = info.history.iter().map(|s| s.empty_layer).collect();
+        assert_eq!(empty, [false, true]);
+        assert_eq!(info.history[0].created.as_deref(), Some("2024-01-02T03:04:05Z"));
+        // Nothing is downloaded until the files are needed
+        assert!(!requests.lock().unwrap().iter().any(|p| p.ends_with("/get")));
+
+        let files = inspector.list_files(&info.layers[0]).unwrap();
+        assert_eq!(paths(&files), ["app/hello"]);
+    }
+
+    #[test]
+    fn zero_size_layer_falls_back_to_the_archive() {
+        let (_, diff_ids) = saved_image();
+        // The empty RUN layer reports size 0 like the ENV step, so the
+        // history can't be paired with the two layers
+        let history = json!([
+            {"Created": 1704164645, "CreatedBy": "RUN true", "Size": 0},
+            {"Created": 1704164645, "CreatedBy": "ENV A=1", "Size": 0},
+            {"Created": 1704164645, "CreatedBy": "COPY hello /app/", "Size": 11},
+        ]);
+        let (mut inspector, requests) = daemon("api-fallback", &diff_ids, history);
+
+        let info = inspector.inspect("app:1.0").unwrap();
+        assert_eq!(requests.lock().unwrap().iter().filter(|p| p.ends_with("/get")).count(), 1);
+        assert_eq!(info.repo_tags, ["app:1.0", "app:latest"]);
+        let created_by: Vec<Option<&str>> = info.layers.iter().map(|l| l.created_by.as_deref()).collect();
+        assert_eq!(created_by, [Some("COPY hello /app/"), Some("RUN true")]);
+        assert_eq!(info.layers[1].digest, diff_ids[1]);
+
+        assert_eq!(paths(&inspector.list_files(&info.layers[0]).unwrap()), ["app/hello"]);
+        assert!(inspector.list_files(&info.layers[1]).unwrap().is_empty());
+        // The archive read during inspect is reused
+        assert_eq!(requests.lock().unwrap().iter().filter(|p| p.ends_with("/get")).count(), 1);
+    }
+
+    #[test]
+    fn unknown_image_suggests_known_tags() {
+        let (mut inspector, _) = daemon("api-unknown", &[], json!([]));
+        let err = inspector.inspect("app:1.1").unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.contains("Image 'app:1.1' not found"), "{message}");
+        assert!(message.contains("did you mean app:1.0"), "{message}");
+        assert!(message.contains("returned 404"), "{message}");
+    }
+}
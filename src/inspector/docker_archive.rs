@@ -1,30 +1,615 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use oci_spec::image::{Descriptor, ImageIndex, ImageManifest};
+use serde::Deserialize;
 
-use super::{FileEntry, ImageInfo, Inspector, LayerInfo};
+use super::image_config::ImageConfig;
+use super::blob_store::is_index;
+use super::layer_blob::{self, LayerListing};
+use super::oci_layout::{annotation, IMAGE_NAME_ANNOTATION, REF_NAME_ANNOTATION};
+use super::platform::{self, Platform};
+use super::reference::Reference;
+use super::{split_repo_tag, DigestCheck, Efficiency, FileEntry, ImageInfo, Inspector, LayerChanges, LayerInfo};
 
-/// Reads layers from a `docker save` tar archive.
+/// How many links between archive entries to follow before giving up.
+const MAX_LINK_HOPS: usize = 8;
+
+/// Reads layers from a `docker save` or `oci-archive` tarball, optionally
+/// compressed with gzip, zstd or xz. `-` reads the archive from stdin.
 /// Cross-platform, no daemon needed, but requires decompression.
 pub struct DockerArchiveInspector {
     archive_path: PathBuf,
+    /// Platform to pick from multi-platform OCI archives
+    platform: Platform,
+    /// The archive, read once on first use (stdin can't be read twice)
+    archive: Option<SavedArchive>,
+    /// Whether to hash layer blobs while reading the archive
+    verify: bool,
+    /// Layers read during `inspect`
+    layers: ArchiveLayers,
 }
 
 impl DockerArchiveInspector {
     pub fn new(archive_path: PathBuf) -> Self {
-        Self { archive_path }
+        Self {
+            archive_path,
+            platform: Platform::host(),
+            archive: None,
+            verify: false,
+            layers: ArchiveLayers::default(),
+        }
+    }
+
+    fn is_stdin(&self) -> bool {
+        self.archive_path == Path::new("-")
+    }
+
+    fn source_name(&self) -> String {
+        if self.is_stdin() {
+            "stdin".to_string()
+        } else {
+            self.archive_path.display().to_string()
+        }
+    }
+
+    fn archive(&mut self) -> Result<&SavedArchive> {
+        if self.archive.is_none() {
+            let reader: Box<dyn Read> = if self.is_stdin() {
+                Box::new(io::stdin().lock())
+            } else {
+                let file = File::open(&self.archive_path)
+                    .with_context(|| format!("Failed to open {}", self.archive_path.display()))?;
+                Box::new(BufReader::new(file))
+            };
+            let archive = SavedArchive::read(reader, self.verify)
+                .with_context(|| format!("Failed to read {}", self.source_name()))?;
+            self.archive = Some(archive);
+        }
+        Ok(self.archive.as_ref().expect("archive was just read"))
     }
 }
 
 impl Inspector for DockerArchiveInspector {
     fn inspect(&mut self, _image: &str) -> Result<ImageInfo> {
-        // TODO: read manifest.json from tar, enumerate layer dirs
-        let _ = &self.archive_path;
-        anyhow::bail!("docker archive inspector not yet implemented")
+        let source = self.source_name();
+        let platform = self.platform.clone();
+        let (info, layers) = self.archive()?.image_info(&source, None, &platform)?;
+        self.layers = layers;
+        Ok(info)
+    }
+
+    fn list_files(&mut self, layer: &LayerInfo) -> Result<Vec<FileEntry>> {
+        self.layers
+            .files
+            .get(&layer.digest)
+            .cloned()
+            .with_context(|| format!("Layer {} not found in archive", layer.digest))
+    }
+
+    fn enable_verify(&mut self) {
+        self.verify = true;
+    }
+
+    fn verify_layer(&mut self, layer: &LayerInfo) -> Result<Vec<DigestCheck>> {
+        self.layers.checks(layer)
+    }
+
+    fn verify_config(&mut self, _image: &str) -> Result<Vec<DigestCheck>> {
+        Ok(self.layers.config_checks())
+    }
+
+    fn select_platform(&mut self, platform: &Platform) {
+        self.platform = platform.clone();
+    }
+
+    fn platforms(&mut self, _image: &str) -> Result<Vec<Platform>> {
+        self.archive()?.platforms()
+    }
+}
+
+/// Check whether a file is an image archive by its content: a tar, or a
+/// tar compressed with gzip, zstd or xz, whatever its extension.
+pub fn is_archive_file(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let mut head = Vec::with_capacity(512);
+    file.take(512).read_to_end(&mut head).is_ok() && layer_blob::is_archive(&head)
+}
+
+/// What was read of an image's layers, keyed by layer digest.
+#[derive(Default)]
+pub struct ArchiveLayers {
+    pub files: HashMap<String, Vec<FileEntry>>,
+    /// Digest checks, if the archive was read with hashing on
+    pub checks: HashMap<String, Vec<DigestCheck>>,
+    /// The config's digest against the name the archive stores it under
+    pub config: Option<DigestCheck>,
+}
+
+impl ArchiveLayers {
+    pub fn checks(&self, layer: &LayerInfo) -> Result<Vec<DigestCheck>> {
+        self.checks
+            .get(&layer.digest)
+            .cloned()
+            .with_context(|| format!("Layer {} was not hashed while it was read", layer.digest))
+    }
+
+    pub fn config_checks(&self) -> Vec<DigestCheck> {
+        self.config.iter().cloned().collect()
+    }
+}
+
+/// One entry of `manifest.json` in a `docker save` archive.
+#[derive(Deserialize)]
+struct ManifestEntry {
+    #[serde(rename = "Config")]
+    config: String,
+    #[serde(rename = "RepoTags", default)]
+    repo_tags: Option<Vec<String>>,
+    #[serde(rename = "Layers")]
+    layers: Vec<String>,
+}
+
+/// The contents of a `docker save` or `oci-archive` stream, collected in
+/// a single pass.
+///
+/// The legacy Docker layout (`<id>/layer.tar`, `<config>.json`), the
+/// Docker 25+ layout and OCI archives (`blobs/sha256/<hex>`) are handled
+/// the same way: JSON files are kept in memory, layer blobs are listed as
+/// they stream past, and everything is tied together at the end via
+/// `manifest.json`, or `index.json` for archives that only have that.
+/// This works on non-seekable input such as a pipe from `docker save`,
+/// and on archives wrapped in gzip, zstd or xz. With `hash`, layer blobs
+/// are hashed as they stream past, for `--verify`.
+pub struct SavedArchive {
+    /// Small JSON files (manifest.json, image configs) keyed by archive path
+    metadata: HashMap<String, Vec<u8>>,
+    /// File listings of layer blobs keyed by archive path
+    layers: HashMap<String, LayerListing>,
+    /// Symlinks and hard links between entries, keyed by archive path.
+    /// Legacy saves link `<id>/layer.tar` to an earlier copy when an image
+    /// repeats a layer.
+    aliases: HashMap<String, String>,
+}
+
+impl SavedArchive {
+    pub fn read<R: Read>(reader: R, hash: bool) -> Result<Self> {
+        let mut archive = tar::Archive::new(layer_blob::decompress(reader)?);
+        let mut metadata = HashMap::new();
+        let mut layers = HashMap::new();
+        let mut aliases = HashMap::new();
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_symlink() || entry_type.is_hard_link() {
+                let path = normalize_entry_path(&entry.path()?);
+                if let Some(target) = entry.link_name()? {
+                    // Hard links name the target by its archive path,
+                    // symlinks relative to the link's directory
+                    let target = if entry_type.is_symlink() {
+                        resolve_symlink(&path, &target)
+                    } else {
+                        normalize_entry_path(&target)
+                    };
+                    aliases.insert(path, target);
+                }
+                continue;
+            }
+            if !entry_type.is_file() {
+                continue;
+            }
+            let path = normalize_entry_path(&entry.path()?);
+
+            // Peek at the first block to tell JSON metadata from layer blobs
+            let mut head = Vec::with_capacity(512);
+            (&mut entry).take(512).read_to_end(&mut head)?;
+
+            if matches!(head.first(), Some(b'{' | b'[')) {
+                let mut data = head;
+                entry.read_to_end(&mut data)?;
+                metadata.insert(path, data);
+            } else if layer_blob::sniff(&head).is_some() {
+                let listing = layer_blob::list(head.as_slice().chain(&mut entry), hash)
+                    .with_context(|| format!("Failed to list layer {path}"))?;
+                layers.insert(path, listing);
+            }
+        }
+
+        Ok(Self {
+            metadata,
+            layers,
+            aliases,
+        })
+    }
+
+    /// Follow links between archive entries to the one holding the data.
+    fn resolve<'a>(&'a self, mut path: &'a str) -> &'a str {
+        for _ in 0..MAX_LINK_HOPS {
+            match self.aliases.get(path) {
+                Some(target) => path = target,
+                None => break,
+            }
+        }
+        path
+    }
+
+    /// Resolve the archive's manifest into image metadata, returning what
+    /// was read of its layers alongside it.
+    ///
+    /// An archive can hold several images (`docker save nginx` saves every
+    /// tag); `wanted` picks the one tagged with that reference, otherwise
+    /// the first image is used. `platform` picks from multi-platform OCI
+    /// archives; `docker save` archives hold a single platform per image.
+    pub fn image_info(
+        &self,
+        fallback_name: &str,
+        wanted: Option<&str>,
+        platform: &Platform,
+    ) -> Result<(ImageInfo, ArchiveLayers)> {
+        if self.metadata.contains_key("manifest.json") {
+            self.docker_image(fallback_name, wanted)
+        } else if self.metadata.contains_key("index.json") {
+            self.oci_image(fallback_name, wanted, platform)
+        } else {
+            anyhow::bail!("manifest.json not found in archive (is this a `docker save` or OCI archive?)")
+        }
+    }
+
+    /// Every platform in a multi-platform OCI archive whose blobs are
+    /// present. Empty for `docker save` archives.
+    pub fn platforms(&self) -> Result<Vec<Platform>> {
+        if self.metadata.contains_key("manifest.json") || !self.metadata.contains_key("index.json") {
+            return Ok(Vec::new());
+        }
+        let index = self.read_index("index.json")?;
+        let candidates: Vec<&Descriptor> = index.manifests().iter().collect();
+        self.platforms_of(&candidates)
+    }
+
+    fn platforms_of(&self, descriptors: &[&Descriptor]) -> Result<Vec<Platform>> {
+        let mut platforms = platform::list(descriptors, |d| self.has_blob(d));
+        for descriptor in descriptors {
+            if !is_index(descriptor.media_type()) || !self.has_blob(descriptor) {
+                continue;
+            }
+            let index = self.read_index(&blob_path(descriptor))?;
+            let children: Vec<&Descriptor> = index.manifests().iter().collect();
+            for platform in self.platforms_of(&children)? {
+                if !platforms.contains(&platform) {
+                    platforms.push(platform);
+                }
+            }
+        }
+        Ok(platforms)
+    }
+
+    /// Read the image from a `docker save` archive's `manifest.json`.
+    fn docker_image(
+        &self,
+        fallback_name: &str,
+        wanted: Option<&str>,
+    ) -> Result<(ImageInfo, ArchiveLayers)> {
+        let manifest: Vec<ManifestEntry> =
+            serde_json::from_slice(&self.metadata["manifest.json"]).context("Failed to parse manifest.json")?;
+        let wanted = wanted.and_then(|w| Reference::parse(w).ok());
+        let position = wanted
+            .and_then(|wanted| {
+                manifest.iter().position(|m| {
+                    m.repo_tags
+                        .iter()
+                        .flatten()
+                        .any(|t| wanted.is_same_image(t))
+                })
+            })
+            .unwrap_or(0);
+        let entry = manifest
+            .into_iter()
+            .nth(position)
+            .context("manifest.json contains no images")?;
+
+        let config_data = self
+            .metadata
+            .get(self.resolve(&entry.config))
+            .with_context(|| format!("Image config {} not found in archive", entry.config))?;
+        let config = ImageConfig::parse(config_data)
+            .with_context(|| format!("Failed to parse image config {}", entry.config))?;
+
+        // Legacy archives name the config `<hex>.json`, newer ones store
+        // it as a blob
+        let expected = blob_digest(&entry.config).or_else(|| {
+            let hex = entry.config.strip_suffix(".json")?;
+            Some(format!("sha256:{hex}"))
+        });
+
+        let repo_tags = entry.repo_tags.unwrap_or_default();
+        let (name, tag) = match repo_tags.first() {
+            Some(repo_tag) => split_repo_tag(repo_tag),
+            None => (fallback_name.to_string(), None),
+        };
+        let (info, mut layers) = self.build_image(config, &entry.layers, name, tag, repo_tags)?;
+        layers.config = expected.map(|expected| DigestCheck::new("config", expected, &info.id));
+        Ok((info, layers))
+    }
+
+    /// Read the image from an OCI archive's `index.json`, following nested
+    /// indexes down to a manifest whose blobs are in the archive.
+    fn oci_image(
+        &self,
+        fallback_name: &str,
+        wanted: Option<&str>,
+        platform: &Platform,
+    ) -> Result<(ImageInfo, ArchiveLayers)> {
+        let index = self.read_index("index.json")?;
+        let mut candidates: Vec<&Descriptor> = index.manifests().iter().collect();
+        if let Some(wanted) = wanted {
+            let reference = Reference::parse(wanted).ok();
+            let matching: Vec<&Descriptor> = candidates
+                .iter()
+                .copied()
+                .filter(|d| {
+                    let by_name = annotation(d, IMAGE_NAME_ANNOTATION)
+                        .zip(reference.as_ref())
+                        .is_some_and(|(name, reference)| reference.is_same_image(name));
+                    by_name || annotation(d, REF_NAME_ANNOTATION) == Some(wanted)
+                })
+                .collect();
+            if !matching.is_empty() {
+                candidates = matching;
+            }
+        }
+
+        let descriptor = platform::select(&candidates, platform, |d| self.has_blob(d))
+            .context("No usable image manifest in index.json")?
+            .clone();
+        let manifest = self.resolve_manifest(&descriptor, platform)?;
+
+        let config_path = blob_path(manifest.config());
+        let config_data = self
+            .metadata
+            .get(&config_path)
+            .with_context(|| format!("Image config {config_path} not found in archive"))?;
+        let config = ImageConfig::parse(config_data)
+            .with_context(|| format!("Failed to parse image config {config_path}"))?;
+
+        let (name, tag) = match annotation(&descriptor, IMAGE_NAME_ANNOTATION) {
+            Some(full_name) => split_repo_tag(full_name),
+            None => (
+                fallback_name.to_string(),
+                annotation(&descriptor, REF_NAME_ANNOTATION).map(String::from),
+            ),
+        };
+        // Every name the index gives the same manifest
+        let repo_tags = index
+            .manifests()
+            .iter()
+            .filter(|d| d.digest() == descriptor.digest())
+            .filter_map(|d| annotation(d, IMAGE_NAME_ANNOTATION))
+            .map(String::from)
+            .collect();
+        let layer_paths: Vec<String> = manifest.layers().iter().map(blob_path).collect();
+        let (info, mut layers) = self.build_image(config, &layer_paths, name, tag, repo_tags)?;
+        layers.config = Some(DigestCheck::new("config", manifest.config().digest().to_string(), &info.id));
+        Ok((info, layers))
+    }
+
+    /// Whether a blob was stored in the archive; other platforms of an
+    /// index are often left out.
+    fn has_blob(&self, descriptor: &Descriptor) -> bool {
+        self.metadata.contains_key(&blob_path(descriptor))
+    }
+
+    fn read_index(&self, path: &str) -> Result<ImageIndex> {
+        let data = self
+            .metadata
+            .get(path)
+            .with_context(|| format!("{path} not found in archive"))?;
+        ImageIndex::from_reader(data.as_slice()).with_context(|| format!("Failed to parse {path}"))
+    }
+
+    fn resolve_manifest(&self, descriptor: &Descriptor, platform: &Platform) -> Result<ImageManifest> {
+        let path = blob_path(descriptor);
+        let data = self
+            .metadata
+            .get(&path)
+            .with_context(|| format!("Manifest {path} not found in archive"))?;
+
+        let value: serde_json::Value =
+            serde_json::from_slice(data).with_context(|| format!("Failed to parse {path}"))?;
+        if value.get("manifests").is_none() {
+            return ImageManifest::from_reader(data.as_slice())
+                .with_context(|| format!("Failed to parse manifest {path}"));
+        }
+
+        let index = self.read_index(&path)?;
+        let candidates: Vec<&Descriptor> = index.manifests().iter().collect();
+        let child = platform::select(&candidates, platform, |d| self.has_blob(d))
+            .with_context(|| format!("No usable image manifest in index {path}"))?;
+        self.resolve_manifest(child, platform)
+    }
+
+    /// Pair the config's history with the listed layer blobs.
+    fn build_image(
+        &self,
+        config: ImageConfig,
+        layer_paths: &[String],
+        name: String,
+        tag: Option<String>,
+        repo_tags: Vec<String>,
+    ) -> Result<(ImageInfo, ArchiveLayers)> {
+        let history = config.history();
+        let mut layers = Vec::with_capacity(layer_paths.len());
+        let mut archive_layers = ArchiveLayers::default();
+        let mut total_size = 0u64;
+
+        for (i, layer_path) in layer_paths.iter().enumerate() {
+            let listing = self
+                .layers
+                .get(self.resolve(layer_path))
+                .with_context(|| format!("Layer {layer_path} not found in archive"))?;
+            let digest = config
+                .rootfs
+                .diff_ids
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| layer_path.clone());
+
+            let size = listing.files.iter().map(|f| f.size).sum();
+            total_size += size;
+            layers.push(LayerInfo {
+                digest: digest.clone(),
+                created_by: history.created_by(i),
+                size,
+                changes: LayerChanges::default(),
+                compression: Some(listing.compression),
+                compressed_size: Some(listing.compressed_size),
+                files: Vec::new(),
+            });
+            if let Some(digests) = &listing.digests {
+                // Docker 25+ and OCI archives name blobs by digest; legacy
+                // `<id>/layer.tar` entries only have their diff ID
+                let expected_blob = blob_digest(layer_path);
+                let expected_diff_id = config.rootfs.diff_ids.get(i).map(String::as_str);
+                archive_layers
+                    .checks
+                    .insert(digest.clone(), digests.checks(expected_blob.as_deref(), expected_diff_id));
+            }
+            archive_layers.files.insert(digest, listing.files.clone());
+        }
+
+        // Archives don't record which digests the image was pulled as
+        let info = ImageInfo {
+            name,
+            tag,
+            id: config.id,
+            repo_tags,
+            repo_digests: Vec::new(),
+            created: config.created,
+            config: config.config,
+            os: config.os,
+            architecture: config.architecture,
+            variant: config.variant,
+            total_size,
+            layers,
+            filesystem: Vec::new(),
+            efficiency: Efficiency::default(),
+            history: history.steps,
+            warnings: history.warnings,
+        };
+        Ok((info, archive_layers))
+    }
+}
+
+/// Where a blob lives inside an OCI archive.
+fn blob_path(descriptor: &Descriptor) -> String {
+    let digest = descriptor.digest();
+    format!("blobs/{}/{}", digest.algorithm().as_ref(), digest.digest())
+}
+
+/// The digest a `blobs/<algorithm>/<hex>` path is named after.
+fn blob_digest(path: &str) -> Option<String> {
+    let (algorithm, hex) = path.strip_prefix("blobs/")?.split_once('/')?;
+    Some(format!("{algorithm}:{hex}"))
+}
+
+/// Resolve a symlink entry's target, relative to the link's directory,
+/// into an archive path.
+fn resolve_symlink(link: &str, target: &Path) -> String {
+    let mut parts: Vec<String> = link.split('/').map(String::from).collect();
+    parts.pop();
+    for component in target.components() {
+        match component {
+            Component::RootDir => parts.clear(),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            Component::Normal(s) => parts.push(s.to_string_lossy().into_owned()),
+            _ => {}
+        }
     }
+    parts.join("/")
+}
+
+/// Archive paths are referenced from manifest.json without a leading `./`.
+fn normalize_entry_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn append_file(builder: &mut tar::Builder<Vec<u8>>, path: &str, data: &[u8]) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+
+    fn append_link(builder: &mut tar::Builder<Vec<u8>>, kind: tar::EntryType, path: &str, target: &str) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_size(0);
+        builder.append_link(&mut header, path, target).unwrap();
+    }
+
+    fn layer_tar(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        append_file(&mut builder, name, data);
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn resolve_symlink_is_relative_to_the_link() {
+        assert_eq!(resolve_symlink("b/layer.tar", Path::new("../a/layer.tar")), "a/layer.tar");
+        assert_eq!(resolve_symlink("b/layer.tar", Path::new("/a/layer.tar")), "a/layer.tar");
+        assert_eq!(resolve_symlink("b/layer.tar", Path::new("other.tar")), "b/other.tar");
+    }
+
+    #[test]
+    fn legacy_save_with_linked_layers() {
+        let base = layer_tar("etc/base", b"base");
+        let config = serde_json::json!({
+            "architecture": "amd64",
+            "os": "linux",
+            "rootfs": {
+                "type": "layers",
+                "diff_ids": ["sha256:aaaa", "sha256:bbbb", "sha256:aaaa", "sha256:aaaa"],
+            },
+        });
+        let manifest = serde_json::json!([{
+            "Config": "cfg.json",
+            "RepoTags": ["linked:latest"],
+            "Layers": ["a/layer.tar", "b/layer.tar", "c/layer.tar", "d/layer.tar"],
+        }]);
+
+        let mut builder = tar::Builder::new(Vec::new());
+        append_file(&mut builder, "a/layer.tar", &base);
+        append_file(&mut builder, "b/layer.tar", &layer_tar("etc/app", b"application"));
+        append_link(&mut builder, tar::EntryType::Symlink, "c/layer.tar", "../a/layer.tar");
+        append_link(&mut builder, tar::EntryType::Link, "d/layer.tar", "c/layer.tar");
+        append_file(&mut builder, "cfg.json", config.to_string().as_bytes());
+        append_file(&mut builder, "manifest.json", manifest.to_string().as_bytes());
+        let archive = builder.into_inner().unwrap();
+
+        let saved = SavedArchive::read(archive.as_slice(), false).unwrap();
+        let (info, layers) = saved.image_info("fallback", None, &Platform::host()).unwrap();
 
-    fn list_files(&mut self, _layer: &LayerInfo) -> Result<Vec<FileEntry>> {
-        // TODO: extract layer.tar from archive and list contents
-        anyhow::bail!("docker archive inspector not yet implemented")
+        assert_eq!(info.name, "linked");
+        let sizes: Vec<u64> = info.layers.iter().map(|l| l.size).collect();
+        assert_eq!(sizes, [4, 11, 4, 4]);
+        assert_eq!(info.total_size, 23);
+        assert_eq!(layers.files["sha256:aaaa"][0].path, Path::new("etc/base"));
     }
 }
@@ -0,0 +1,100 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::reference::did_you_mean_name;
+
+/// A Docker container, as recorded in `<root>/containers/<id>/config.v2.json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DockerContainer {
+    /// Full container ID
+    pub id: String,
+
+    /// Container name, without Docker's leading `/`
+    pub name: String,
+
+    /// Image reference the container was created from (e.g. "nginx:latest")
+    pub image: String,
+
+    /// Image ID (config digest)
+    pub image_id: String,
+
+    pub running: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConfigV2 {
+    #[serde(rename = "ID")]
+    id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    config: ContainerConfig,
+    #[serde(default)]
+    state: ContainerState,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerConfig {
+    #[serde(default)]
+    image: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerState {
+    #[serde(default)]
+    running: bool,
+}
+
+/// Every container under `<storage_root>/containers`.
+fn read_containers(storage_root: &Path) -> Result<Vec<DockerContainer>> {
+    let dir = storage_root.join("containers");
+    let entries = fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+
+    let mut containers = Vec::new();
+    for entry in entries {
+        let path = entry?.path().join("config.v2.json");
+        // Containers being created or removed may lack a config
+        let Ok(data) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let config: ConfigV2 =
+            serde_json::from_str(&data).with_context(|| format!("Failed to parse {}", path.display()))?;
+        containers.push(DockerContainer {
+            id: config.id,
+            name: config.name.trim_start_matches('/').to_string(),
+            image: config.config.image,
+            image_id: config.image,
+            running: config.state.running,
+        });
+    }
+    Ok(containers)
+}
+
+/// Find a container by name, full ID or unique ID prefix, as the Docker CLI does.
+pub fn find(storage_root: &Path, query: &str) -> Result<DockerContainer> {
+    let containers = read_containers(storage_root)?;
+    let name = query.trim_start_matches('/');
+
+    if let Some(container) = containers.iter().find(|c| c.id == query || c.name == name) {
+        return Ok(container.clone());
+    }
+
+    let by_prefix: Vec<&DockerContainer> = containers.iter().filter(|c| c.id.starts_with(query)).collect();
+    match by_prefix.as_slice() {
+        [container] => Ok((*container).clone()),
+        [] => anyhow::bail!(
+            "No such container '{query}' in {}{}",
+            storage_root.display(),
+            did_you_mean_name(name, containers.iter().map(|c| c.name.as_str()))
+        ),
+        _ => anyhow::bail!("Container ID '{query}' is ambiguous ({} containers match)", by_prefix.len()),
+    }
+}
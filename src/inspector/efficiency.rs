@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::{Efficiency, LayerInfo, Waste, WastedPath};
+
+/// How many of the most wasteful paths `analyze` keeps.
+const MAX_OFFENDERS: usize = 100;
+
+/// Work out how much of the layers' size never reaches the final
+/// filesystem. Needs the marks `merge::merge_layers` leaves on the entries.
+///
+/// A copy of a file is wasted when a later layer deletes it, or replaces it:
+/// with a file of the same size it counts as a same-size rewrite (most
+/// likely the same content, e.g. after a `chmod -R`, but layers only
+/// record sizes), otherwise as overwritten.
+pub fn analyze(layers: &[LayerInfo]) -> Efficiency {
+    let sizes: HashMap<(usize, &Path), u64> = layers
+        .iter()
+        .enumerate()
+        .flat_map(|(i, layer)| {
+            layer
+                .files
+                .iter()
+                .filter(|f| !f.is_whiteout)
+                .map(move |f| ((i, f.path.as_path()), f.size))
+        })
+        .collect();
+
+    let mut efficiency = Efficiency::default();
+    // Wasted copies by path, with the size of the largest one
+    let mut by_path: HashMap<&Path, (WastedPath, u64)> = HashMap::new();
+    for file in layers.iter().flat_map(|layer| &layer.files).filter(|f| !f.is_whiteout) {
+        efficiency.total_bytes += file.size;
+        let waste = match (file.deleted_in, file.overwritten_in) {
+            (Some(_), _) => Waste::Deleted,
+            (None, Some(later)) if sizes.get(&(later, file.path.as_path())) == Some(&file.size) => {
+                Waste::SameSizeRewrite
+            }
+            (None, Some(_)) => Waste::Overwritten,
+            (None, None) => continue,
+        };
+        match waste {
+            Waste::Deleted => efficiency.deleted_bytes += file.size,
+            Waste::SameSizeRewrite => efficiency.same_size_rewrite_bytes += file.size,
+            Waste::Overwritten => efficiency.overwritten_bytes += file.size,
+        }
+        if file.size == 0 {
+            continue;
+        }
+
+        let (wasted, largest) = by_path.entry(&file.path).or_insert_with(|| {
+            let wasted = WastedPath {
+                path: file.path.clone(),
+                wasted_bytes: 0,
+                copies: 0,
+                waste,
+            };
+            (wasted, 0)
+        });
+        // Name the path after its biggest wasted copy
+        if file.size > *largest {
+            *largest = file.size;
+            wasted.waste = waste;
+        }
+        wasted.wasted_bytes += file.size;
+        wasted.copies += 1;
+    }
+
+    efficiency.wasted_bytes =
+        efficiency.deleted_bytes + efficiency.same_size_rewrite_bytes + efficiency.overwritten_bytes;
+    efficiency.score = match efficiency.total_bytes {
+        0 => 1.0,
+        total => 1.0 - efficiency.wasted_bytes as f64 / total as f64,
+    };
+
+    let mut offenders: Vec<WastedPath> = by_path.into_values().map(|(wasted, _)| wasted).collect();
+    offenders.sort_by(|a, b| b.wasted_bytes.cmp(&a.wasted_bytes).then_with(|| a.path.cmp(&b.path)));
+    offenders.truncate(MAX_OFFENDERS);
+    efficiency.offenders = offenders;
+    efficiency
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::inspector::{merge, FileEntry, FileKind, LayerChanges};
+
+    fn file(path: &str, size: u64) -> FileEntry {
+        FileEntry {
+            size,
+            is_whiteout: false,
+            ..FileEntry::whiteout(path.into())
+        }
+    }
+
+    fn dir(path: &str) -> FileEntry {
+        FileEntry {
+            kind: FileKind::Dir,
+            ..file(path, 0)
+        }
+    }
+
+    fn merged(files: Vec<Vec<FileEntry>>) -> Vec<LayerInfo> {
+        let mut layers: Vec<LayerInfo> = files
+            .into_iter()
+            .enumerate()
+            .map(|(i, files)| LayerInfo {
+                digest: format!("sha256:{i}"),
+                created_by: None,
+                size: files.iter().map(|f| f.size).sum(),
+                changes: LayerChanges::default(),
+                compression: None,
+                compressed_size: None,
+                files,
+            })
+            .collect();
+        merge::merge_layers(&mut layers);
+        layers
+    }
+
+    struct Case {
+        name: &'static str,
+        layers: Vec<Vec<FileEntry>>,
+        /// (total, overwritten, deleted, same-size rewrite) bytes
+        bytes: (u64, u64, u64, u64),
+        /// (path, wasted bytes, copies, waste), worst first
+        offenders: &'static [(&'static str, u64, usize, Waste)],
+    }
+
+    #[test]
+    fn analyze_cases() {
+        let cases = [
+            Case {
+                name: "empty image",
+                layers: vec![],
+                bytes: (0, 0, 0, 0),
+                offenders: &[],
+            },
+            Case {
+                name: "nothing wasted",
+                layers: vec![vec![file("a", 10)], vec![file("b", 5)]],
+                bytes: (15, 0, 0, 0),
+                offenders: &[],
+            },
+            Case {
+                name: "overwritten with a different size",
+                layers: vec![vec![file("f", 10)], vec![file("f", 4)]],
+                bytes: (14, 10, 0, 0),
+                offenders: &[("f", 10, 1, Waste::Overwritten)],
+            },
+            Case {
+                name: "rewritten with the same size",
+                layers: vec![vec![file("f", 10)], vec![file("f", 10)]],
+                bytes: (20, 0, 0, 10),
+                offenders: &[("f", 10, 1, Waste::SameSizeRewrite)],
+            },
+            Case {
+                name: "deleted by a whiteout and by a file replacing its directory",
+                layers: vec![
+                    vec![dir("d"), file("d/a", 6), file("x", 3)],
+                    vec![FileEntry::whiteout(".wh.x".into()), file("d", 1)],
+                ],
+                bytes: (10, 0, 9, 0),
+                offenders: &[("d/a", 6, 1, Waste::Deleted), ("x", 3, 1, Waste::Deleted)],
+            },
+            Case {
+                name: "copies across several layers",
+                layers: vec![vec![file("f", 10)], vec![file("f", 10)], vec![file("f", 3), file("g", 8)]],
+                bytes: (31, 10, 0, 10),
+                offenders: &[("f", 20, 2, Waste::SameSizeRewrite)],
+            },
+        ];
+
+        for case in cases {
+            let efficiency = analyze(&merged(case.layers));
+            let bytes = (
+                efficiency.total_bytes,
+                efficiency.overwritten_bytes,
+                efficiency.deleted_bytes,
+                efficiency.same_size_rewrite_bytes,
+            );
+            assert_eq!(bytes, case.bytes, "{}: bytes", case.name);
+
+            let (total, overwritten, deleted, rewritten) = case.bytes;
+            let wasted = overwritten + deleted + rewritten;
+            assert_eq!(efficiency.wasted_bytes, wasted, "{}: wasted bytes", case.name);
+            let score = if total == 0 { 1.0 } else { 1.0 - wasted as f64 / total as f64 };
+            assert!((efficiency.score - score).abs() < 1e-9, "{}: score", case.name);
+
+            let offenders: Vec<(PathBuf, u64, usize, Waste)> = efficiency
+                .offenders
+                .into_iter()
+                .map(|o| (o.path, o.wasted_bytes, o.copies, o.waste))
+                .collect();
+            let expected: Vec<(PathBuf, u64, usize, Waste)> = case
+                .offenders
+                .iter()
+                .map(|&(path, wasted, copies, waste)| (path.into(), wasted, copies, waste))
+                .collect();
+            assert_eq!(offenders, expected, "{}: offenders", case.name);
+        }
+    }
+}
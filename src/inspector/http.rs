@@ -0,0 +1,302 @@
+use std::io::{self, Read};
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+/// Maximum size of a single body chunk handed to callers.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A bidirectional byte stream an HTTP request can be sent over
+/// (TCP socket, unix socket, ...).
+pub trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// A minimal HTTP/1.1 response: status, headers and a streamed body.
+pub struct Response {
+    pub status: u16,
+    headers: Vec<(String, String)>,
+    reader: BufReader<Box<dyn Connection>>,
+    body: BodyKind,
+}
+
+enum BodyKind {
+    Length(u64),
+    /// `remaining` bytes of the current chunk are still to be read
+    Chunked { remaining: u64, done: bool },
+    UntilClose { done: bool },
+}
+
+/// Send a single request over `conn`, with `body` unless it is empty, and
+/// read the response head. Each connection carries one request
+/// (`Connection: close`).
+pub async fn send(
+    mut conn: Box<dyn Connection>,
+    method: &str,
+    host: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<Response> {
+    let mut request = format!("{method} {path} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n");
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    if !body.is_empty() {
+        request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    request.push_str("\r\n");
+    conn.write_all(request.as_bytes()).await?;
+    conn.write_all(body).await?;
+    conn.flush().await?;
+
+    let mut reader = BufReader::new(conn);
+    let status_line = read_line(&mut reader).await?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .with_context(|| format!("Malformed HTTP status line: {status_line:?}"))?;
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(&mut reader).await?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let mut response = Response {
+        status,
+        headers,
+        reader,
+        body: BodyKind::UntilClose { done: false },
+    };
+    response.body = if method == "HEAD" || status == 204 || status == 304 {
+        BodyKind::Length(0)
+    } else if response
+        .header("transfer-encoding")
+        .is_some_and(|v| v.eq_ignore_ascii_case("chunked"))
+    {
+        BodyKind::Chunked { remaining: 0, done: false }
+    } else if let Some(len) = response.header("content-length") {
+        BodyKind::Length(len.parse().context("Invalid Content-Length")?)
+    } else {
+        BodyKind::UntilClose { done: false }
+    };
+    Ok(response)
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Look up a header by (case-insensitive) name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Read the next piece of the body, or `None` once it is exhausted.
+    pub async fn chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        match &mut self.body {
+            BodyKind::Length(remaining) => {
+                if *remaining == 0 {
+                    return Ok(None);
+                }
+                let want = (*remaining).min(CHUNK_SIZE as u64) as usize;
+                let mut buf = vec![0u8; want];
+                let n = self.reader.read(&mut buf).await?;
+                if n == 0 {
+                    anyhow::bail!("Connection closed with {remaining} body bytes outstanding");
+                }
+                *remaining -= n as u64;
+                buf.truncate(n);
+                Ok(Some(buf))
+            }
+            BodyKind::Chunked { remaining, done } => {
+                if *done {
+                    return Ok(None);
+                }
+                if *remaining == 0 {
+                    let size_line = read_line(&mut self.reader).await?;
+                    let size_hex = size_line.split(';').next().unwrap_or("").trim();
+                    let size = u64::from_str_radix(size_hex, 16)
+                        .with_context(|| format!("Malformed chunk size: {size_line:?}"))?;
+                    if size == 0 {
+                        // Skip trailers up to the terminating blank line
+                        while !read_line(&mut self.reader).await?.is_empty() {}
+                        *done = true;
+                        return Ok(None);
+                    }
+                    *remaining = size;
+                }
+                // The peer picks chunk sizes, so read big chunks piecewise
+                let want = (*remaining).min(CHUNK_SIZE as u64) as usize;
+                let mut buf = vec![0u8; want];
+                let n = self.reader.read(&mut buf).await?;
+                if n == 0 {
+                    anyhow::bail!("Connection closed with {remaining} chunk bytes outstanding");
+                }
+                *remaining -= n as u64;
+                buf.truncate(n);
+                if *remaining == 0 {
+                    read_line(&mut self.reader).await?;
+                }
+                Ok(Some(buf))
+            }
+            BodyKind::UntilClose { done } => {
+                if *done {
+                    return Ok(None);
+                }
+                let mut buf = vec![0u8; CHUNK_SIZE];
+                let n = self.reader.read(&mut buf).await?;
+                if n == 0 {
+                    *done = true;
+                    return Ok(None);
+                }
+                buf.truncate(n);
+                Ok(Some(buf))
+            }
+        }
+    }
+
+    /// Read the whole body into memory.
+    pub async fn bytes(mut self) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.chunk().await? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// Forward the body into `tx` until it ends or the receiver goes away.
+    pub async fn pipe_to(mut self, tx: mpsc::Sender<Vec<u8>>) -> Result<()> {
+        while let Some(chunk) = self.chunk().await? {
+            if tx.send(chunk).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn read_line<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Result<String> {
+    let mut line = String::new();
+    let n = reader.read_line(&mut line).await?;
+    if n == 0 {
+        anyhow::bail!("Connection closed unexpectedly");
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// A blocking `Read` fed by body chunks from an async task, so sync parsers
+/// (like the tar reader) can consume a download as it streams in.
+pub struct ChannelReader {
+    rx: mpsc::Receiver<Vec<u8>>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl ChannelReader {
+    pub fn new(rx: mpsc::Receiver<Vec<u8>>) -> Self {
+        Self {
+            rx,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.buf = chunk;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Send a GET over a socket pair whose other end reads the request
+    /// and replies `raw`.
+    async fn reply(raw: &'static [u8]) -> Result<Response> {
+        let (client, server) = tokio::net::UnixStream::pair()?;
+        tokio::spawn(async move {
+            let mut server = BufReader::new(server);
+            let mut request = String::new();
+            while server.read_line(&mut request).await? > 2 {
+                request.clear();
+            }
+            server.write_all(raw).await?;
+            // Closing the server's end marks the end of an unframed body
+            server.shutdown().await
+        });
+        send(Box::new(client), "GET", "test", "/", &[("Accept", "*/*")], &[]).await
+    }
+
+    #[tokio::test]
+    async fn content_length_body() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nX-Extra: yes\r\n\r\nhello trailing";
+        let response = reply(raw).await.unwrap();
+        assert!(response.is_success());
+        assert_eq!(response.header("x-extra"), Some("yes"));
+        assert_eq!(response.bytes().await.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn chunked_body_with_extensions_and_trailers() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5;name=value\r\nhello\r\n1\r\n \r\n5\r\nworld\r\n0\r\nDigest: x\r\n\r\n";
+        let response = reply(raw).await.unwrap();
+        assert_eq!(response.bytes().await.unwrap(), b"hello world");
+    }
+
+    #[tokio::test]
+    async fn oversized_chunk_is_read_piecewise() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\nnot that much";
+        let response = reply(raw).await.unwrap();
+        let err = response.bytes().await.unwrap_err();
+        assert!(err.to_string().contains("chunk bytes outstanding"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn body_until_close() {
+        let response = reply(b"HTTP/1.0 404 Not Found\r\n\r\nno such thing").await.unwrap();
+        assert_eq!(response.status, 404);
+        assert!(!response.is_success());
+        assert_eq!(response.bytes().await.unwrap(), b"no such thing");
+    }
+
+    #[tokio::test]
+    async fn truncated_body() {
+        let response = reply(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort").await.unwrap();
+        let err = response.bytes().await.unwrap_err();
+        assert!(err.to_string().contains("5 body bytes outstanding"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn malformed_status_line() {
+        let err = reply(b"garbage\r\n\r\n").await.err().unwrap();
+        assert!(err.to_string().contains("Malformed HTTP status line"), "{err}");
+    }
+}
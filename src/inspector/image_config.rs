@@ -0,0 +1,156 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{HistoryStep, RuntimeConfig};
+
+/// The subset of an image config JSON (Docker or OCI) that peel reads.
+#[derive(Deserialize)]
+pub struct ImageConfig {
+    /// Image ID: the digest of the config JSON itself
+    #[serde(skip)]
+    pub id: String,
+    pub created: Option<String>,
+    #[serde(default, deserialize_with = "super::null_as_default")]
+    pub config: RuntimeConfig,
+    pub os: Option<String>,
+    pub architecture: Option<String>,
+    pub variant: Option<String>,
+    pub rootfs: Rootfs,
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+}
+
+#[derive(Deserialize)]
+pub struct Rootfs {
+    pub diff_ids: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryEntry {
+    pub created: Option<String>,
+    pub created_by: Option<String>,
+    pub author: Option<String>,
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub empty_layer: bool,
+}
+
+/// Dockerfile instructions that only change the config, never the filesystem.
+const METADATA_INSTRUCTIONS: [&str; 13] = [
+    "ARG", "CMD", "ENTRYPOINT", "ENV", "EXPOSE", "HEALTHCHECK", "LABEL", "MAINTAINER", "ONBUILD", "SHELL",
+    "STOPSIGNAL", "USER", "VOLUME",
+];
+
+/// The config's history paired with its layers.
+pub struct History {
+    pub steps: Vec<HistoryStep>,
+    /// Why the pairing may be wrong, if history and layers don't line up
+    pub warnings: Vec<String>,
+}
+
+impl History {
+    /// The command that created layer `index`, if a history step produced it.
+    pub fn created_by(&self, index: usize) -> Option<String> {
+        self.steps
+            .iter()
+            .find(|step| step.layer == Some(index))
+            .and_then(|step| step.created_by.clone())
+    }
+}
+
+impl ImageConfig {
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let mut config: Self = serde_json::from_slice(data)?;
+        config.id = format!("sha256:{:x}", Sha256::digest(data));
+        Ok(config)
+    }
+
+    /// Every history step, with the steps that added a layer paired with
+    /// `rootfs.diff_ids` in order.
+    ///
+    /// Some builders don't flag config-only steps (ENV, LABEL, ...) as
+    /// `empty_layer`; if the counts only agree once those are treated as
+    /// empty, they are. Any remaining disagreement is reported in `warnings`
+    /// and the steps are paired from the base layer up.
+    pub fn history(&self) -> History {
+        let layers = self.rootfs.diff_ids.len();
+        let mut empty: Vec<bool> = self.history.iter().map(|entry| entry.empty_layer).collect();
+        let mut warnings = Vec::new();
+
+        let layer_steps = empty.iter().filter(|e| !**e).count();
+        if layer_steps > layers {
+            let unflagged: Vec<usize> = (0..empty.len())
+                .filter(|&i| !empty[i] && self.history[i].created_by.as_deref().is_some_and(is_metadata_step))
+                .collect();
+            if layer_steps - unflagged.len() == layers {
+                for i in unflagged {
+                    empty[i] = true;
+                }
+            }
+        }
+
+        let layer_steps = empty.iter().filter(|e| !**e).count();
+        if !self.history.is_empty() && layer_steps != layers {
+            warnings.push(format!(
+                "Image history has {layer_steps} step(s) that add a layer, but the image has {layers} layer(s); \
+                 layer commands may be mislabeled"
+            ));
+        }
+
+        let mut next_layer = 0;
+        let steps = self
+            .history
+            .iter()
+            .zip(empty)
+            .map(|(entry, empty_layer)| {
+                let layer = (!empty_layer && next_layer < layers).then(|| {
+                    next_layer += 1;
+                    next_layer - 1
+                });
+                HistoryStep {
+                    created: entry.created.clone(),
+                    created_by: entry.created_by.clone(),
+                    author: entry.author.clone(),
+                    comment: entry.comment.clone(),
+                    empty_layer,
+                    layer,
+                }
+            })
+            .collect();
+        History { steps, warnings }
+    }
+
+    /// Compute chain IDs from diff IDs.
+    ///
+    /// chain\[0\] = diff\[0\]
+    /// chain\[i\] = sha256(chain\[i-1\] + " " + diff\[i\])
+    pub fn chain_ids(&self) -> Vec<String> {
+        let diff_ids = &self.rootfs.diff_ids;
+        let mut chain_ids: Vec<String> = Vec::with_capacity(diff_ids.len());
+        for (i, diff_id) in diff_ids.iter().enumerate() {
+            if i == 0 {
+                chain_ids.push(diff_id.clone());
+            } else {
+                chain_ids.push(chain_id(&chain_ids[i - 1], diff_id));
+            }
+        }
+        chain_ids
+    }
+}
+
+/// Whether a history command is a config-only instruction, in either the
+/// classic builder's form (`/bin/sh -c #(nop)  ENV ...`) or BuildKit's.
+fn is_metadata_step(created_by: &str) -> bool {
+    let command = created_by.trim_start_matches("/bin/sh -c").trim_start();
+    let command = command.trim_start_matches("#(nop)").trim_start();
+    command
+        .split_whitespace()
+        .next()
+        .is_some_and(|instruction| METADATA_INSTRUCTIONS.contains(&instruction))
+}
+
+/// Chain ID of a layer stacked on the layer with chain ID `parent`.
+pub fn chain_id(parent: &str, diff_id: &str) -> String {
+    let input = format!("{parent} {diff_id}");
+    format!("sha256:{:x}", Sha256::digest(input.as_bytes()))
+}
@@ -0,0 +1,173 @@
+use std::io::{self, Read};
+
+use anyhow::{Context, Result};
+use flate2::read::MultiGzDecoder;
+use sha2::{Digest, Sha256};
+use xz2::read::XzDecoder;
+
+use super::{layer_tar, Compression, DigestCheck, FileEntry};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// A layer blob's file listing, plus how the blob was stored.
+pub struct LayerListing {
+    pub files: Vec<FileEntry>,
+    pub compression: Compression,
+    /// Bytes read from the blob, i.e. its size as stored
+    pub compressed_size: u64,
+    /// The blob's digests, if it was listed with hashing on
+    pub digests: Option<LayerDigests>,
+}
+
+/// SHA-256 digests of a layer blob, computed while listing it.
+#[derive(Clone)]
+pub struct LayerDigests {
+    /// Digest of the blob as stored, as referenced from the manifest
+    pub blob: String,
+    /// Digest of the uncompressed tar, as listed in the config's `diff_ids`
+    pub diff_id: String,
+}
+
+impl LayerDigests {
+    /// Compare against the digests recorded for the layer. A blob digest
+    /// is only checked where the source addresses blobs by digest.
+    pub fn checks(&self, expected_blob: Option<&str>, expected_diff_id: Option<&str>) -> Vec<DigestCheck> {
+        let mut checks = vec![DigestCheck::new(
+            "diff ID",
+            expected_diff_id.unwrap_or("(missing from config)"),
+            &self.diff_id,
+        )];
+        if let Some(expected) = expected_blob {
+            checks.push(DigestCheck::new("blob", expected, &self.blob));
+        }
+        checks
+    }
+}
+
+/// Tell the compression of a layer blob from its first bytes. Returns
+/// `None` if it is neither compressed nor the start of a tar stream.
+/// Needs at least 512 bytes to recognise an uncompressed tar.
+pub fn sniff(head: &[u8]) -> Option<Compression> {
+    if head.starts_with(GZIP_MAGIC) {
+        Some(Compression::Gzip)
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Some(Compression::Zstd)
+    } else if layer_tar::looks_like_tar(head) {
+        Some(Compression::Uncompressed)
+    } else {
+        None
+    }
+}
+
+/// List the files in a layer blob, whatever its compression. Media types
+/// aren't trusted: Docker labels uncompressed layers `tar.gzip` in some
+/// archives, so the format is taken from the magic bytes instead.
+/// Multi-member gzip and multi-frame zstd (including skippable frames, as
+/// written by eStargz and zstd:chunked) are read to the end.
+///
+/// With `hash`, the blob and the uncompressed tar are hashed on the way
+/// through, for `--verify`.
+pub fn list<R: Read>(reader: R, hash: bool) -> Result<LayerListing> {
+    let mut counted = CountingReader::new(reader, hash);
+    let mut head = Vec::with_capacity(512);
+    (&mut counted).take(512).read_to_end(&mut head)?;
+    let compression = sniff(&head).context("Layer blob is not a tar, gzip or zstd stream")?;
+
+    let mut stream = head.as_slice().chain(&mut counted);
+    let (files, diff_id) = match compression {
+        Compression::Gzip => list_tar(MultiGzDecoder::new(&mut stream), hash)?,
+        Compression::Zstd => {
+            let decoder = zstd::stream::read::Decoder::new(&mut stream)
+                .context("Failed to start zstd decoder")?;
+            list_tar(decoder, hash)?
+        }
+        Compression::Uncompressed => list_tar(&mut stream, hash)?,
+    };
+
+    // The tar reader stops at the end-of-archive marker; read whatever
+    // follows (padding, TOCs, footers) so the stored size is exact
+    io::copy(&mut stream, &mut io::sink())?;
+
+    let compressed_size = counted.count;
+    let digests = counted
+        .digest()
+        .zip(diff_id)
+        .map(|(blob, diff_id)| LayerDigests { blob, diff_id });
+    Ok(LayerListing {
+        files,
+        compression,
+        compressed_size,
+        digests,
+    })
+}
+
+/// List an uncompressed tar stream. When hashing, the stream is read to
+/// its very end, since the diff ID covers any trailing padding too.
+fn list_tar<R: Read>(reader: R, hash: bool) -> Result<(Vec<FileEntry>, Option<String>)> {
+    let mut tar = CountingReader::new(reader, hash);
+    let files = layer_tar::list_entries(&mut tar)?;
+    if hash {
+        io::copy(&mut tar, &mut io::sink())?;
+    }
+    Ok((files, tar.digest()))
+}
+
+/// Whether a stream starting with `head` looks like a whole image archive:
+/// a tar, possibly wrapped in gzip, zstd or xz (`docker save | xz`).
+pub fn is_archive(head: &[u8]) -> bool {
+    head.starts_with(XZ_MAGIC) || sniff(head).is_some()
+}
+
+/// Undo whatever compression wraps a stream, going by its magic bytes.
+/// Uncompressed input is passed through untouched.
+pub fn decompress<'a, R: Read + 'a>(mut reader: R) -> Result<Box<dyn Read + 'a>> {
+    let mut head = Vec::with_capacity(512);
+    (&mut reader).take(512).read_to_end(&mut head)?;
+    let is_xz = head.starts_with(XZ_MAGIC);
+    let compression = sniff(&head);
+
+    let stream = io::Cursor::new(head).chain(reader);
+    Ok(match compression {
+        _ if is_xz => Box::new(XzDecoder::new_multi_decoder(stream)),
+        Some(Compression::Gzip) => Box::new(MultiGzDecoder::new(stream)),
+        Some(Compression::Zstd) => {
+            Box::new(zstd::stream::read::Decoder::new(stream).context("Failed to start zstd decoder")?)
+        }
+        _ => Box::new(stream),
+    })
+}
+
+/// Counts, and optionally hashes, bytes pulled from the underlying reader.
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+    hasher: Option<Sha256>,
+}
+
+impl<R> CountingReader<R> {
+    fn new(inner: R, hash: bool) -> Self {
+        Self {
+            inner,
+            count: 0,
+            hasher: hash.then(Sha256::new),
+        }
+    }
+
+    /// `sha256:<hex>` of everything read so far, if hashing.
+    fn digest(&mut self) -> Option<String> {
+        self.hasher.take().map(|h| format!("sha256:{:x}", h.finalize()))
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
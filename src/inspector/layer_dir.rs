@@ -0,0 +1,223 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use super::{xattr_value, FileEntry, FileKind, OPAQUE_WHITEOUT};
+
+/// xattrs overlayfs marks an opaque directory with, for the kernel's own
+/// upper dirs and for unprivileged (`userxattr`) mounts.
+const OPAQUE_XATTRS: [&str; 2] = ["trusted.overlay.opaque", "user.overlay.opaque"];
+
+/// xattrs marking a zero-size regular file as a whiteout (overlayfs 6.7+).
+const WHITEOUT_XATTRS: [&str; 2] = ["trusted.overlay.whiteout", "user.overlay.whiteout"];
+
+/// Prefixes of the xattrs overlayfs keeps its own bookkeeping in; they
+/// describe the upper dir, not the layer's contents.
+const OVERLAY_XATTR_PREFIXES: [&str; 2] = ["trusted.overlay.", "user.overlay."];
+
+/// Inodes already listed under another name, keyed by (device, inode).
+pub type SeenInodes = HashMap<(u64, u64), PathBuf>;
+
+/// List the entries in an unpacked layer directory (e.g. an overlay `diff`
+/// dir), with paths relative to `dir`.
+///
+/// overlayfs records deletions as 0:0 character devices and replaced
+/// directories with an "opaque" xattr; both are reported the way the layer
+/// tarball stores them, as a `.wh.<name>` whiteout and a `.wh..wh..opq`
+/// entry inside the directory.
+pub fn list_entries(dir: &Path) -> Result<Vec<FileEntry>> {
+    let mut entries = Vec::new();
+    walk_layer_dir(dir, dir, &mut SeenInodes::new(), &mut entries)?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+fn walk_layer_dir(dir: &Path, base: &Path, seen: &mut SeenInodes, entries: &mut Vec<FileEntry>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        let relative = path.strip_prefix(base).unwrap_or(&path).to_path_buf();
+
+        if is_overlay_whiteout(&path, &metadata) {
+            let mut whiteout = OsString::from(".wh.");
+            whiteout.push(entry.file_name());
+            entries.push(whiteout_like(relative.with_file_name(whiteout), &metadata));
+            continue;
+        }
+
+        entries.push(entry_from_disk(&path, relative.clone(), &metadata, seen)?);
+        if metadata.is_dir() {
+            if has_flag(&path, &OPAQUE_XATTRS) {
+                entries.push(whiteout_like(relative.join(OPAQUE_WHITEOUT), &metadata));
+            }
+            walk_layer_dir(&path, base, seen, entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// Describe a file on disk the way a layer tar would record it. Files with
+/// several names are listed in full once; later names in `seen` become
+/// hardlinks to the first.
+pub fn entry_from_disk(path: &Path, relative: PathBuf, metadata: &Metadata, seen: &mut SeenInodes) -> Result<FileEntry> {
+    let file_type = metadata.file_type();
+    let mut kind = if file_type.is_dir() {
+        FileKind::Dir
+    } else if file_type.is_symlink() {
+        FileKind::Symlink
+    } else if file_type.is_char_device() {
+        FileKind::CharDevice
+    } else if file_type.is_block_device() {
+        FileKind::BlockDevice
+    } else if file_type.is_fifo() {
+        FileKind::Fifo
+    } else if file_type.is_socket() {
+        FileKind::Socket
+    } else {
+        FileKind::File
+    };
+
+    let mut link_target = None;
+    if kind == FileKind::Symlink {
+        link_target = Some(fs::read_link(path).with_context(|| format!("Failed to read link {}", path.display()))?);
+    } else if kind != FileKind::Dir && metadata.nlink() > 1 {
+        match seen.get(&(metadata.dev(), metadata.ino())) {
+            Some(first) => {
+                kind = FileKind::Hardlink;
+                link_target = Some(first.clone());
+            }
+            None => {
+                seen.insert((metadata.dev(), metadata.ino()), relative.clone());
+            }
+        }
+    }
+
+    let is_whiteout = relative
+        .file_name()
+        .is_some_and(|n| n.to_string_lossy().starts_with(".wh."));
+    Ok(FileEntry {
+        size: if is_whiteout || kind != FileKind::File { 0 } else { metadata.len() },
+        is_whiteout,
+        kind,
+        mode: metadata.mode() & 0o7777,
+        uid: metadata.uid().into(),
+        gid: metadata.gid().into(),
+        mtime: metadata.mtime(),
+        link_target,
+        xattrs: read_xattrs(path),
+        change: None,
+        overwritten_in: None,
+        deleted_in: None,
+        path: relative,
+    })
+}
+
+/// A whiteout carrying the ownership and mtime of the overlayfs marker it
+/// was translated from.
+fn whiteout_like(path: PathBuf, metadata: &Metadata) -> FileEntry {
+    FileEntry {
+        uid: metadata.uid().into(),
+        gid: metadata.gid().into(),
+        mtime: metadata.mtime(),
+        ..FileEntry::whiteout(path)
+    }
+}
+
+/// Whether a non-directory is overlayfs's marker for a deleted path: a 0:0
+/// character device, or an empty file flagged with a whiteout xattr.
+fn is_overlay_whiteout(path: &Path, metadata: &Metadata) -> bool {
+    if metadata.file_type().is_char_device() {
+        return metadata.rdev() == 0;
+    }
+    metadata.is_file() && metadata.len() == 0 && has_flag(path, &WHITEOUT_XATTRS)
+}
+
+/// Whether any of the given xattrs is set to "y" on `path`. Unreadable
+/// xattrs (e.g. `trusted.*` without CAP_SYS_ADMIN) count as unset.
+fn has_flag(path: &Path, names: &[&str]) -> bool {
+    names
+        .iter()
+        .any(|name| matches!(xattr::get(path, name), Ok(Some(value)) if value == b"y"))
+}
+
+/// The xattrs set on `path` itself (not a symlink's target), minus
+/// overlayfs's own. Filesystems without xattr support have none.
+fn read_xattrs(path: &Path) -> BTreeMap<String, String> {
+    let Ok(names) = xattr::list(path) else {
+        return BTreeMap::new();
+    };
+    names
+        .filter_map(|name| {
+            let name = name.into_string().ok()?;
+            if OVERLAY_XATTR_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+                return None;
+            }
+            let value = xattr::get(path, &name).ok()??;
+            Some((name, xattr_value(&value)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("peel-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn xattr_markers_become_tar_whiteouts() {
+        let tmp = TempDir::new("layer-dir-xattrs");
+        let root = &tmp.0;
+        fs::create_dir_all(root.join("etc/conf.d")).unwrap();
+        fs::write(root.join("etc/hosts"), "127.0.0.1 localhost\n").unwrap();
+        fs::write(root.join("etc/removed"), "").unwrap();
+        // Filesystems without user xattrs (e.g. tmpfs on older kernels) can't mark either
+        if xattr::set(root.join("etc/removed"), "user.overlay.whiteout", b"y").is_err()
+            || xattr::set(root.join("etc/conf.d"), "user.overlay.opaque", b"y").is_err()
+        {
+            eprintln!("skipping: user xattrs are not supported in {}", root.display());
+            return;
+        }
+
+        let entries = list_entries(root).unwrap();
+        let paths: Vec<&Path> = entries.iter().map(|e| e.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            [
+                Path::new("etc"),
+                Path::new("etc/.wh.removed"),
+                Path::new("etc/conf.d"),
+                Path::new("etc/conf.d/.wh..wh..opq"),
+                Path::new("etc/hosts"),
+            ]
+        );
+        let whiteout = &entries[1];
+        assert!(whiteout.is_whiteout);
+        assert_eq!(whiteout.size, 0);
+        assert!(entries[3].is_whiteout);
+        // overlayfs's own markers aren't part of the layer's contents
+        assert!(entries[2].xattrs.is_empty(), "{:?}", entries[2].xattrs);
+        assert!(!entries[4].is_whiteout);
+        assert_eq!(entries[4].size, 20);
+    }
+}
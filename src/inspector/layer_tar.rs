@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tar::EntryType;
+
+use super::{xattr_value, FileEntry, FileKind};
+
+/// Check whether a 512-byte block looks like the start of a tar stream.
+/// An all-zero block is the end-of-archive marker of an empty layer.
+pub fn looks_like_tar(block: &[u8]) -> bool {
+    if block.len() < 512 {
+        return false;
+    }
+    &block[257..262] == b"ustar" || block[..512].iter().all(|&b| b == 0)
+}
+
+/// List the entries in an uncompressed layer tarball, with the metadata
+/// from their headers (PAX records override the ustar fields they extend).
+pub fn list_entries<R: Read>(reader: R) -> Result<Vec<FileEntry>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+
+    for entry in archive.entries().context("Failed to read layer tar")? {
+        let mut entry = entry.context("Failed to read layer tar entry")?;
+        let path = normalize_path(&entry.path()?);
+        let header = entry.header();
+        // The layer's root (`./`) isn't a file in it
+        let Some(kind) = kind_of(header.entry_type()).filter(|_| !path.as_os_str().is_empty()) else {
+            continue;
+        };
+        let is_whiteout = path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with(".wh."));
+        let size = if is_whiteout || kind != FileKind::File {
+            0
+        } else {
+            entry.size()
+        };
+        let link_target = match kind {
+            FileKind::Symlink => entry.link_name()?.map(|p| p.into_owned()),
+            FileKind::Hardlink => entry.link_name()?.map(|p| normalize_path(&p)),
+            _ => None,
+        };
+
+        let mut file = FileEntry {
+            path,
+            size,
+            is_whiteout,
+            kind,
+            mode: header.mode().unwrap_or(0) & 0o7777,
+            uid: header.uid().unwrap_or(0),
+            gid: header.gid().unwrap_or(0),
+            mtime: header.mtime().map_or(0, |t| t as i64),
+            link_target,
+            xattrs: BTreeMap::new(),
+            change: None,
+            overwritten_in: None,
+            deleted_in: None,
+        };
+        if let Some(extensions) = entry.pax_extensions()? {
+            for extension in extensions {
+                let extension = extension.context("Invalid PAX header in layer tar")?;
+                let (Ok(key), value) = (extension.key(), extension.value_bytes()) else {
+                    continue;
+                };
+                apply_pax(&mut file, key, value);
+            }
+        }
+        entries.push(file);
+    }
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// What a tar entry puts on the filesystem, or `None` for entries that are
+/// only archive bookkeeping (`pax_global_header`, GNU long names, ...).
+fn kind_of(entry_type: EntryType) -> Option<FileKind> {
+    Some(match entry_type {
+        EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => FileKind::File,
+        EntryType::Directory => FileKind::Dir,
+        EntryType::Symlink => FileKind::Symlink,
+        EntryType::Link => FileKind::Hardlink,
+        EntryType::Char => FileKind::CharDevice,
+        EntryType::Block => FileKind::BlockDevice,
+        EntryType::Fifo => FileKind::Fifo,
+        _ => return None,
+    })
+}
+
+/// Apply a PAX record that carries metadata the ustar header can't hold
+/// (large IDs, sub-second mtimes) or has no field for (xattrs).
+fn apply_pax(file: &mut FileEntry, key: &str, value: &[u8]) {
+    let text = || std::str::from_utf8(value).ok();
+    match key {
+        "uid" => file.uid = text().and_then(|v| v.parse().ok()).unwrap_or(file.uid),
+        "gid" => file.gid = text().and_then(|v| v.parse().ok()).unwrap_or(file.gid),
+        "mtime" => {
+            // Seconds with an optional fraction, e.g. "1700000000.123"
+            let seconds = text().and_then(|v| v.split('.').next()?.parse().ok());
+            file.mtime = seconds.unwrap_or(file.mtime);
+        }
+        _ => {
+            if let Some(name) = key.strip_prefix("SCHILY.xattr.") {
+                file.xattrs.insert(name.to_string(), xattr_value(value));
+            }
+        }
+    }
+}
+
+/// Strip leading `./` and `/` so tar paths match the relative paths
+/// produced by walking a layer directory.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tar::Header;
+
+    use super::*;
+
+    fn append(tar: &mut tar::Builder<Vec<u8>>, path: &str, entry_type: EntryType, data: &[u8]) {
+        let mut header = Header::new_ustar();
+        header.set_entry_type(entry_type);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o755);
+        tar.append_data(&mut header, path, data).unwrap();
+    }
+
+    #[test]
+    fn root_and_bookkeeping_entries_are_not_files() {
+        let mut tar = tar::Builder::new(Vec::new());
+        append(&mut tar, "pax_global_header", EntryType::XGlobalHeader, b"20 comment=built\n");
+        append(&mut tar, "./", EntryType::Directory, b"");
+        append(&mut tar, "./etc/", EntryType::Directory, b"");
+        append(&mut tar, "./etc/hostname", EntryType::Regular, b"peel\n");
+        let tar = tar.into_inner().unwrap();
+
+        let entries = list_entries(tar.as_slice()).unwrap();
+        let listed: Vec<(&str, FileKind, u64)> = entries
+            .iter()
+            .map(|e| (e.path.to_str().unwrap(), e.kind, e.size))
+            .collect();
+        assert_eq!(listed, [("etc", FileKind::Dir, 0), ("etc/hostname", FileKind::File, 5)]);
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use super::image_config::{self, ImageConfig};
+use super::reference::{did_you_mean, id_prefix, Reference};
+use super::{
+    split_repo_tag, tar_split, DigestCheck, Efficiency, FileEntry, ImageInfo, LayerChanges, LayerInfo, StoredImage,
+};
+
+/// Docker's graph-driver image metadata under `<root>/image/<driver>/`:
+/// `repositories.json`, the image config store and the layer database.
+/// Shared by the overlay2, vfs and btrfs inspectors, which only differ in
+/// where the layer contents live.
+pub struct LayerDb {
+    image_dir: PathBuf,
+}
+
+/// Where a container's read-write layer lives in the graph driver.
+pub struct ContainerMount {
+    /// Driver directory holding the container's changes
+    pub mount_id: String,
+    /// Driver directory of the init layer Docker puts between the
+    /// container and its image (`/etc/hosts` and friends), if any
+    pub init_id: Option<String>,
+    /// Chain ID of the image's top layer
+    pub parent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Repositories {
+    #[serde(rename = "Repositories")]
+    repositories: HashMap<String, HashMap<String, String>>,
+}
+
+impl LayerDb {
+    pub fn new(storage_root: &Path, driver: &str) -> Self {
+        Self {
+            image_dir: storage_root.join("image").join(driver),
+        }
+    }
+
+    fn read_repositories(&self) -> Result<Repositories> {
+        let repos_path = self.image_dir.join("repositories.json");
+        let repos_data = fs::read_to_string(&repos_path)
+            .with_context(|| format!("Failed to read {}", repos_path.display()))?;
+        serde_json::from_str(&repos_data)
+            .with_context(|| format!("Failed to parse {}", repos_path.display()))
+    }
+
+    /// Look an image up by reference (`nginx`, `docker.io/library/nginx:1.25`,
+    /// `nginx@sha256:...`) in repositories.json, or by image ID (prefix).
+    /// Returns (name, tag, config_digest_hex).
+    pub fn resolve_image(&self, image: &str) -> Result<(String, Option<String>, String)> {
+        let repos = self.read_repositories()?;
+        let reference = Reference::parse(image);
+
+        if let Ok(reference) = &reference
+            && let Some(config_digest) = repos
+                .repositories
+                .get(&reference.familiar_name())
+                .and_then(|refs| refs.get(&reference.familiar_key()))
+        {
+            let digest_hex = config_digest
+                .strip_prefix("sha256:")
+                .unwrap_or(config_digest);
+            let tag = reference.tag_or_default().map(String::from);
+            return Ok((reference.familiar_name(), tag, digest_hex.to_string()));
+        }
+
+        if let Some(prefix) = id_prefix(image)
+            && let Some(digest_hex) = self.find_image_id(prefix)?
+        {
+            // Show the image under one of its tags, if it has any
+            let config_digest = format!("sha256:{digest_hex}");
+            let repo_tag = repos
+                .repositories
+                .values()
+                .flat_map(|refs| refs.iter())
+                .filter(|(key, id)| **id == config_digest && !key.contains('@'))
+                .map(|(key, _)| key)
+                .min();
+            let (name, tag) = match repo_tag {
+                Some(repo_tag) => split_repo_tag(repo_tag),
+                None => (image.to_string(), None),
+            };
+            return Ok((name, tag, digest_hex));
+        }
+
+        // An invalid reference that isn't an ID either: report why
+        reference?;
+        let known = repos.repositories.values().flat_map(|refs| refs.keys()).map(String::as_str);
+        anyhow::bail!(
+            "Image '{image}' not found in repositories.json{}",
+            did_you_mean(image, known)
+        )
+    }
+
+    /// Find the image whose ID (config digest) starts with `prefix`.
+    fn find_image_id(&self, prefix: &str) -> Result<Option<String>> {
+        let content_dir = self.image_dir.join("imagedb/content/sha256");
+        let entries = fs::read_dir(&content_dir)
+            .with_context(|| format!("Failed to read {}", content_dir.display()))?;
+        let mut matches = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if name.starts_with(prefix) {
+                matches.push(name);
+            }
+        }
+        match matches.len() {
+            0 | 1 => Ok(matches.pop()),
+            _ => anyhow::bail!("Image ID '{prefix}' is ambiguous ({} images match)", matches.len()),
+        }
+    }
+
+    pub fn read_image_config(&self, digest_hex: &str) -> Result<ImageConfig> {
+        let config_path = self
+            .image_dir
+            .join("imagedb/content/sha256")
+            .join(digest_hex);
+        let config_data = fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read image config {}", config_path.display()))?;
+        ImageConfig::parse(config_data.as_bytes()).context("Failed to parse image config")
+    }
+
+    pub fn get_cache_id(&self, chain_id: &str) -> Result<String> {
+        let chain_hex = chain_id.strip_prefix("sha256:").unwrap_or(chain_id);
+        let path = self
+            .image_dir
+            .join("layerdb/sha256")
+            .join(chain_hex)
+            .join("cache-id");
+        let cache_id = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read cache-id for chain {chain_id}"))?;
+        Ok(cache_id.trim().to_string())
+    }
+
+    pub fn get_layer_size(&self, chain_id: &str) -> Result<u64> {
+        let chain_hex = chain_id.strip_prefix("sha256:").unwrap_or(chain_id);
+        let path = self
+            .image_dir
+            .join("layerdb/sha256")
+            .join(chain_hex)
+            .join("size");
+        let size_str = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read size for chain {chain_id}"))?;
+        size_str.trim().parse::<u64>().context("Failed to parse layer size")
+    }
+
+    /// Chain ID of a layer's parent, or `None` for a base layer.
+    pub fn get_parent(&self, chain_id: &str) -> Result<Option<String>> {
+        let chain_hex = chain_id.strip_prefix("sha256:").unwrap_or(chain_id);
+        let path = self
+            .image_dir
+            .join("layerdb/sha256")
+            .join(chain_hex)
+            .join("parent");
+        match fs::read_to_string(&path) {
+            Ok(parent) => Ok(Some(parent.trim().to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read parent for chain {chain_id}")),
+        }
+    }
+
+    /// Check a layer against the layer database. Its chain ID, computed
+    /// from the image config's diff IDs, must match the one recomputed
+    /// from the layer's `diff` and `parent` files; and the tar rebuilt from
+    /// `tar-split.json.gz` and the files in `dir` must hash to that diff ID.
+    pub fn verify_layer(&self, chain_id: &str, dir: &Path) -> Result<Vec<DigestCheck>> {
+        let chain_hex = chain_id.strip_prefix("sha256:").unwrap_or(chain_id);
+        let layer_dir = self.image_dir.join("layerdb/sha256").join(chain_hex);
+        let diff_id = fs::read_to_string(layer_dir.join("diff"))
+            .with_context(|| format!("Failed to read diff for chain {chain_id}"))?;
+        let diff_id = diff_id.trim();
+
+        let stored_chain_id = match self.get_parent(chain_id)? {
+            Some(parent) => image_config::chain_id(&parent, diff_id),
+            None => diff_id.to_string(),
+        };
+        let tar_digest = tar_split::diff_id(&layer_dir.join("tar-split.json.gz"), dir)?;
+        Ok(vec![
+            DigestCheck::new("chain ID", chain_id, stored_chain_id),
+            DigestCheck::new("diff ID", diff_id, tar_digest),
+        ])
+    }
+
+    /// Check that an image's config still hashes to the ID it is stored
+    /// under in `imagedb/content/sha256`.
+    pub fn verify_config(&self, image: &str) -> Result<Vec<DigestCheck>> {
+        let (_, _, digest_hex) = self.resolve_image(image)?;
+        let config = self.read_image_config(&digest_hex)?;
+        Ok(vec![DigestCheck::new("config", format!("sha256:{digest_hex}"), config.id)])
+    }
+
+    /// A container's read-write layer, from `layerdb/mounts/<id>`.
+    pub fn get_mount(&self, container_id: &str) -> Result<ContainerMount> {
+        let mount_dir = self.image_dir.join("layerdb/mounts").join(container_id);
+        let read_id = |file: &str| {
+            fs::read_to_string(mount_dir.join(file))
+                .ok()
+                .map(|id| id.trim().to_string())
+                .filter(|id| !id.is_empty())
+        };
+        let mount_id = read_id("mount-id")
+            .with_context(|| format!("No mount-id for container {container_id} in {}", mount_dir.display()))?;
+        Ok(ContainerMount {
+            mount_id,
+            init_id: read_id("init-id"),
+            parent: read_id("parent"),
+        })
+    }
+
+    /// Every image in the image store, tagged or not, with its layers
+    /// keyed by chain ID and sized by the files `layer_files` lists for
+    /// them. Docker's own `size` counts symlinks too, so it isn't used.
+    pub fn list_images(
+        &self,
+        mut layer_files: impl FnMut(&str) -> Result<Vec<FileEntry>>,
+    ) -> Result<Vec<StoredImage>> {
+        let repos = self.read_repositories()?;
+        let mut tags: HashMap<&str, Vec<String>> = HashMap::new();
+        for (key, id) in repos.repositories.values().flat_map(|refs| refs.iter()) {
+            if !key.contains('@') {
+                tags.entry(id.as_str()).or_default().push(key.clone());
+            }
+        }
+
+        let content_dir = self.image_dir.join("imagedb/content/sha256");
+        let entries = fs::read_dir(&content_dir)
+            .with_context(|| format!("Failed to read {}", content_dir.display()))?;
+        // Sizes by chain ID, for layers several images share
+        let mut sizes: HashMap<String, u64> = HashMap::new();
+        let mut images = Vec::new();
+        for entry in entries {
+            let digest_hex = entry?.file_name().to_string_lossy().into_owned();
+            let config = self.read_image_config(&digest_hex)?;
+            let id = format!("sha256:{digest_hex}");
+            let mut layers = Vec::new();
+            for chain_id in config.chain_ids() {
+                let size = match sizes.get(&chain_id) {
+                    Some(&size) => size,
+                    None => {
+                        let size = layer_files(&chain_id)?.iter().map(|f| f.size).sum();
+                        sizes.insert(chain_id.clone(), size);
+                        size
+                    }
+                };
+                layers.push((chain_id, size));
+            }
+            let mut image_tags = tags.remove(id.as_str()).unwrap_or_default();
+            image_tags.sort();
+            images.push(StoredImage {
+                id,
+                tags: image_tags,
+                layers,
+            });
+        }
+        Ok(images)
+    }
+
+    /// Resolve an image and describe its layers, keyed by chain ID.
+    pub fn inspect(&self, image: &str) -> Result<ImageInfo> {
+        let (name, tag, digest_hex) = self.resolve_image(image)?;
+        let config = self.read_image_config(&digest_hex)?;
+        let chain_ids = config.chain_ids();
+        let history = config.history();
+
+        let mut layers = Vec::with_capacity(chain_ids.len());
+        let mut total_size = 0u64;
+
+        for (i, chain_id) in chain_ids.iter().enumerate() {
+            let size = self.get_layer_size(chain_id).unwrap_or(0);
+            total_size += size;
+            layers.push(LayerInfo {
+                digest: chain_id.clone(),
+                created_by: history.created_by(i),
+                size,
+                changes: LayerChanges::default(),
+                compression: None,
+                compressed_size: None,
+                files: Vec::new(),
+            });
+        }
+
+        // Every key in repositories.json naming this image: tags, and
+        // `name@sha256:...` for the manifests it was pulled as
+        let repos = self.read_repositories()?;
+        let (mut repo_digests, mut repo_tags): (Vec<String>, Vec<String>) = repos
+            .repositories
+            .values()
+            .flat_map(|refs| refs.iter())
+            .filter(|(_, id)| **id == config.id)
+            .map(|(key, _)| key.clone())
+            .partition(|key| key.contains('@'));
+        repo_tags.sort();
+        repo_digests.sort();
+
+        Ok(ImageInfo {
+            name,
+            tag,
+            id: config.id,
+            repo_tags,
+            repo_digests,
+            created: config.created,
+            config: config.config,
+            os: config.os,
+            architecture: config.architecture,
+            variant: config.variant,
+            total_size,
+            layers,
+            filesystem: Vec::new(),
+            efficiency: Efficiency::default(),
+            history: history.steps,
+            warnings: history.warnings,
+        })
+    }
+}
+
+/// Describe a container's read-write layer from its files.
+pub fn container_layer(container_id: &str, files: Vec<FileEntry>) -> LayerInfo {
+    LayerInfo {
+        digest: container_id.to_string(),
+        created_by: None,
+        size: files.iter().map(|f| f.size).sum(),
+        changes: LayerChanges::default(),
+        compression: None,
+        compressed_size: None,
+        files,
+    }
+}
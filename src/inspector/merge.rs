@@ -0,0 +1,303 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+
+use super::{Change, FileKind, LayerChanges, LayerInfo, MergedEntry};
+
+/// Where the entry visible at a path comes from: (layer index, index into
+/// that layer's files).
+type Source = (usize, usize);
+
+/// Apply the layers in order, the way a runtime unpacks them, and return
+/// the resulting root filesystem sorted by path. Each layer's whiteouts
+/// delete from the layers below it (an opaque directory hides everything
+/// they had under it), then its entries replace whatever was at their
+/// paths; a non-directory replacing a directory removes its contents too.
+///
+/// Along the way every entry gets its `change` against the layers below,
+/// every layer its `changes` totals, and every entry that a later layer
+/// replaces or deletes gets `overwritten_in` or `deleted_in` set to that
+/// layer's index.
+pub fn merge_layers(layers: &mut [LayerInfo]) -> Vec<MergedEntry> {
+    let mut visible: BTreeMap<PathBuf, Source> = BTreeMap::new();
+
+    for i in 0..layers.len() {
+        let (lower, upper) = layers.split_at_mut(i);
+        let layer = &mut upper[0];
+        let mut changes: Vec<Option<Change>> = vec![None; layer.files.len()];
+        let mut bytes_removed = 0;
+
+        for (index, file) in layer.files.iter().enumerate().filter(|(_, f)| f.is_whiteout) {
+            let removed = if file.is_opaque() {
+                let dir = file.path.parent().unwrap_or(Path::new(""));
+                take_tree(&mut visible, dir, false)
+            } else {
+                take_tree(&mut visible, &file.deleted_path(), true)
+            };
+            let mut removed_size = 0;
+            for (below, below_index) in removed {
+                let deleted = &mut lower[below].files[below_index];
+                deleted.deleted_in = Some(i);
+                removed_size += deleted.size;
+            }
+            changes[index] = Some(Change::Deleted { removed_size });
+            bytes_removed += removed_size;
+        }
+
+        // Entries listed again later in the same tar, where the last one wins
+        let mut relisted = Vec::new();
+        for (index, file) in layer.files.iter().enumerate().filter(|(_, f)| !f.is_whiteout) {
+            let Some(&(source, source_index)) = visible.get(&file.path) else {
+                changes[index] = Some(Change::Added);
+                visible.insert(file.path.clone(), (i, index));
+                continue;
+            };
+            changes[index] = if source < i {
+                let replaced = &mut lower[source].files[source_index];
+                replaced.overwritten_in = Some(i);
+                let size_delta = file.size as i64 - replaced.size as i64;
+                if replaced.kind == FileKind::Dir && file.kind != FileKind::Dir {
+                    for (below, below_index) in take_tree(&mut visible, &file.path, false) {
+                        let deleted = &mut lower[below].files[below_index];
+                        deleted.deleted_in = Some(i);
+                        bytes_removed += deleted.size;
+                    }
+                }
+                Some(Change::Modified { size_delta })
+            } else {
+                // Changes the lower layers the way the first listing did
+                relisted.push(source_index);
+                let first_size = layer.files[source_index].size as i64;
+                match changes[source_index] {
+                    Some(Change::Modified { size_delta }) => Some(Change::Modified {
+                        size_delta: size_delta - first_size + file.size as i64,
+                    }),
+                    change => change,
+                }
+            };
+            visible.insert(file.path.clone(), (i, index));
+        }
+        for index in relisted {
+            layer.files[index].overwritten_in = Some(i);
+        }
+
+        let mut totals = LayerChanges {
+            bytes_removed,
+            ..LayerChanges::default()
+        };
+        for (file, change) in layer.files.iter_mut().zip(changes) {
+            if file.overwritten_in.is_none() {
+                match change {
+                    Some(Change::Added) => totals.bytes_added += file.size,
+                    Some(Change::Modified { .. }) => totals.bytes_changed += file.size,
+                    _ => {}
+                }
+            }
+            file.change = change;
+        }
+        layer.changes = totals;
+    }
+
+    visible
+        .into_iter()
+        .map(|(path, (layer, index))| {
+            let file = &layers[layer].files[index];
+            MergedEntry {
+                path,
+                kind: file.kind,
+                size: file.size,
+                layer,
+            }
+        })
+        .collect()
+}
+
+/// Remove `root`'s descendants from `visible`, and `root` itself with
+/// `inclusive`, returning where they came from.
+fn take_tree(visible: &mut BTreeMap<PathBuf, Source>, root: &Path, inclusive: bool) -> Vec<Source> {
+    // Paths sort component-wise, so a directory's descendants follow it
+    let paths: Vec<PathBuf> = visible
+        .range::<Path, _>((Bound::Included(root), Bound::Unbounded))
+        .map(|(path, _)| path)
+        .take_while(|path| path.starts_with(root))
+        .filter(|path| inclusive || path.as_path() != root)
+        .cloned()
+        .collect();
+    paths.iter().filter_map(|path| visible.remove(path)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inspector::FileEntry;
+
+    fn file(path: &str, size: u64) -> FileEntry {
+        FileEntry {
+            size,
+            is_whiteout: false,
+            ..FileEntry::whiteout(path.into())
+        }
+    }
+
+    fn dir(path: &str) -> FileEntry {
+        FileEntry {
+            kind: FileKind::Dir,
+            ..file(path, 0)
+        }
+    }
+
+    fn whiteout(path: &str) -> FileEntry {
+        FileEntry::whiteout(path.into())
+    }
+
+    fn layers(files: Vec<Vec<FileEntry>>) -> Vec<LayerInfo> {
+        files
+            .into_iter()
+            .enumerate()
+            .map(|(i, files)| LayerInfo {
+                digest: format!("sha256:{i}"),
+                created_by: None,
+                size: files.iter().map(|f| f.size).sum(),
+                changes: LayerChanges::default(),
+                compression: None,
+                compressed_size: None,
+                files,
+            })
+            .collect()
+    }
+
+    /// Every (layer, path, marking layer) an entry was marked with by `mark`.
+    fn marked(layers: &[LayerInfo], mark: fn(&FileEntry) -> Option<usize>) -> Vec<(usize, String, usize)> {
+        let mut marked: Vec<_> = layers
+            .iter()
+            .enumerate()
+            .flat_map(|(i, layer)| {
+                layer
+                    .files
+                    .iter()
+                    .filter_map(move |f| Some((i, f.path.display().to_string(), mark(f)?)))
+            })
+            .collect();
+        marked.sort();
+        marked
+    }
+
+    struct Case {
+        name: &'static str,
+        layers: Vec<Vec<FileEntry>>,
+        /// The merged filesystem: (path, layer it comes from)
+        merged: &'static [(&'static str, usize)],
+        /// (layer, path, later layer) of entries a later layer deleted
+        deleted: &'static [(usize, &'static str, usize)],
+        /// (layer, path, later layer) of entries something replaced
+        overwritten: &'static [(usize, &'static str, usize)],
+        /// (bytes_added, bytes_changed, bytes_removed) of each layer
+        totals: &'static [(u64, u64, u64)],
+    }
+
+    #[test]
+    fn merge_layers_cases() {
+        let cases = [
+            Case {
+                name: "opaque directory at the root",
+                layers: vec![
+                    vec![dir("a"), file("a/x", 10), file("b", 5)],
+                    vec![whiteout(".wh..wh..opq"), file("c", 3)],
+                ],
+                merged: &[("c", 1)],
+                deleted: &[(0, "a", 1), (0, "a/x", 1), (0, "b", 1)],
+                overwritten: &[],
+                totals: &[(15, 0, 0), (3, 0, 15)],
+            },
+            Case {
+                name: "opaque directory",
+                layers: vec![
+                    vec![dir("d"), file("d/a", 4), file("e", 1)],
+                    vec![dir("d"), whiteout("d/.wh..wh..opq"), file("d/b", 2)],
+                ],
+                merged: &[("d", 1), ("d/b", 1), ("e", 0)],
+                deleted: &[(0, "d/a", 1)],
+                overwritten: &[(0, "d", 1)],
+                totals: &[(5, 0, 0), (2, 0, 4)],
+            },
+            Case {
+                name: "whiteout of a directory",
+                layers: vec![
+                    vec![dir("d"), file("d/a", 4), dir("d/sub"), file("d/sub/b", 6), file("d.txt", 1)],
+                    vec![whiteout(".wh.d")],
+                ],
+                merged: &[("d.txt", 0)],
+                deleted: &[(0, "d", 1), (0, "d/a", 1), (0, "d/sub", 1), (0, "d/sub/b", 1)],
+                overwritten: &[],
+                totals: &[(11, 0, 0), (0, 0, 10)],
+            },
+            Case {
+                name: "file replacing a directory",
+                layers: vec![vec![dir("d"), file("d/a", 5), file("d/b", 2)], vec![file("d", 3)]],
+                merged: &[("d", 1)],
+                deleted: &[(0, "d/a", 1), (0, "d/b", 1)],
+                overwritten: &[(0, "d", 1)],
+                totals: &[(7, 0, 0), (0, 3, 7)],
+            },
+            Case {
+                name: "path relisted in the same tar",
+                layers: vec![
+                    vec![file("f", 4)],
+                    vec![file("f", 2), file("g", 1), file("f", 7), file("g", 5)],
+                ],
+                merged: &[("f", 1), ("g", 1)],
+                deleted: &[],
+                overwritten: &[(0, "f", 1), (1, "f", 1), (1, "g", 1)],
+                totals: &[(4, 0, 0), (5, 7, 0)],
+            },
+        ];
+
+        for case in cases {
+            let mut layers = layers(case.layers);
+            let merged: Vec<(String, usize)> = merge_layers(&mut layers)
+                .into_iter()
+                .map(|entry| (entry.path.display().to_string(), entry.layer))
+                .collect();
+            let expected: Vec<(String, usize)> = case.merged.iter().map(|&(p, l)| (p.to_string(), l)).collect();
+            assert_eq!(merged, expected, "{}: merged filesystem", case.name);
+
+            let expected = |marks: &[(usize, &str, usize)]| -> Vec<(usize, String, usize)> {
+                marks.iter().map(|&(l, p, by)| (l, p.to_string(), by)).collect()
+            };
+            assert_eq!(marked(&layers, |f| f.deleted_in), expected(case.deleted), "{}: deleted_in", case.name);
+            assert_eq!(
+                marked(&layers, |f| f.overwritten_in),
+                expected(case.overwritten),
+                "{}: overwritten_in",
+                case.name
+            );
+
+            let totals: Vec<(u64, u64, u64)> = layers
+                .iter()
+                .map(|l| (l.changes.bytes_added, l.changes.bytes_changed, l.changes.bytes_removed))
+                .collect();
+            assert_eq!(totals, case.totals, "{}: layer totals", case.name);
+        }
+    }
+
+    #[test]
+    fn changes_against_lower_layers() {
+        let mut layers = layers(vec![
+            vec![dir("d"), file("d/a", 5), file("f", 4)],
+            vec![whiteout("d/.wh.a"), file("f", 2), file("g", 1), file("f", 7)],
+        ]);
+        merge_layers(&mut layers);
+        let changes: Vec<Option<Change>> = layers[1].files.iter().map(|f| f.change).collect();
+        assert_eq!(
+            changes,
+            [
+                Some(Change::Deleted { removed_size: 5 }),
+                Some(Change::Modified { size_delta: -2 }),
+                Some(Change::Added),
+                // The last listing wins, against what the layers below had
+                Some(Change::Modified { size_delta: 3 }),
+            ]
+        );
+        assert!(layers[0].files.iter().all(|f| f.change == Some(Change::Added)));
+    }
+}
@@ -1,13 +1,53 @@
+mod blob_store;
+pub mod docker_api;
 pub mod docker_archive;
+pub mod docker_container;
+pub mod efficiency;
+mod http;
+mod image_config;
+mod layer_blob;
+#[cfg(target_os = "linux")]
+mod layer_dir;
+mod layer_tar;
+pub mod merge;
 pub mod oci;
+pub mod oci_layout;
+pub mod platform;
+pub mod reference;
+pub mod registry;
+#[cfg(test)]
+mod test_http;
+mod timestamp;
+mod toc;
 
+#[cfg(target_os = "linux")]
+mod boltdb;
+#[cfg(target_os = "linux")]
+pub mod btrfs;
+#[cfg(target_os = "linux")]
+pub mod containerd;
+#[cfg(target_os = "linux")]
+pub mod containers_storage;
+#[cfg(target_os = "linux")]
+mod layerdb;
 #[cfg(target_os = "linux")]
 pub mod overlay2;
+#[cfg(target_os = "linux")]
+mod snapshot_diff;
+#[cfg(target_os = "linux")]
+mod tar_split;
+#[cfg(target_os = "linux")]
+pub mod vfs;
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use anyhow::Result;
-use serde::Serialize;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+
+pub use platform::Platform;
 
 /// Full inspection result for a container image.
 #[derive(Debug, Clone, Serialize)]
@@ -18,14 +58,145 @@ pub struct ImageInfo {
     /// Image tag (e.g. "latest")
     pub tag: Option<String>,
 
+    /// Image ID: the digest of the image config (e.g. sha256:abc123...)
+    pub id: String,
+
+    /// Every tag known to point at this image (e.g. "nginx:1.25"), as far
+    /// as the source records them
+    pub repo_tags: Vec<String>,
+
+    /// Manifest digests the image was pulled or pushed as (e.g. "nginx@sha256:...")
+    pub repo_digests: Vec<String>,
+
+    /// When the image was built (RFC 3339)
+    pub created: Option<String>,
+
+    /// Defaults for containers started from the image
+    pub config: RuntimeConfig,
+
+    /// Target operating system (e.g. "linux")
+    pub os: Option<String>,
+
     /// Target architecture (e.g. "amd64")
     pub architecture: Option<String>,
 
+    /// Architecture variant (e.g. "v8" for arm64)
+    pub variant: Option<String>,
+
     /// Total size across all layers, in bytes
     pub total_size: u64,
 
     /// Layers in order (base first)
     pub layers: Vec<LayerInfo>,
+
+    /// The root filesystem the layers add up to, sorted by path
+    /// (populated separately via `merge::merge_layers`)
+    pub filesystem: Vec<MergedEntry>,
+
+    /// How much of the layers' size is wasted on files later layers
+    /// replace or delete (populated separately via `efficiency::analyze`)
+    pub efficiency: Efficiency,
+
+    /// Every build step in order (base first), including the ones that
+    /// only changed the config and added no layer
+    pub history: Vec<HistoryStep>,
+
+    /// Problems found while reading the image that didn't stop inspection
+    /// (e.g. history that doesn't line up with the layers)
+    pub warnings: Vec<String>,
+}
+
+/// The `config` section of an image config: how containers run by default.
+/// Read from the image config's PascalCase keys, written in snake_case.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "PascalCase"))]
+pub struct RuntimeConfig {
+    /// `KEY=value` pairs
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub env: Vec<String>,
+
+    pub entrypoint: Option<Vec<String>>,
+
+    pub cmd: Option<Vec<String>>,
+
+    /// User (and group) to run as, e.g. "nginx" or "1000:1000"
+    pub user: Option<String>,
+
+    pub working_dir: Option<String>,
+
+    /// Ports as "<port>/<protocol>", e.g. "80/tcp"
+    #[serde(default, deserialize_with = "map_keys")]
+    pub exposed_ports: Vec<String>,
+
+    /// Mount points for anonymous volumes
+    #[serde(default, deserialize_with = "map_keys")]
+    pub volumes: Vec<String>,
+
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub labels: BTreeMap<String, String>,
+
+    pub stop_signal: Option<String>,
+
+    pub healthcheck: Option<Healthcheck>,
+}
+
+/// A container health check, with durations in nanoseconds as Docker
+/// records them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "PascalCase"))]
+pub struct Healthcheck {
+    /// e.g. `["CMD-SHELL", "curl -f http://localhost/"]`, or `["NONE"]`
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub test: Vec<String>,
+
+    pub interval: Option<i64>,
+
+    pub timeout: Option<i64>,
+
+    pub start_period: Option<i64>,
+
+    pub start_interval: Option<i64>,
+
+    pub retries: Option<u32>,
+}
+
+/// Read `null` like a missing field.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Read a Go-style set (`{"80/tcp": {}}`) as its sorted keys.
+fn map_keys<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let set: Option<BTreeMap<String, serde::de::IgnoredAny>> = Option::deserialize(deserializer)?;
+    Ok(set.map(|set| set.into_keys().collect()).unwrap_or_default())
+}
+
+/// One step of an image's build history.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryStep {
+    /// When the step ran (RFC 3339)
+    pub created: Option<String>,
+
+    /// The command that ran (e.g. "RUN /bin/sh -c apt-get update")
+    pub created_by: Option<String>,
+
+    pub author: Option<String>,
+
+    /// Free-form note from the builder (e.g. "buildkit.dockerfile.v0")
+    pub comment: Option<String>,
+
+    /// Whether the step only changed the config (ENV, LABEL, ...)
+    pub empty_layer: bool,
+
+    /// Index into `layers` of the layer this step added
+    pub layer: Option<usize>,
 }
 
 /// Metadata about a single layer in an image.
@@ -37,24 +208,275 @@ pub struct LayerInfo {
     /// The Dockerfile command that created this layer (if available)
     pub created_by: Option<String>,
 
-    /// Total size of files in this layer, in bytes
+    /// Combined size of the regular files in this layer, in bytes. Until
+    /// `files` is listed, backends may report their store's own figure
+    /// (e.g. a history entry's size or the unpacked tar's length).
     pub size: u64,
 
+    /// What the layer did to the filesystem below it, in bytes
+    /// (filled in by `merge::merge_layers`)
+    pub changes: LayerChanges,
+
+    /// How the layer blob is compressed (unknown when read from unpacked storage)
+    pub compression: Option<Compression>,
+
+    /// Size of the layer blob as stored, in bytes (unknown when read from unpacked storage)
+    pub compressed_size: Option<u64>,
+
     /// Files in this layer (populated separately via list_files)
     pub files: Vec<FileEntry>,
 }
 
+/// Wasted space across an image's layers.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Efficiency {
+    /// Share of the layers' bytes that end up in the final filesystem,
+    /// from 0 to 1
+    pub score: f64,
+
+    /// Size of every file in every layer
+    pub total_bytes: u64,
+
+    /// Size of the file copies no container ever sees: the sum of the three below
+    pub wasted_bytes: u64,
+
+    /// Replaced by a later layer with a different version
+    pub overwritten_bytes: u64,
+
+    /// Deleted by a later layer
+    pub deleted_bytes: u64,
+
+    /// Replaced by a later layer with a file of the same size, often the
+    /// same content rewritten (e.g. by `chmod -R`); contents aren't compared
+    pub same_size_rewrite_bytes: u64,
+
+    /// The paths wasting the most, largest first
+    pub offenders: Vec<WastedPath>,
+}
+
+/// A path whose copies in some layers are wasted.
+#[derive(Debug, Clone, Serialize)]
+pub struct WastedPath {
+    pub path: PathBuf,
+
+    /// Combined size of the wasted copies
+    pub wasted_bytes: u64,
+
+    /// How many layers hold a wasted copy
+    pub copies: usize,
+
+    /// What made the largest copy wasted
+    pub waste: Waste,
+}
+
+/// Why a copy of a file is wasted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Waste {
+    Overwritten,
+    Deleted,
+    SameSizeRewrite,
+}
+
+/// Bytes a layer added, changed and removed relative to the layers below it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LayerChanges {
+    /// Size of the files at paths the lower layers didn't have
+    pub bytes_added: u64,
+
+    /// Size of the files replacing ones from the lower layers
+    pub bytes_changed: u64,
+
+    /// Size of the lower layers' files this layer deleted
+    pub bytes_removed: u64,
+}
+
+/// How a layer entry changes the filesystem of the layers below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Change {
+    /// A path the lower layers didn't have
+    Added,
+    /// Replaces what the lower layers had at the path
+    Modified {
+        /// New size minus the size of what it replaces
+        size_delta: i64,
+    },
+    /// A whiteout
+    Deleted {
+        /// Size of the files it hides
+        removed_size: u64,
+    },
+}
+
+/// A path in the image's final filesystem, after every layer is applied.
+#[derive(Debug, Clone, Serialize)]
+pub struct MergedEntry {
+    pub path: PathBuf,
+
+    pub kind: FileKind,
+
+    /// File size in bytes (0 for anything but regular files)
+    pub size: u64,
+
+    /// Index into `layers` of the layer the path comes from
+    pub layer: usize,
+}
+
+/// Compression of a layer blob, detected from its magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Uncompressed,
+    Gzip,
+    Zstd,
+}
+
+/// Name of the whiteout that marks its directory as opaque: everything the
+/// lower layers had under it is hidden, not just individual paths.
+pub const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// What kind of filesystem object an entry is, as a layer tar records it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    /// Another name for a file listed earlier in the layer (see `link_target`)
+    Hardlink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+}
+
 /// A single file entry within a layer.
 #[derive(Debug, Clone, Serialize)]
 pub struct FileEntry {
     /// Full path within the layer
     pub path: PathBuf,
 
-    /// File size in bytes
+    /// File size in bytes (0 for anything but regular files)
     pub size: u64,
 
     /// Whether this is a whiteout (deletion marker)
     pub is_whiteout: bool,
+
+    pub kind: FileKind,
+
+    /// Permission bits, including setuid, setgid and sticky (e.g. 0o4755)
+    pub mode: u32,
+
+    pub uid: u64,
+
+    pub gid: u64,
+
+    /// Modification time in seconds since the Unix epoch
+    pub mtime: i64,
+
+    /// Where a symlink points, or the path a hardlink shares its file with
+    pub link_target: Option<PathBuf>,
+
+    /// Extended attributes (e.g. `security.capability`). Values that
+    /// aren't printable text are base64 with a `0s` prefix, like `getfattr`.
+    pub xattrs: BTreeMap<String, String>,
+
+    /// What this entry does to the layers below it
+    /// (set by `merge::merge_layers`)
+    pub change: Option<Change>,
+
+    /// Index of the later layer that put something else at this path
+    /// (set by `merge::merge_layers`)
+    pub overwritten_in: Option<usize>,
+
+    /// Index of the later layer that deleted this path, with a whiteout or
+    /// by replacing a directory above it (set by `merge::merge_layers`)
+    pub deleted_in: Option<usize>,
+}
+
+impl FileEntry {
+    /// A whiteout synthesized for a deleted path, with no metadata of its own.
+    pub fn whiteout(path: PathBuf) -> Self {
+        Self {
+            path,
+            size: 0,
+            is_whiteout: true,
+            kind: FileKind::File,
+            mode: 0,
+            uid: 0,
+            gid: 0,
+            mtime: 0,
+            link_target: None,
+            xattrs: BTreeMap::new(),
+            change: None,
+            overwritten_in: None,
+            deleted_in: None,
+        }
+    }
+
+    /// Whether this is an opaque-directory marker (`.wh..wh..opq`).
+    pub fn is_opaque(&self) -> bool {
+        self.is_whiteout && self.path.file_name().is_some_and(|n| n == OPAQUE_WHITEOUT)
+    }
+
+    /// The path a `.wh.` whiteout deletes.
+    pub fn deleted_path(&self) -> PathBuf {
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        self.path.with_file_name(name.trim_start_matches(".wh."))
+    }
+}
+
+/// Render an xattr value for `FileEntry::xattrs`: as-is if it's printable
+/// text, otherwise base64 with getfattr's `0s` prefix.
+pub fn xattr_value(value: &[u8]) -> String {
+    match std::str::from_utf8(value) {
+        Ok(text) if !text.chars().any(char::is_control) && !text.starts_with("0s") => text.to_string(),
+        _ => format!("0s{}", BASE64.encode(value)),
+    }
+}
+
+/// An image found in local storage, as enumerated by `Inspector::list_images`.
+#[derive(Debug, Clone)]
+pub struct StoredImage {
+    /// Image ID (config digest, e.g. sha256:abc123...)
+    pub id: String,
+
+    /// Every name the image is tagged with (e.g. "nginx:latest"), possibly none
+    pub tags: Vec<String>,
+
+    /// Storage layer IDs with the combined size of their regular files in
+    /// bytes, base first.
+    /// Images sharing a layer share its ID.
+    pub layers: Vec<(String, u64)>,
+}
+
+/// A digest recomputed from a layer's contents, next to the one the image
+/// records for it (`--verify`).
+#[derive(Debug, Clone)]
+pub struct DigestCheck {
+    /// What the digest identifies (e.g. "diff ID", "blob", "chain ID")
+    pub kind: &'static str,
+
+    /// Digest recorded in the image metadata
+    pub expected: String,
+
+    /// Digest computed from what is actually stored
+    pub actual: String,
+}
+
+impl DigestCheck {
+    pub fn new(kind: &'static str, expected: impl Into<String>, actual: impl Into<String>) -> Self {
+        Self {
+            kind,
+            expected: expected.into(),
+            actual: actual.into(),
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.expected == self.actual
+    }
 }
 
 /// Common interface for reading image layers from different backends.
@@ -64,4 +486,53 @@ pub trait Inspector {
 
     /// List all files in a specific layer.
     fn list_files(&mut self, layer: &LayerInfo) -> Result<Vec<FileEntry>>;
+
+    /// Choose which platform `inspect` reads from a multi-platform image.
+    /// Sources that only ever hold one platform ignore this.
+    fn select_platform(&mut self, _platform: &Platform) {}
+
+    /// Every platform of a multi-platform image. Empty for sources that
+    /// only hold one platform.
+    fn platforms(&mut self, _image: &str) -> Result<Vec<Platform>> {
+        Ok(Vec::new())
+    }
+
+    /// Every image in the backend's storage. Only backends that read
+    /// storage directly can enumerate it.
+    fn list_images(&mut self) -> Result<Vec<StoredImage>> {
+        anyhow::bail!("Listing images needs direct storage access")
+    }
+
+    /// What a container changed on top of its image: its read-write layer,
+    /// with files listed.
+    fn container_layer(&mut self, _container_id: &str) -> Result<LayerInfo> {
+        anyhow::bail!("Inspecting containers is only supported for Docker's overlay2, vfs and btrfs storage")
+    }
+
+    /// Hash layer blobs as they are read, for `verify_layer`. Must be
+    /// called before `inspect`; sources that can rehash a layer later
+    /// ignore it.
+    fn enable_verify(&mut self) {}
+
+    /// Recompute a layer's digests from its stored contents and pair them
+    /// with the ones the image records: always the diff ID of the
+    /// uncompressed tar, plus blob or chain IDs where the source has them.
+    fn verify_layer(&mut self, _layer: &LayerInfo) -> Result<Vec<DigestCheck>> {
+        anyhow::bail!("Verifying layers is not supported for this source")
+    }
+
+    /// Hash the image config `inspect` read and pair it with the digest
+    /// the source stores or references it under. Empty for sources that
+    /// never hand out the raw config.
+    fn verify_config(&mut self, _image: &str) -> Result<Vec<DigestCheck>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Split "repo:tag" into its parts, ignoring a ':' that belongs to a registry port.
+pub fn split_repo_tag(repo_tag: &str) -> (String, Option<String>) {
+    match repo_tag.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => (name.to_string(), Some(tag.to_string())),
+        _ => (repo_tag.to_string(), None),
+    }
 }
@@ -1,28 +1,116 @@
-use anyhow::Result;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
 
-use super::{FileEntry, ImageInfo, Inspector, LayerInfo};
+use anyhow::{Context, Result};
 
-/// Reads layers via the OCI image layout or container runtime API.
-/// Cross-platform, no root needed, but slower (requires API calls).
+use super::docker_archive::{ArchiveLayers, SavedArchive};
+use super::{DigestCheck, FileEntry, ImageInfo, Inspector, LayerInfo, Platform};
+
+/// Reads layers by streaming `docker save` / `podman save` output through
+/// the archive parser. Cross-platform, no root needed (only access to the
+/// runtime), but slower since every layer is serialized by the daemon.
 pub struct OciInspector {
     runtime_cmd: String,
+    /// Whether to hash layer blobs while reading the saved image
+    verify: bool,
+    /// Platform to pick from multi-platform OCI archives
+    platform: Platform,
+    /// Layers read during `inspect`
+    layers: ArchiveLayers,
 }
 
 impl OciInspector {
     pub fn new(runtime_cmd: String) -> Self {
-        Self { runtime_cmd }
+        Self {
+            runtime_cmd,
+            verify: false,
+            platform: Platform::host(),
+            layers: ArchiveLayers::default(),
+        }
+    }
+
+    /// Arguments that make the runtime CLI write an image archive to stdout.
+    /// `ctr` only exports the platform it's asked for; `docker save` and
+    /// `podman save` write the one they have.
+    fn save_args(&self, image: &str) -> Vec<String> {
+        let binary = Path::new(&self.runtime_cmd)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match binary.as_str() {
+            "ctr" => vec![
+                "images".to_string(),
+                "export".to_string(),
+                format!("--platform={}", self.platform),
+                "-".to_string(),
+                image.to_string(),
+            ],
+            _ => vec!["save".to_string(), image.to_string()],
+        }
     }
 }
 
 impl Inspector for OciInspector {
-    fn inspect(&mut self, _image: &str) -> Result<ImageInfo> {
-        // TODO: use runtime CLI (docker/podman) to inspect image and list layers
-        let _ = &self.runtime_cmd;
-        anyhow::bail!("OCI inspector not yet implemented")
+    fn inspect(&mut self, image: &str) -> Result<ImageInfo> {
+        let mut child = Command::new(&self.runtime_cmd)
+            .args(self.save_args(image))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run `{} save`", self.runtime_cmd))?;
+
+        // Drain stderr on a separate thread so a chatty runtime can't block the pipe
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stderr_reader = thread::spawn(move || {
+            let mut buf = String::new();
+            let _ = stderr.read_to_string(&mut buf);
+            buf
+        });
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let archive = SavedArchive::read(BufReader::new(stdout), self.verify);
+        let status = child.wait()?;
+        let stderr = stderr_reader.join().unwrap_or_default();
+
+        if !status.success() {
+            anyhow::bail!(
+                "`{} save {image}` failed: {}",
+                self.runtime_cmd,
+                stderr.trim()
+            );
+        }
+
+        let archive = archive
+            .with_context(|| format!("Failed to read `{} save` output", self.runtime_cmd))?;
+        let (info, layers) = archive.image_info(image, Some(image), &self.platform)?;
+        self.layers = layers;
+        Ok(info)
+    }
+
+    fn list_files(&mut self, layer: &LayerInfo) -> Result<Vec<FileEntry>> {
+        self.layers
+            .files
+            .get(&layer.digest)
+            .cloned()
+            .with_context(|| format!("Layer {} not found in saved image", layer.digest))
+    }
+
+    fn select_platform(&mut self, platform: &Platform) {
+        self.platform = platform.clone();
+    }
+
+    fn enable_verify(&mut self) {
+        self.verify = true;
+    }
+
+    fn verify_layer(&mut self, layer: &LayerInfo) -> Result<Vec<DigestCheck>> {
+        self.layers.checks(layer)
     }
 
-    fn list_files(&mut self, _layer: &LayerInfo) -> Result<Vec<FileEntry>> {
-        // TODO: extract layer tar via runtime CLI and list contents
-        anyhow::bail!("OCI inspector not yet implemented")
+    fn verify_config(&mut self, _image: &str) -> Result<Vec<DigestCheck>> {
+        Ok(self.layers.config_checks())
     }
 }
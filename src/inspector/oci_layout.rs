@@ -0,0 +1,355 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use oci_spec::image::{Descriptor, ImageIndex, ImageManifest};
+
+use super::blob_store::BlobStore;
+use super::docker_archive::ArchiveLayers;
+use super::image_config::ImageConfig;
+use super::{
+    split_repo_tag, DigestCheck, Efficiency, FileEntry, ImageInfo, Inspector, LayerChanges, LayerInfo, Platform,
+};
+
+/// Annotation holding the tag of a manifest in an OCI layout's index.json.
+pub const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+/// Annotation holding the full image name, written by containerd and buildx.
+pub const IMAGE_NAME_ANNOTATION: &str = "io.containerd.image.name";
+
+/// Reads layers from an OCI image layout directory
+/// (`oci-layout`, `index.json`, `blobs/sha256/`).
+/// Cross-platform, no daemon needed, but layer blobs are usually compressed.
+pub struct OciLayoutInspector {
+    layout_dir: PathBuf,
+    blobs: BlobStore,
+    /// Tag to select from index.json (`dir:tag`), if given
+    reference: Option<String>,
+    /// Platform to pick from multi-platform images
+    platform: Platform,
+    /// Whether to hash layer blobs while listing them
+    verify: bool,
+    /// Layers listed (and hashed, when verifying) during `inspect`
+    layers: ArchiveLayers,
+}
+
+impl OciLayoutInspector {
+    pub fn new(layout_dir: PathBuf, reference: Option<String>) -> Self {
+        Self {
+            blobs: BlobStore::new(layout_dir.clone()),
+            layout_dir,
+            reference,
+            platform: Platform::host(),
+            verify: false,
+            layers: ArchiveLayers::default(),
+        }
+    }
+
+    /// Parse `<dir>` or `<dir>:<tag>` if `<dir>` is an OCI image layout.
+    pub fn parse_arg(arg: &str) -> Option<(PathBuf, Option<String>)> {
+        if is_layout_dir(Path::new(arg)) {
+            return Some((PathBuf::from(arg), None));
+        }
+        let (dir, reference) = arg.rsplit_once(':')?;
+        is_layout_dir(Path::new(dir)).then(|| (PathBuf::from(dir), Some(reference.to_string())))
+    }
+
+    /// Read index.json, keeping the entries for the requested tag.
+    fn index_entries(&self) -> Result<Vec<Descriptor>> {
+        let index_path = self.layout_dir.join("index.json");
+        let index = ImageIndex::from_file(&index_path)
+            .with_context(|| format!("Failed to parse {}", index_path.display()))?;
+
+        let mut entries = index.manifests().clone();
+        if let Some(reference) = &self.reference {
+            entries.retain(|d| annotation(d, REF_NAME_ANNOTATION) == Some(reference.as_str()));
+            if entries.is_empty() {
+                anyhow::bail!(
+                    "Reference '{reference}' not found in {}",
+                    index_path.display()
+                );
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Walk index.json (and any nested indexes) down to the image manifest
+    /// for the selected platform.
+    fn resolve_manifest(&self) -> Result<(Descriptor, ImageManifest)> {
+        let entries = self.index_entries()?;
+        let candidates: Vec<&Descriptor> = entries.iter().collect();
+        let descriptor = self
+            .blobs
+            .select(&candidates, &self.platform)
+            .with_context(|| format!("No usable image manifest in {}", self.layout_dir.join("index.json").display()))?
+            .clone();
+
+        // Return the outer descriptor, its annotations carry the image name
+        let manifest = self.blobs.resolve_manifest(&descriptor, &self.platform)?;
+        Ok((descriptor, manifest))
+    }
+}
+
+impl Inspector for OciLayoutInspector {
+    fn inspect(&mut self, _image: &str) -> Result<ImageInfo> {
+        let (descriptor, manifest) = self.resolve_manifest()?;
+        self.layers = ArchiveLayers::default();
+
+        let config_data = self.blobs.read_blob(manifest.config())?;
+        let config = ImageConfig::parse(&config_data)
+            .with_context(|| format!("Failed to parse image config {}", manifest.config().digest()))?;
+        self.layers.config = Some(DigestCheck::new("config", manifest.config().digest().to_string(), &config.id));
+
+        let history = config.history();
+        let mut layers = Vec::with_capacity(manifest.layers().len());
+        let mut total_size = 0u64;
+
+        for (i, layer_desc) in manifest.layers().iter().enumerate() {
+            let listing = self.blobs.list_layer(layer_desc, self.verify)?;
+            let digest = config
+                .rootfs
+                .diff_ids
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| layer_desc.digest().to_string());
+
+            let size = listing.files.iter().map(|f| f.size).sum();
+            total_size += size;
+            layers.push(LayerInfo {
+                digest: digest.clone(),
+                created_by: history.created_by(i),
+                size,
+                changes: LayerChanges::default(),
+                compression: Some(listing.compression),
+                compressed_size: Some(listing.compressed_size),
+                files: Vec::new(),
+            });
+            if let Some(digests) = &listing.digests {
+                let expected_diff_id = config.rootfs.diff_ids.get(i).map(String::as_str);
+                let checks = digests.checks(Some(layer_desc.digest().as_ref()), expected_diff_id);
+                self.layers.checks.insert(digest.clone(), checks);
+            }
+            self.layers.files.insert(digest, listing.files);
+        }
+
+        // Every name index.json gives the same manifest, whichever was asked for
+        let index_path = self.layout_dir.join("index.json");
+        let index = ImageIndex::from_file(&index_path)
+            .with_context(|| format!("Failed to parse {}", index_path.display()))?;
+        let repo_tags = index
+            .manifests()
+            .iter()
+            .filter(|d| d.digest() == descriptor.digest())
+            .filter_map(|d| {
+                annotation(d, IMAGE_NAME_ANNOTATION).or_else(|| annotation(d, REF_NAME_ANNOTATION))
+            })
+            .map(String::from)
+            .collect();
+        let dir_name = self.layout_dir.display().to_string();
+        let (name, tag) = match annotation(&descriptor, IMAGE_NAME_ANNOTATION) {
+            Some(full_name) => split_repo_tag(full_name),
+            None => (
+                dir_name,
+                annotation(&descriptor, REF_NAME_ANNOTATION).map(String::from),
+            ),
+        };
+
+        Ok(ImageInfo {
+            name,
+            tag,
+            id: config.id,
+            repo_tags,
+            repo_digests: Vec::new(),
+            created: config.created,
+            config: config.config,
+            os: config.os,
+            architecture: config.architecture,
+            variant: config.variant,
+            total_size,
+            layers,
+            filesystem: Vec::new(),
+            efficiency: Efficiency::default(),
+            history: history.steps,
+            warnings: history.warnings,
+        })
+    }
+
+    fn list_files(&mut self, layer: &LayerInfo) -> Result<Vec<FileEntry>> {
+        self.layers
+            .files
+            .get(&layer.digest)
+            .cloned()
+            .with_context(|| format!("Layer {} not found in OCI layout", layer.digest))
+    }
+
+    fn enable_verify(&mut self) {
+        self.verify = true;
+    }
+
+    fn verify_layer(&mut self, layer: &LayerInfo) -> Result<Vec<DigestCheck>> {
+        self.layers.checks(layer)
+    }
+
+    fn verify_config(&mut self, _image: &str) -> Result<Vec<DigestCheck>> {
+        Ok(self.layers.config_checks())
+    }
+
+    fn select_platform(&mut self, platform: &Platform) {
+        self.platform = platform.clone();
+    }
+
+    fn platforms(&mut self, _image: &str) -> Result<Vec<Platform>> {
+        let entries = self.index_entries()?;
+        let candidates: Vec<&Descriptor> = entries.iter().collect();
+        self.blobs.platforms(&candidates)
+    }
+}
+
+fn is_layout_dir(path: &Path) -> bool {
+    path.is_dir() && path.join("oci-layout").is_file() && path.join("index.json").is_file()
+}
+
+pub fn annotation<'a>(descriptor: &'a Descriptor, key: &str) -> Option<&'a str> {
+    descriptor
+        .annotations()
+        .as_ref()
+        .and_then(|a| a.get(key))
+        .map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::{json, Value};
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("peel-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Store `data` under `blobs/sha256/` and return a descriptor for it.
+    fn write_blob(layout: &Path, media_type: &str, data: &[u8]) -> Value {
+        let hex = format!("{:x}", Sha256::digest(data));
+        let dir = layout.join("blobs/sha256");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(&hex), data).unwrap();
+        json!({ "mediaType": media_type, "digest": format!("sha256:{hex}"), "size": data.len() })
+    }
+
+    /// Store a one-file image for `arch` and return its manifest descriptor.
+    fn write_image(layout: &Path, arch: &str) -> Value {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(arch.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, format!("etc/{arch}"), arch.as_bytes()).unwrap();
+        let layer = write_blob(layout, "application/vnd.oci.image.layer.v1.tar", &builder.into_inner().unwrap());
+
+        let config = json!({
+            "architecture": arch,
+            "os": "linux",
+            "rootfs": { "type": "layers", "diff_ids": [layer["digest"]] },
+        });
+        let config = write_blob(layout, "application/vnd.oci.image.config.v1+json", config.to_string().as_bytes());
+        let manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": config,
+            "layers": [layer],
+        });
+        let mut descriptor =
+            write_blob(layout, "application/vnd.oci.image.manifest.v1+json", manifest.to_string().as_bytes());
+        descriptor["platform"] = json!({ "os": "linux", "architecture": arch });
+        descriptor
+    }
+
+    /// An OCI layout tagging a multi-platform index `multi` and a plain
+    /// amd64 manifest `single`.
+    fn write_layout(layout: &Path) {
+        let amd64 = write_image(layout, "amd64");
+        let arm64 = write_image(layout, "arm64");
+        let nested = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [amd64, arm64],
+        });
+        let mut multi =
+            write_blob(layout, "application/vnd.oci.image.index.v1+json", nested.to_string().as_bytes());
+        multi["annotations"] = json!({ REF_NAME_ANNOTATION: "multi" });
+        let mut single = amd64.clone();
+        single.as_object_mut().unwrap().remove("platform");
+        single["annotations"] = json!({ REF_NAME_ANNOTATION: "single" });
+
+        let index = json!({ "schemaVersion": 2, "manifests": [multi, single] });
+        fs::write(layout.join("index.json"), index.to_string()).unwrap();
+        fs::write(layout.join("oci-layout"), r#"{"imageLayoutVersion":"1.0.0"}"#).unwrap();
+    }
+
+    #[test]
+    fn parse_arg_splits_off_the_tag() {
+        let tmp = TempDir::new("oci-layout-arg");
+        let layout = tmp.0.join("image");
+        let colon = tmp.0.join("with:colon");
+        write_layout(&layout);
+        write_layout(&colon);
+
+        let arg = |path: &Path, suffix: &str| format!("{}{suffix}", path.display());
+        assert_eq!(OciLayoutInspector::parse_arg(&arg(&layout, "")), Some((layout.clone(), None)));
+        assert_eq!(
+            OciLayoutInspector::parse_arg(&arg(&layout, ":multi")),
+            Some((layout.clone(), Some("multi".to_string())))
+        );
+        // A colon in the directory name isn't a tag separator
+        assert_eq!(OciLayoutInspector::parse_arg(&arg(&colon, "")), Some((colon.clone(), None)));
+        assert_eq!(
+            OciLayoutInspector::parse_arg(&arg(&colon, ":single")),
+            Some((colon.clone(), Some("single".to_string())))
+        );
+        assert_eq!(OciLayoutInspector::parse_arg(&arg(&tmp.0, "/missing:multi")), None);
+        assert_eq!(OciLayoutInspector::parse_arg(&arg(&tmp.0, "")), None);
+    }
+
+    #[test]
+    fn index_resolves_tag_and_platform() {
+        let tmp = TempDir::new("oci-layout-index");
+        write_layout(&tmp.0);
+        let arm64: Platform = "linux/arm64".parse().unwrap();
+        let amd64: Platform = "linux/amd64".parse().unwrap();
+
+        let mut multi = OciLayoutInspector::new(tmp.0.clone(), Some("multi".to_string()));
+        assert_eq!(multi.platforms("").unwrap(), [amd64.clone(), arm64.clone()]);
+        multi.select_platform(&arm64);
+        let info = multi.inspect("").unwrap();
+        assert_eq!(info.tag.as_deref(), Some("multi"));
+        assert_eq!(info.architecture.as_deref(), Some("arm64"));
+        let files = multi.list_files(&info.layers[0]).unwrap();
+        assert_eq!(files[0].path, Path::new("etc/arm64"));
+
+        // Tags pick their own entry, whatever platform is selected
+        let mut single = OciLayoutInspector::new(tmp.0.clone(), Some("single".to_string()));
+        single.select_platform(&arm64);
+        let info = single.inspect("").unwrap();
+        assert_eq!(info.tag.as_deref(), Some("single"));
+        assert_eq!(info.architecture.as_deref(), Some("amd64"));
+        assert_eq!(info.total_size, 5);
+
+        let mut missing = OciLayoutInspector::new(tmp.0.clone(), Some("nope".to_string()));
+        let err = missing.inspect("").unwrap_err();
+        assert!(err.to_string().contains("Reference 'nope' not found"), "{err}");
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Result;
+use oci_spec::image::Descriptor;
+
+/// An image platform: `os/architecture[/variant]`, e.g. `linux/arm64/v8`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    pub os: String,
+    pub architecture: String,
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    /// The platform images run as on this machine. Containers are Linux
+    /// even on macOS, so only the architecture comes from the host.
+    pub fn host() -> Self {
+        Self {
+            os: "linux".to_string(),
+            architecture: normalize_arch(std::env::consts::ARCH).to_string(),
+            variant: None,
+        }
+    }
+
+    /// The platform an index entry is for, if it says.
+    pub fn of(descriptor: &Descriptor) -> Option<Self> {
+        let platform = descriptor.platform().as_ref()?;
+        Some(Self {
+            os: platform.os().to_string(),
+            architecture: normalize_arch(&platform.architecture().to_string()).to_string(),
+            variant: platform.variant().clone().filter(|v| !v.is_empty()),
+        })
+    }
+
+    /// Whether an image for `other` satisfies a request for `self`. A
+    /// request without a variant accepts any; arm64 images without a
+    /// variant are `v8`.
+    pub fn matches(&self, other: &Platform) -> bool {
+        let default_variant = |p: &Platform| match (p.variant.as_deref(), p.architecture.as_str()) {
+            (Some(v), _) => Some(v.to_string()),
+            (None, "arm64") => Some("v8".to_string()),
+            (None, _) => None,
+        };
+        self.os == other.os
+            && self.architecture == other.architecture
+            && (self.variant.is_none() || default_variant(self) == default_variant(other))
+    }
+
+    /// Attestation manifests (buildx provenance, SBOMs) use `unknown/unknown`.
+    fn is_attestation(&self) -> bool {
+        self.os == "unknown"
+    }
+}
+
+impl FromStr for Platform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split('/').collect();
+        match parts.as_slice() {
+            [os, arch] | [os, arch, _] if !os.is_empty() && !arch.is_empty() => Ok(Self {
+                os: os.to_string(),
+                architecture: normalize_arch(arch).to_string(),
+                variant: parts.get(2).filter(|v| !v.is_empty()).map(|v| v.to_string()),
+            }),
+            _ => anyhow::bail!("Invalid platform '{s}' (expected os/arch[/variant], e.g. linux/arm64)"),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{variant}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Pick the entry for `wanted` from an index's manifests, considering only
+/// those `available` (e.g. present in a local store). Entries without
+/// platform information (single-image indexes, nested indexes) are used
+/// when nothing matches exactly.
+pub fn select<'a>(
+    descriptors: &[&'a Descriptor],
+    wanted: &Platform,
+    available: impl Fn(&Descriptor) -> bool,
+) -> Result<&'a Descriptor> {
+    let usable: Vec<&'a Descriptor> = descriptors
+        .iter()
+        .copied()
+        .filter(|d| available(d) && !Platform::of(d).is_some_and(|p| p.is_attestation()))
+        .collect();
+
+    if let Some(exact) = usable
+        .iter()
+        .find(|d| Platform::of(d).is_some_and(|p| wanted.matches(&p)))
+    {
+        return Ok(exact);
+    }
+    if let Some(untagged) = usable.iter().find(|d| d.platform().is_none()) {
+        return Ok(untagged);
+    }
+
+    let offered: Vec<String> = usable
+        .iter()
+        .filter_map(|d| Platform::of(d))
+        .map(|p| p.to_string())
+        .collect();
+    if offered.is_empty() {
+        anyhow::bail!("No image manifest found");
+    }
+    anyhow::bail!(
+        "Platform {wanted} not found (available: {}); pick one with --platform",
+        offered.join(", ")
+    )
+}
+
+/// The platforms listed in an index, skipping attestations and duplicates.
+pub fn list(descriptors: &[&Descriptor], available: impl Fn(&Descriptor) -> bool) -> Vec<Platform> {
+    let mut platforms: Vec<Platform> = Vec::new();
+    for platform in descriptors
+        .iter()
+        .filter(|d| available(d))
+        .filter_map(|d| Platform::of(d))
+    {
+        if !platform.is_attestation() && !platforms.contains(&platform) {
+            platforms.push(platform);
+        }
+    }
+    platforms
+}
+
+/// Map Go/OCI and Rust architecture names onto the OCI ones.
+fn normalize_arch(arch: &str) -> &str {
+    match arch {
+        "x86_64" | "x86-64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" | "i386" => "386",
+        "powerpc64" => "ppc64le",
+        other => other,
+    }
+}
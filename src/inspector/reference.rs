@@ -0,0 +1,355 @@
+use std::fmt;
+
+use anyhow::Result;
+
+/// Registry name used in references to Docker Hub.
+pub const DOCKER_HUB: &str = "docker.io";
+
+/// Namespace of Docker Hub's official images (`nginx` is `library/nginx`).
+const OFFICIAL_NAMESPACE: &str = "library/";
+
+/// Tag assumed when a reference has neither a tag nor a digest.
+const DEFAULT_TAG: &str = "latest";
+
+/// An image reference normalized the way Docker does it:
+/// `[registry/]repository[:tag][@digest]`, where a missing registry is
+/// Docker Hub and single-component Hub repositories live under `library/`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+    /// Whether the registry was spelled out rather than defaulted
+    qualified: bool,
+}
+
+impl Reference {
+    pub fn parse(image: &str) -> Result<Self> {
+        let invalid = |reason: &str| anyhow::anyhow!("Invalid image reference '{image}': {reason}");
+
+        let (rest, digest) = match image.split_once('@') {
+            Some((rest, digest)) => {
+                let valid = digest.split_once(':').is_some_and(|(algorithm, hex)| {
+                    !algorithm.is_empty()
+                        && hex.len() >= 32
+                        && hex.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+                });
+                if !valid {
+                    return Err(invalid("digest must look like sha256:<hex>"));
+                }
+                (rest, Some(digest.to_string()))
+            }
+            None => (image, None),
+        };
+
+        // A ':' followed by a '/' is a registry port, not a tag
+        let (name, tag) = match rest.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => (name, Some(tag.to_string())),
+            _ => (rest, None),
+        };
+        if let Some(tag) = &tag {
+            let valid = (1..=128).contains(&tag.len())
+                && !tag.starts_with(['.', '-'])
+                && tag.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+            if !valid {
+                return Err(invalid("tags are 1 to 128 letters, digits, '_', '.' or '-'"));
+            }
+        }
+
+        // The first component is a registry if it looks like a host name
+        let (registry, repository, qualified) = match name.split_once('/') {
+            Some((domain, path))
+                if domain.contains(['.', ':'])
+                    || domain == "localhost"
+                    || domain.chars().any(|c| c.is_ascii_uppercase()) =>
+            {
+                (domain.to_string(), path.to_string(), true)
+            }
+            _ => (DOCKER_HUB.to_string(), name.to_string(), false),
+        };
+        let registry = if registry == "index.docker.io" {
+            DOCKER_HUB.to_string()
+        } else {
+            registry
+        };
+        let repository = if registry == DOCKER_HUB && !repository.contains('/') {
+            format!("{OFFICIAL_NAMESPACE}{repository}")
+        } else {
+            repository
+        };
+
+        if repository.is_empty() || repository.split('/').any(str::is_empty) {
+            return Err(invalid("empty path component"));
+        }
+        if repository.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(invalid("repository name must be lowercase"));
+        }
+        if !repository
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '.' | '_' | '-'))
+        {
+            return Err(invalid("repository names use lowercase letters, digits, '.', '_', '-' and '/'"));
+        }
+
+        Ok(Self {
+            registry,
+            repository,
+            tag,
+            digest,
+            qualified,
+        })
+    }
+
+    /// Fully qualified name without tag or digest (`docker.io/library/nginx`).
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.registry, self.repository)
+    }
+
+    /// The short name Docker shows and stores in `repositories.json`
+    /// (`nginx`, `user/app`, `quay.io/org/app`).
+    pub fn familiar_name(&self) -> String {
+        if self.registry != DOCKER_HUB {
+            return self.name();
+        }
+        match self.repository.strip_prefix(OFFICIAL_NAMESPACE) {
+            Some(short) if !short.contains('/') => short.to_string(),
+            _ => self.repository.clone(),
+        }
+    }
+
+    /// The tag to look up: the given one, or `latest` when the reference
+    /// has neither a tag nor a digest.
+    pub fn tag_or_default(&self) -> Option<&str> {
+        match (&self.tag, &self.digest) {
+            (Some(tag), _) => Some(tag),
+            (None, None) => Some(DEFAULT_TAG),
+            (None, Some(_)) => None,
+        }
+    }
+
+    /// `:tag` or `@digest` suffix used to look an image up. As in Docker,
+    /// a digest wins over a tag in `name:tag@digest`.
+    fn lookup_suffix(&self) -> String {
+        match (&self.digest, self.tag_or_default()) {
+            (Some(digest), _) => format!("@{digest}"),
+            (None, Some(tag)) => format!(":{tag}"),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Key in Docker's `repositories.json` (`nginx:latest`, `nginx@sha256:...`).
+    pub fn familiar_key(&self) -> String {
+        format!("{}{}", self.familiar_name(), self.lookup_suffix())
+    }
+
+    /// Fully qualified key, as containerd and containers/storage name images.
+    pub fn qualified_key(&self) -> String {
+        format!("{}{}", self.name(), self.lookup_suffix())
+    }
+
+    /// Names to try in stores that keep fully qualified names. Unqualified
+    /// references are also tried under `localhost/`, where Podman and
+    /// Buildah put local builds, and as typed.
+    pub fn candidate_names(&self) -> Vec<String> {
+        let mut candidates = Vec::new();
+        if !self.qualified {
+            candidates.push(self.familiar_key());
+            candidates.push(format!("localhost/{}{}", self.familiar_name(), self.lookup_suffix()));
+        }
+        candidates.push(self.qualified_key());
+        candidates
+    }
+
+    /// Whether a stored name (`nginx:latest`, `docker.io/library/nginx:latest`)
+    /// refers to the same image as this reference.
+    pub fn is_same_image(&self, name: &str) -> bool {
+        Reference::parse(name).is_ok_and(|r| r.qualified_key() == self.qualified_key())
+    }
+
+    /// Whether `other` names the same repository, counting local builds
+    /// (`localhost/app`) as matches for an unqualified `app`.
+    fn same_repository(&self, other: &Reference) -> bool {
+        self.name() == other.name()
+            || (!self.qualified && other.registry == "localhost" && other.repository == self.familiar_name())
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{tag}")?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{digest}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The hex part of something that looks like an image ID: a `sha256:`
+/// digest or a bare hex prefix of at least 4 characters.
+pub fn id_prefix(image: &str) -> Option<&str> {
+    let (hex, explicit) = match image.strip_prefix("sha256:") {
+        Some(hex) => (hex, true),
+        None => (image, false),
+    };
+    let is_hex = !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase());
+    (is_hex && (explicit || hex.len() >= 4)).then_some(hex)
+}
+
+/// A " (did you mean ...?)" hint for a reference that matched none of the
+/// `known` image names: other tags of the same repository first, else
+/// names a couple of typos away. Empty when nothing is close.
+pub fn did_you_mean<'a>(image: &str, known: impl IntoIterator<Item = &'a str>) -> String {
+    let Ok(wanted) = Reference::parse(image) else {
+        return String::new();
+    };
+    let known: Vec<Reference> = known
+        .into_iter()
+        .filter_map(|k| Reference::parse(k).ok())
+        .filter(|k| k.tag.is_some() && k.qualified_key() != wanted.qualified_key())
+        .collect();
+
+    let mut suggestions: Vec<(usize, String)> = known
+        .iter()
+        .filter(|k| wanted.same_repository(k))
+        .map(|k| {
+            let distance = edit_distance(wanted.tag_or_default().unwrap_or(""), k.tag.as_deref().unwrap_or(""));
+            (distance, k.familiar_key())
+        })
+        .collect();
+    if suggestions.is_empty() {
+        let name = wanted.familiar_name();
+        suggestions = known
+            .iter()
+            .map(|k| {
+                let distance = edit_distance(&name, &k.familiar_name()).min(edit_distance(&name, &k.repository));
+                (distance, k.familiar_key())
+            })
+            .filter(|(distance, _)| is_close(&name, *distance))
+            .collect();
+    }
+    format_hint(suggestions)
+}
+
+/// A " (did you mean ...?)" hint for a plain name (e.g. a container name)
+/// that matched none of `known`. Empty when nothing is close.
+pub fn did_you_mean_name<'a>(name: &str, known: impl IntoIterator<Item = &'a str>) -> String {
+    let suggestions = known
+        .into_iter()
+        .map(|k| (edit_distance(name, k), k.to_string()))
+        .filter(|(distance, _)| is_close(name, *distance))
+        .collect();
+    format_hint(suggestions)
+}
+
+/// Whether a name `distance` edits away from `name` is likely a typo of it.
+fn is_close(name: &str, distance: usize) -> bool {
+    distance <= (name.len() / 3).max(2)
+}
+
+/// Format the three closest suggestions as a hint.
+fn format_hint(mut suggestions: Vec<(usize, String)>) -> String {
+    suggestions.sort();
+    suggestions.dedup_by(|a, b| a.1 == b.1);
+
+    let names: Vec<String> = suggestions.into_iter().take(3).map(|(_, name)| name).collect();
+    match names.as_slice() {
+        [] => String::new(),
+        [one] => format!(" (did you mean {one}?)"),
+        [rest @ .., last] => format!(" (did you mean {} or {last}?)", rest.join(", ")),
+    }
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+
+    #[test]
+    fn parse_normalizes_like_docker() {
+        // (input, normalized reference, familiar name)
+        let cases: &[(&str, &str, &str)] = &[
+            ("nginx", "docker.io/library/nginx", "nginx"),
+            ("nginx:1.25", "docker.io/library/nginx:1.25", "nginx"),
+            ("user/app:v2", "docker.io/user/app:v2", "user/app"),
+            ("docker.io/nginx", "docker.io/library/nginx", "nginx"),
+            ("docker.io/library/nginx:latest", "docker.io/library/nginx:latest", "nginx"),
+            ("index.docker.io/library/nginx", "docker.io/library/nginx", "nginx"),
+            ("library/nginx/sub", "docker.io/library/nginx/sub", "library/nginx/sub"),
+            ("quay.io/org/app:1", "quay.io/org/app:1", "quay.io/org/app"),
+            ("localhost/app", "localhost/app", "localhost/app"),
+            ("localhost:5000/x:tag", "localhost:5000/x:tag", "localhost:5000/x"),
+            ("localhost:5000/x", "localhost:5000/x", "localhost:5000/x"),
+            (&format!("nginx@{DIGEST}"), &format!("docker.io/library/nginx@{DIGEST}"), "nginx"),
+            (&format!("ghcr.io/o/a:v1@{DIGEST}"), &format!("ghcr.io/o/a:v1@{DIGEST}"), "ghcr.io/o/a"),
+        ];
+        for &(input, normalized, familiar) in cases {
+            let reference = Reference::parse(input).unwrap_or_else(|e| panic!("{input}: {e}"));
+            assert_eq!(reference.to_string(), normalized, "{input}");
+            assert_eq!(reference.familiar_name(), familiar, "{input}");
+        }
+
+        let reference = Reference::parse("localhost:5000/x:tag").unwrap();
+        assert_eq!(reference.registry, "localhost:5000");
+        assert_eq!(reference.repository, "x");
+        assert_eq!(reference.tag.as_deref(), Some("tag"));
+        let reference = Reference::parse(&format!("nginx@{DIGEST}")).unwrap();
+        assert_eq!((reference.tag, reference.digest.as_deref()), (None, Some(DIGEST)));
+    }
+
+    #[test]
+    fn lookup_keys() {
+        // (input, familiar key, qualified key)
+        let cases: &[(&str, &str, &str)] = &[
+            ("nginx", "nginx:latest", "docker.io/library/nginx:latest"),
+            ("user/app:v2", "user/app:v2", "docker.io/user/app:v2"),
+            ("localhost:5000/x:tag", "localhost:5000/x:tag", "localhost:5000/x:tag"),
+            (&format!("nginx:1@{DIGEST}"), &format!("nginx@{DIGEST}"), &format!("docker.io/library/nginx@{DIGEST}")),
+        ];
+        for &(input, familiar, qualified) in cases {
+            let reference = Reference::parse(input).unwrap();
+            assert_eq!(reference.familiar_key(), familiar, "{input}");
+            assert_eq!(reference.qualified_key(), qualified, "{input}");
+        }
+
+        let candidates = Reference::parse("app").unwrap().candidate_names();
+        assert_eq!(candidates, ["app:latest", "localhost/app:latest", "docker.io/library/app:latest"]);
+        assert!(Reference::parse("nginx").unwrap().is_same_image("docker.io/library/nginx:latest"));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_references() {
+        for input in [
+            "Nginx",
+            "nginx:",
+            "nginx:-tag",
+            "user//app",
+            "/app",
+            "nginx@sha256:abc",
+            "nginx@sha256",
+            "app name",
+        ] {
+            assert!(Reference::parse(input).is_err(), "{input} should not parse");
+        }
+    }
+}
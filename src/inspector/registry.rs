@@ -0,0 +1,1078 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use oci_spec::image::{Descriptor, ImageIndex, ImageManifest};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+use super::docker_archive::ArchiveLayers;
+use super::http::{self, ChannelReader, Connection};
+use super::image_config::ImageConfig;
+use super::toc::{self, TocLocation};
+use super::layer_blob::{self, LayerListing};
+use super::platform::{self, Platform};
+use super::reference::{did_you_mean, Reference, DOCKER_HUB};
+use super::{
+    split_repo_tag, Compression, DigestCheck, Efficiency, FileEntry, ImageInfo, Inspector, LayerChanges, LayerInfo,
+};
+
+/// Manifest types we can read, most specific last.
+const MANIFEST_ACCEPT: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+/// Host actually serving Docker Hub's distribution API.
+const DOCKER_HUB_API: &str = "registry-1.docker.io";
+
+/// Keys `docker login` may have stored Docker Hub credentials under.
+const DOCKER_HUB_AUTH_KEYS: &[&str] = &["index.docker.io", "docker.io", "registry-1.docker.io"];
+
+/// Server URL credential helpers keep Docker Hub's credentials under.
+const DOCKER_HUB_SERVER_URL: &str = "https://index.docker.io/v1/";
+
+/// Username a credential helper returns with an identity token as secret.
+const IDENTITY_TOKEN_USERNAME: &str = "<token>";
+
+/// Blob downloads usually redirect to a CDN; give up after this many hops.
+const MAX_REDIRECTS: usize = 5;
+
+impl Reference {
+    /// Host serving the distribution API for this registry.
+    fn api_host(&self) -> &str {
+        if self.registry == DOCKER_HUB {
+            DOCKER_HUB_API
+        } else {
+            &self.registry
+        }
+    }
+
+    /// Tag or digest to request the manifest by.
+    fn manifest_ref(&self) -> &str {
+        self.digest
+            .as_deref()
+            .or(self.tag.as_deref())
+            .unwrap_or("latest")
+    }
+
+    /// Registries on the local machine are spoken to over plain HTTP,
+    /// the same exemption Docker makes for insecure registries.
+    fn is_local(&self) -> bool {
+        let host = Url::strip_port(&self.registry);
+        host == "localhost" || host == "::1" || host.starts_with("127.")
+    }
+}
+
+/// The pieces of a URL the client needs.
+#[derive(Debug, Clone)]
+struct Url {
+    tls: bool,
+    /// Host with an optional `:port`
+    host: String,
+    /// Path and query
+    path: String,
+}
+
+impl Url {
+    fn parse(url: &str) -> Result<Self> {
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            anyhow::bail!("Unsupported URL '{url}'");
+        };
+        let (host, path) = match rest.find(['/', '?']) {
+            Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+            Some(i) => (&rest[..i], rest[i..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+        Ok(Self {
+            tls,
+            host: host.to_string(),
+            path,
+        })
+    }
+
+    /// Resolve a `Location` header against this URL.
+    fn join(&self, location: &str) -> Result<Self> {
+        if location.starts_with("http://") || location.starts_with("https://") {
+            return Self::parse(location);
+        }
+        if !location.starts_with('/') {
+            anyhow::bail!("Unsupported redirect location '{location}'");
+        }
+        Ok(Self {
+            path: location.to_string(),
+            ..self.clone()
+        })
+    }
+
+    /// Split an explicit `:port` off a host (`example.com:5000`, `[::1]:5000`).
+    fn split_port(host: &str) -> (&str, Option<&str>) {
+        match host.rsplit_once(':') {
+            Some((name, port))
+                if !port.is_empty()
+                    && port.chars().all(|c| c.is_ascii_digit())
+                    && (!name.contains(':') || name.ends_with(']')) =>
+            {
+                (name, Some(port))
+            }
+            _ => (host, None),
+        }
+    }
+
+    fn strip_port(host: &str) -> &str {
+        let (name, _) = Self::split_port(host);
+        name.trim_start_matches('[').trim_end_matches(']')
+    }
+
+    /// Host name without the port, for TLS server name checks.
+    fn hostname(&self) -> &str {
+        Self::strip_port(&self.host)
+    }
+
+    /// `host:port` to connect to, filling in the scheme's default port.
+    fn addr(&self) -> String {
+        match Self::split_port(&self.host) {
+            (_, Some(_)) => self.host.clone(),
+            (name, None) => format!("{name}:{}", if self.tls { 443 } else { 80 }),
+        }
+    }
+}
+
+/// `~/.docker/config.json`, as written by `docker login`.
+#[derive(Deserialize)]
+struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, AuthEntry>,
+    /// Credential helper holding every registry's credentials
+    /// (`docker-credential-<name>`)
+    #[serde(rename = "credsStore")]
+    creds_store: Option<String>,
+    /// Credential helpers for particular registries, by host
+    #[serde(rename = "credHelpers", default)]
+    cred_helpers: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct AuthEntry {
+    /// base64 of `user:password`
+    auth: Option<String>,
+    /// OAuth refresh token some logins store instead of a password
+    identitytoken: Option<String>,
+}
+
+/// Output of `docker-credential-<helper> get`.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HelperCredentials {
+    username: String,
+    secret: String,
+}
+
+/// What `docker login` left for a registry.
+#[derive(Debug, PartialEq)]
+enum Credentials {
+    /// base64 of `user:password`
+    Basic(String),
+    /// OAuth refresh token, traded for an access token at the token endpoint
+    IdentityToken(String),
+}
+
+/// Response of a bearer token endpoint.
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// Error body returned by distribution API endpoints.
+#[derive(Deserialize)]
+struct RegistryErrors {
+    errors: Vec<RegistryError>,
+}
+
+#[derive(Deserialize)]
+struct RegistryError {
+    message: String,
+}
+
+/// Response of `GET /v2/<name>/tags/list`.
+#[derive(Deserialize)]
+struct TagList {
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// A client for one repository on one registry, handling token auth and
+/// redirects on top of the minimal HTTP client.
+struct RegistryClient {
+    reference: Reference,
+    base: Url,
+    /// Credentials from the Docker config or its credential helper
+    credentials: Option<Credentials>,
+    /// `Authorization` header value once a challenge has been answered
+    authorization: Option<String>,
+    tls: TlsConnector,
+    /// Problems that didn't stop the inspection, for `ImageInfo::warnings`
+    warnings: Vec<String>,
+}
+
+impl RegistryClient {
+    fn new(reference: Reference) -> Self {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let mut warnings = Vec::new();
+        Self {
+            base: Url {
+                tls: !reference.is_local(),
+                host: reference.api_host().to_string(),
+                path: "/".to_string(),
+            },
+            credentials: load_credentials(&reference.registry, &mut warnings),
+            authorization: None,
+            tls: TlsConnector::from(Arc::new(config)),
+            warnings,
+            reference,
+        }
+    }
+
+    async fn connect(&self, url: &Url) -> Result<Box<dyn Connection>> {
+        let addr = url.addr();
+        let stream = TcpStream::connect(addr.as_str())
+            .await
+            .with_context(|| format!("Failed to connect to {addr}"))?;
+        if !url.tls {
+            return Ok(Box::new(stream));
+        }
+
+        let server_name = ServerName::try_from(url.hostname().to_string())
+            .with_context(|| format!("Invalid server name '{}'", url.hostname()))?;
+        let stream = self
+            .tls
+            .connect(server_name, stream)
+            .await
+            .with_context(|| format!("TLS handshake with {} failed", url.host))?;
+        Ok(Box::new(stream))
+    }
+
+    async fn send(&self, url: &Url, headers: &[(&str, &str)]) -> Result<http::Response> {
+        let conn = self.connect(url).await?;
+        http::send(conn, "GET", &url.host, &url.path, headers, &[]).await
+    }
+
+    async fn post_form(&self, url: &Url, form: &str) -> Result<http::Response> {
+        let conn = self.connect(url).await?;
+        let headers = [("Content-Type", "application/x-www-form-urlencoded")];
+        http::send(conn, "POST", &url.host, &url.path, &headers, form.as_bytes()).await
+    }
+
+    /// GET a path on the registry, authenticating on the first 401 and
+    /// following redirects. Credentials are only sent to the registry
+    /// itself, never to the hosts it redirects to.
+    async fn get(&mut self, path: &str, extra_headers: &[(&str, &str)]) -> Result<http::Response> {
+        let mut url = self.base.join(path)?;
+        let mut authenticated = false;
+        let mut redirects = 0;
+
+        loop {
+            let same_host = url.host == self.base.host;
+            let mut headers = extra_headers.to_vec();
+            if let Some(auth) = self.authorization.as_deref().filter(|_| same_host) {
+                headers.push(("Authorization", auth));
+            }
+
+            let response = self.send(&url, &headers).await?;
+            match response.status {
+                401 if same_host && !authenticated => {
+                    let challenge = response
+                        .header("www-authenticate")
+                        .context("Registry returned 401 without an auth challenge")?
+                        .to_string();
+                    self.authorize(&challenge).await?;
+                    authenticated = true;
+                }
+                301 | 302 | 303 | 307 | 308 => {
+                    redirects += 1;
+                    if redirects > MAX_REDIRECTS {
+                        anyhow::bail!("Too many redirects fetching {path}");
+                    }
+                    let location = response
+                        .header("location")
+                        .context("Redirect without a Location header")?;
+                    url = url.join(location)?;
+                }
+                _ if response.is_success() => return Ok(response),
+                status => {
+                    let body = response.bytes().await.unwrap_or_default();
+                    let message = serde_json::from_slice::<RegistryErrors>(&body)
+                        .ok()
+                        .and_then(|e| e.errors.into_iter().next())
+                        .map(|e| e.message)
+                        .unwrap_or_else(|| String::from_utf8_lossy(&body).trim().to_string());
+                    anyhow::bail!("Registry {} returned {status} for {path}: {message}", self.reference.registry)
+                }
+            }
+        }
+    }
+
+    /// Answer a `WWW-Authenticate` challenge: Basic uses the stored
+    /// credentials directly, Bearer trades them (or nothing, for anonymous
+    /// pulls) for a token scoped to this repository.
+    async fn authorize(&mut self, challenge: &str) -> Result<()> {
+        let (scheme, params) = parse_challenge(challenge);
+
+        if scheme.eq_ignore_ascii_case("basic") {
+            let Some(Credentials::Basic(credentials)) = &self.credentials else {
+                let registry = &self.reference.registry;
+                anyhow::bail!("Registry {registry} requires a username and password; run `docker login {registry}`");
+            };
+            self.authorization = Some(format!("Basic {credentials}"));
+            return Ok(());
+        }
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            anyhow::bail!("Unsupported registry auth scheme '{scheme}'");
+        }
+
+        let realm = params.get("realm").context("Bearer challenge without a realm")?;
+        let scope = params
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:pull", self.reference.repository));
+        let mut query = vec![format!("scope={}", percent_encode(&scope))];
+        if let Some(service) = params.get("service") {
+            query.push(format!("service={}", percent_encode(service)));
+        }
+        let response = match &self.credentials {
+            // OAuth2: trade the refresh token for an access token
+            Some(Credentials::IdentityToken(token)) => {
+                query.extend([
+                    "grant_type=refresh_token".to_string(),
+                    format!("refresh_token={}", percent_encode(token)),
+                    "client_id=peel".to_string(),
+                ]);
+                self.post_form(&Url::parse(realm)?, &query.join("&")).await?
+            }
+            credentials => {
+                let separator = if realm.contains('?') { '&' } else { '?' };
+                let token_url = Url::parse(&format!("{realm}{separator}{}", query.join("&")))?;
+                let basic = match credentials {
+                    Some(Credentials::Basic(c)) => Some(format!("Basic {c}")),
+                    _ => None,
+                };
+                let headers: Vec<(&str, &str)> = basic.iter().map(|b| ("Authorization", b.as_str())).collect();
+                self.send(&token_url, &headers).await?
+            }
+        };
+        if !response.is_success() {
+            anyhow::bail!("Failed to get a registry token from {realm}: HTTP {}", response.status);
+        }
+        let body = response.bytes().await?;
+        let token: TokenResponse =
+            serde_json::from_slice(&body).with_context(|| format!("Failed to parse token response from {realm}"))?;
+        let token = token
+            .token
+            .or(token.access_token)
+            .with_context(|| format!("Token response from {realm} has no token"))?;
+        self.authorization = Some(format!("Bearer {token}"));
+        Ok(())
+    }
+
+    /// Fetch a manifest or index by tag or digest, with the digest of the
+    /// exact bytes the registry returned. One fetched by digest must hash to it.
+    async fn fetch_manifest_or_index(&mut self, reference: &str) -> Result<(String, Fetched)> {
+        let path = format!("/v2/{}/manifests/{reference}", self.reference.repository);
+        let response = match self.get(&path, &[("Accept", MANIFEST_ACCEPT)]).await {
+            Ok(response) => response,
+            Err(e) if self.reference.digest.is_none() && reference == self.reference.manifest_ref() => {
+                let hint = self.tag_hint().await;
+                return Err(if hint.is_empty() { e } else { e.context(format!("Tag '{reference}' not found{hint}")) });
+            }
+            Err(e) => return Err(e),
+        };
+        let body = response.bytes().await?;
+        let digest = format!("sha256:{:x}", Sha256::digest(&body));
+        if reference.starts_with("sha256:") && digest != reference {
+            anyhow::bail!("Manifest {reference} from the registry hashes to {digest}");
+        }
+        let value: serde_json::Value =
+            serde_json::from_slice(&body).with_context(|| format!("Failed to parse manifest {reference}"))?;
+
+        if value.get("manifests").is_none() {
+            let manifest = ImageManifest::from_reader(body.as_slice())
+                .with_context(|| format!("Failed to parse manifest {reference}"))?;
+            return Ok((digest, Fetched::Manifest(Box::new(manifest))));
+        }
+        let index = ImageIndex::from_reader(body.as_slice())
+            .with_context(|| format!("Failed to parse index {reference}"))?;
+        Ok((digest, Fetched::Index(Box::new(index))))
+    }
+
+    /// "did you mean" hint listing the repository's tags closest to the
+    /// requested one. Empty if the registry won't list them.
+    async fn tag_hint(&mut self) -> String {
+        let path = format!("/v2/{}/tags/list", self.reference.repository);
+        let Ok(response) = self.get(&path, &[]).await else {
+            return String::new();
+        };
+        let Some(list) = response
+            .bytes()
+            .await
+            .ok()
+            .and_then(|body| serde_json::from_slice::<TagList>(&body).ok())
+        else {
+            return String::new();
+        };
+        let name = self.reference.name();
+        let known: Vec<String> = list.tags.iter().map(|tag| format!("{name}:{tag}")).collect();
+        did_you_mean(&self.reference.to_string(), known.iter().map(String::as_str))
+    }
+
+    /// Fetch the manifest for the reference, following an index down to
+    /// the image for `platform`. Also returns the digest the reference
+    /// itself resolved to (the index's, for multi-platform images).
+    async fn fetch_manifest(&mut self, platform: &Platform) -> Result<(String, ImageManifest)> {
+        let mut reference = self.reference.manifest_ref().to_string();
+        let mut top_digest = None;
+        loop {
+            let (digest, fetched) = self.fetch_manifest_or_index(&reference).await?;
+            let top_digest = top_digest.get_or_insert(digest);
+            let index = match fetched {
+                Fetched::Manifest(manifest) => return Ok((top_digest.clone(), *manifest)),
+                Fetched::Index(index) => index,
+            };
+            let candidates: Vec<&Descriptor> = index.manifests().iter().collect();
+            let child = platform::select(&candidates, platform, |_| true)
+                .with_context(|| format!("No usable image manifest in index {reference}"))?;
+            reference = child.digest().to_string();
+        }
+    }
+
+    /// Platforms listed by the reference's index, empty for a single manifest.
+    async fn fetch_platforms(&mut self) -> Result<Vec<Platform>> {
+        let reference = self.reference.manifest_ref().to_string();
+        match self.fetch_manifest_or_index(&reference).await?.1 {
+            Fetched::Manifest(_) => Ok(Vec::new()),
+            Fetched::Index(index) => {
+                let candidates: Vec<&Descriptor> = index.manifests().iter().collect();
+                Ok(platform::list(&candidates, |_| true))
+            }
+        }
+    }
+
+    async fn get_blob(&mut self, descriptor: &Descriptor) -> Result<http::Response> {
+        let path = format!("/v2/{}/blobs/{}", self.reference.repository, descriptor.digest());
+        self.get(&path, &[]).await
+    }
+
+    /// Fetch bytes `start..end` of a blob with a range request.
+    async fn get_blob_range(&mut self, descriptor: &Descriptor, start: u64, end: u64) -> Result<Vec<u8>> {
+        let path = format!("/v2/{}/blobs/{}", self.reference.repository, descriptor.digest());
+        let range = format!("bytes={start}-{}", end.saturating_sub(1));
+        let response = self.get(&path, &[("Range", &range)]).await?;
+        if response.status != 206 {
+            anyhow::bail!("Registry ignored range request for {}", descriptor.digest());
+        }
+        response.bytes().await
+    }
+
+    /// List an eStargz or zstd:chunked layer from its table of contents,
+    /// fetching only the TOC (and eStargz footer) with range requests.
+    async fn list_layer_toc(&mut self, descriptor: &Descriptor, toc: TocLocation) -> Result<LayerListing> {
+        let (files, compression) = match toc {
+            TocLocation::Estargz { toc_digest } => {
+                let blob_size = descriptor.size();
+                let footer_start = blob_size
+                    .checked_sub(toc::ESTARGZ_FOOTER_SIZE)
+                    .context("eStargz blob is smaller than its footer")?;
+                let footer = self.get_blob_range(descriptor, footer_start, blob_size).await?;
+                let (toc_offset, footer_size) =
+                    toc::estargz_toc_offset(&footer).context("Invalid eStargz footer")?;
+                let toc_end = blob_size - footer_size;
+                if toc_offset >= toc_end {
+                    anyhow::bail!("Invalid eStargz TOC offset {toc_offset}");
+                }
+                let data = self.get_blob_range(descriptor, toc_offset, toc_end).await?;
+                (toc::read_estargz_toc(&data, &toc_digest)?, Compression::Gzip)
+            }
+            TocLocation::ZstdChunked { offset, length, checksum } => {
+                let data = self.get_blob_range(descriptor, offset, offset + length).await?;
+                (toc::read_zstd_chunked_manifest(&data, &checksum)?, Compression::Zstd)
+            }
+        };
+        Ok(LayerListing {
+            files,
+            compression,
+            compressed_size: descriptor.size(),
+            digests: None,
+        })
+    }
+
+    /// List a layer's files: from its TOC when it has one, otherwise by
+    /// streaming the blob through the tar lister without keeping it around.
+    /// Hashing needs every byte, so it always takes the full download.
+    async fn list_layer(&mut self, descriptor: &Descriptor, hash: bool) -> Result<LayerListing> {
+        // Fall back to the full download if the TOC can't be used, e.g.
+        // when the registry doesn't support range requests
+        if !hash && let Some(toc) = TocLocation::of(descriptor) {
+            match self.list_layer_toc(descriptor, toc).await {
+                Ok(listing) => return Ok(listing),
+                Err(e) => self.warnings.push(format!(
+                    "Couldn't list layer {} from its TOC, downloading all of it: {e:#}",
+                    descriptor.digest()
+                )),
+            }
+        }
+
+        let response = self.get_blob(descriptor).await?;
+        let (tx, rx) = mpsc::channel(16);
+        let parser = tokio::task::spawn_blocking(move || layer_blob::list(ChannelReader::new(rx), hash));
+        let download = response.pipe_to(tx).await;
+        let files = parser.await?;
+
+        // A broken download surfaces as a truncated tar; report the root cause
+        download.with_context(|| format!("Failed to download layer {}", descriptor.digest()))?;
+        files.with_context(|| format!("Failed to list layer {}", descriptor.digest()))
+    }
+}
+
+/// What a manifest request returned.
+enum Fetched {
+    Manifest(Box<ImageManifest>),
+    Index(Box<ImageIndex>),
+}
+
+/// Reads images straight from a registry over the OCI distribution API
+/// (`registry://<ref>` or `--remote`), without pulling them into a runtime.
+/// Layer blobs are streamed and listed as they download.
+pub struct RegistryInspector {
+    runtime: Runtime,
+    /// Platform to pick from multi-platform images
+    platform: Platform,
+    /// Whether to hash layer blobs as they download
+    verify: bool,
+    /// Layers listed (and hashed, when verifying) as they downloaded
+    layers: ArchiveLayers,
+}
+
+impl RegistryInspector {
+    pub fn new() -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            runtime,
+            platform: Platform::host(),
+            verify: false,
+            layers: ArchiveLayers::default(),
+        })
+    }
+}
+
+impl Inspector for RegistryInspector {
+    fn inspect(&mut self, image: &str) -> Result<ImageInfo> {
+        let reference = Reference::parse(image)?;
+        let mut client = RegistryClient::new(reference);
+        let platform = &self.platform;
+        let verify = self.verify;
+
+        let (config, config_digest, manifest_digest, layers) = self.runtime.block_on(async {
+            let (manifest_digest, manifest) = client.fetch_manifest(platform).await?;
+            let config_data = client.get_blob(manifest.config()).await?.bytes().await?;
+            let config = ImageConfig::parse(&config_data)
+                .with_context(|| format!("Failed to parse image config {}", manifest.config().digest()))?;
+
+            let mut layers = Vec::with_capacity(manifest.layers().len());
+            for layer_desc in manifest.layers() {
+                layers.push((layer_desc.clone(), client.list_layer(layer_desc, verify).await?));
+            }
+            let config_digest = manifest.config().digest().to_string();
+            anyhow::Ok((config, config_digest, manifest_digest, layers))
+        })?;
+        self.layers = ArchiveLayers::default();
+        self.layers.config = Some(DigestCheck::new("config", config_digest, &config.id));
+
+        let history = config.history();
+        let mut infos = Vec::with_capacity(layers.len());
+        let mut total_size = 0u64;
+
+        for (i, (layer_desc, listing)) in layers.into_iter().enumerate() {
+            let digest = config
+                .rootfs
+                .diff_ids
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| layer_desc.digest().to_string());
+            let size: u64 = listing.files.iter().map(|f| f.size).sum();
+            total_size += size;
+            infos.push(LayerInfo {
+                digest: digest.clone(),
+                created_by: history.created_by(i),
+                size,
+                changes: LayerChanges::default(),
+                compression: Some(listing.compression),
+                compressed_size: Some(listing.compressed_size),
+                files: Vec::new(),
+            });
+            if let Some(digests) = &listing.digests {
+                let expected_diff_id = config.rootfs.diff_ids.get(i).map(String::as_str);
+                let checks = digests.checks(Some(layer_desc.digest().as_ref()), expected_diff_id);
+                self.layers.checks.insert(digest.clone(), checks);
+            }
+            self.layers.files.insert(digest, listing.files);
+        }
+
+        let name = image.split('@').next().unwrap_or(image);
+        let (name, tag) = split_repo_tag(name);
+        // Other tags may point at the same manifest, but finding them would
+        // mean fetching every tag's manifest
+        let repo_tags = tag.iter().map(|tag| format!("{name}:{tag}")).collect();
+        let repo_digests = vec![format!("{name}@{manifest_digest}")];
+        let mut warnings = history.warnings;
+        warnings.append(&mut client.warnings);
+        Ok(ImageInfo {
+            name,
+            tag,
+            id: config.id,
+            repo_tags,
+            repo_digests,
+            created: config.created,
+            config: config.config,
+            os: config.os,
+            architecture: config.architecture,
+            variant: config.variant,
+            total_size,
+            layers: infos,
+            filesystem: Vec::new(),
+            efficiency: Efficiency::default(),
+            history: history.steps,
+            warnings,
+        })
+    }
+
+    fn list_files(&mut self, layer: &LayerInfo) -> Result<Vec<FileEntry>> {
+        self.layers
+            .files
+            .get(&layer.digest)
+            .cloned()
+            .with_context(|| format!("Layer {} not found in registry image", layer.digest))
+    }
+
+    fn enable_verify(&mut self) {
+        self.verify = true;
+    }
+
+    fn verify_layer(&mut self, layer: &LayerInfo) -> Result<Vec<DigestCheck>> {
+        self.layers.checks(layer)
+    }
+
+    fn verify_config(&mut self, _image: &str) -> Result<Vec<DigestCheck>> {
+        Ok(self.layers.config_checks())
+    }
+
+    fn select_platform(&mut self, platform: &Platform) {
+        self.platform = platform.clone();
+    }
+
+    fn platforms(&mut self, image: &str) -> Result<Vec<Platform>> {
+        let mut client = RegistryClient::new(Reference::parse(image)?);
+        self.runtime.block_on(client.fetch_platforms())
+    }
+}
+
+/// Look up `docker login` credentials for a registry in the Docker config
+/// (`$DOCKER_CONFIG/config.json`, default `~/.docker/config.json`).
+fn load_credentials(registry: &str, warnings: &mut Vec<String>) -> Option<Credentials> {
+    let dir = match std::env::var_os("DOCKER_CONFIG") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".docker"),
+    };
+    let data = std::fs::read(dir.join("config.json")).ok()?;
+    let config: DockerConfig = serde_json::from_slice(&data).ok()?;
+    config_credentials(&config, registry, warnings)
+}
+
+/// A registry's credentials: from the credential helper the config names
+/// for it (or for every registry), else from `auths`. A helper that fails
+/// is reported in `warnings`, since the registry's 401 alone wouldn't say
+/// why the pull went anonymous.
+fn config_credentials(config: &DockerConfig, registry: &str, warnings: &mut Vec<String>) -> Option<Credentials> {
+    let keys: &[&str] = if registry == DOCKER_HUB {
+        DOCKER_HUB_AUTH_KEYS
+    } else {
+        &[registry]
+    };
+    // Keys may be bare hosts or URLs like https://index.docker.io/v1/
+    let matches = |key: &str| {
+        let host = key
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .split('/')
+            .next()
+            .unwrap_or_default();
+        keys.contains(&host)
+    };
+
+    let helper = config
+        .cred_helpers
+        .iter()
+        .find(|(key, _)| matches(key))
+        .map(|(_, helper)| helper)
+        .or(config.creds_store.as_ref());
+    if let Some(helper) = helper {
+        let server_url = if registry == DOCKER_HUB { DOCKER_HUB_SERVER_URL } else { registry };
+        match run_credential_helper(helper, server_url) {
+            Ok(Some(credentials)) => return Some(credentials),
+            Ok(None) => {}
+            Err(e) => warnings.push(format!("Couldn't get credentials for {registry}, pulling anonymously: {e:#}")),
+        }
+    }
+
+    config.auths.iter().filter(|(key, _)| matches(key)).find_map(|(_, entry)| {
+        match (&entry.identitytoken, &entry.auth) {
+            (Some(token), _) => Some(Credentials::IdentityToken(token.clone())),
+            (None, Some(auth)) => Some(Credentials::Basic(auth.clone())),
+            (None, None) => None,
+        }
+    })
+}
+
+/// Ask `docker-credential-<helper>` for a server's credentials, or `None`
+/// if it has none stored.
+fn run_credential_helper(helper: &str, server_url: &str) -> Result<Option<Credentials>> {
+    let program = format!("docker-credential-{helper}");
+    let mut child = Command::new(&program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {program}"))?;
+    // Dropping stdin closes it, which ends the request
+    child
+        .stdin
+        .take()
+        .context("No stdin for the credential helper")?
+        .write_all(server_url.as_bytes())
+        .with_context(|| format!("Failed to write to {program}"))?;
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run {program}"))?;
+
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.contains("credentials not found") {
+            return Ok(None);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{program} failed: {}", format!("{stdout}{stderr}").trim());
+    }
+    let credentials: HelperCredentials = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("Failed to parse the output of {program}"))?;
+    Ok(Some(if credentials.username == IDENTITY_TOKEN_USERNAME {
+        Credentials::IdentityToken(credentials.secret)
+    } else {
+        Credentials::Basic(BASE64.encode(format!("{}:{}", credentials.username, credentials.secret)))
+    }))
+}
+
+/// Split a `WWW-Authenticate` header into its scheme and parameters,
+/// e.g. `Bearer realm="https://auth.example.com/token",service="example"`.
+fn parse_challenge(header: &str) -> (String, HashMap<String, String>) {
+    let header = header.trim();
+    let (scheme, rest) = header.split_once(' ').unwrap_or((header, ""));
+    let mut params = HashMap::new();
+    let mut chars = rest.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| *c == ',' || c.is_whitespace()).is_some() {}
+        let key: String = std::iter::from_fn(|| chars.next_if(|c| *c != '=')).collect();
+        if key.is_empty() || chars.next().is_none() {
+            break;
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => value.extend(chars.next()),
+                    c => value.push(c),
+                }
+            }
+        } else {
+            value.extend(std::iter::from_fn(|| chars.next_if(|c| *c != ',')));
+        }
+        params.insert(key.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+    (scheme.to_string(), params)
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    use flate2::write::GzEncoder;
+    use serde_json::json;
+
+    use super::*;
+    use crate::inspector::test_http::{self, Reply, Request};
+
+    const TOKEN: &str = "Bearer stand-in-token";
+
+    /// Every request made: (path, Authorization header)
+    type Requests = Arc<Mutex<Vec<(String, Option<String>)>>>;
+
+    fn sha256(data: &[u8]) -> String {
+        format!("sha256:{:x}", Sha256::digest(data))
+    }
+
+    /// A one-layer image, served behind a bearer token by a stand-in
+    /// registry that redirects blob downloads to another host name.
+    struct StandIn {
+        addr: String,
+        index_digest: String,
+        diff_id: String,
+        requests: Requests,
+    }
+
+    impl StandIn {
+        fn start() -> Self {
+            let mut tar = tar::Builder::new(Vec::new());
+            let mut header = tar::Header::new_gnu();
+            header.set_size(11);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, "app/hello", &b"hello world"[..]).unwrap();
+            let tar = tar.into_inner().unwrap();
+            let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            gz.write_all(&tar).unwrap();
+            let layer = gz.finish().unwrap();
+            let diff_id = sha256(&tar);
+
+            let config = json!({
+                "architecture": "amd64",
+                "os": "linux",
+                "rootfs": {"type": "layers", "diff_ids": [diff_id]},
+                "history": [{"created_by": "COPY hello /app/"}],
+            })
+            .to_string();
+            let manifest = json!({
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "config": {
+                    "mediaType": "application/vnd.oci.image.config.v1+json",
+                    "digest": sha256(config.as_bytes()),
+                    "size": config.len(),
+                },
+                "layers": [{
+                    "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                    "digest": sha256(&layer),
+                    "size": layer.len(),
+                }],
+            })
+            .to_string();
+            // The arm64 entry names a digest its manifest doesn't hash to
+            let platform_entry = |digest: String, architecture: &str| {
+                json!({
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "digest": digest,
+                    "size": manifest.len(),
+                    "platform": {"os": "linux", "architecture": architecture},
+                })
+            };
+            let index = json!({
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.index.v1+json",
+                "manifests": [
+                    platform_entry(sha256(b"something else"), "arm64"),
+                    platform_entry(sha256(manifest.as_bytes()), "amd64"),
+                ],
+            })
+            .to_string();
+            let index_digest = sha256(index.as_bytes());
+
+            let requests: Requests = Arc::default();
+            let recorded = requests.clone();
+            let addr = Arc::new(Mutex::new(String::new()));
+            let own_addr = addr.clone();
+            let handler = move |request: &Request| {
+                let authorization = request.header("Authorization").map(String::from);
+                recorded.lock().unwrap().push((request.path.clone(), authorization.clone()));
+                let addr = own_addr.lock().unwrap().clone();
+                // Same server, but a different host as far as the client knows
+                let cdn = addr.replace("127.0.0.1", "localhost");
+
+                if request.method == "POST" && request.path == "/token" {
+                    let form = String::from_utf8_lossy(&request.body);
+                    assert!(form.contains("grant_type=refresh_token"), "{form}");
+                    assert!(form.contains("refresh_token=stand-in-refresh"), "{form}");
+                    assert!(form.contains("scope=repository%3Aapp%3Apull"), "{form}");
+                    return Reply::json(&json!({"access_token": "stand-in-token"}));
+                }
+                if let Some(query) = request.path.strip_prefix("/token?") {
+                    assert!(query.contains("scope=repository%3Aapp%3Apull"), "{query}");
+                    assert!(query.contains("service=stand-in"), "{query}");
+                    return Reply::json(&json!({"token": "stand-in-token"}));
+                }
+                if let Some(blob) = request.path.strip_prefix("/cdn/") {
+                    return match blob {
+                        "config" => Reply::ok(config.clone()),
+                        _ => Reply::ok(layer.clone()),
+                    };
+                }
+                if authorization.as_deref() != Some(TOKEN) {
+                    let challenge =
+                        format!("Bearer realm=\"http://{addr}/token\",service=\"stand-in\",scope=\"repository:app:pull\"");
+                    return Reply::status(401).header("WWW-Authenticate", challenge);
+                }
+
+                let path = request.path.as_str();
+                if path == "/v2/app/manifests/1.0" {
+                    Reply::ok(index.clone())
+                } else if path.starts_with("/v2/app/manifests/sha256:") {
+                    Reply::ok(manifest.clone())
+                } else if path == format!("/v2/app/blobs/{}", sha256(config.as_bytes())) {
+                    Reply::status(307).header("Location", format!("http://{cdn}/cdn/config"))
+                } else if path.starts_with("/v2/app/blobs/") {
+                    Reply::status(307).header("Location", format!("http://{cdn}/cdn/layer"))
+                } else if path == "/v2/app/tags/list" {
+                    Reply::json(&json!({"name": "app", "tags": ["1.0", "1.1", "latest"]}))
+                } else {
+                    Reply::status(404).body(json!({"errors": [{"message": "manifest unknown"}]}).to_string())
+                }
+            };
+            *addr.lock().unwrap() = test_http::serve_tcp(handler);
+            let addr = addr.lock().unwrap().clone();
+
+            Self {
+                addr,
+                index_digest,
+                diff_id,
+                requests,
+            }
+        }
+
+        fn inspector(&self, platform: &str) -> RegistryInspector {
+            let mut inspector = RegistryInspector::new().unwrap();
+            inspector.select_platform(&platform.parse().unwrap());
+            inspector
+        }
+    }
+
+    #[test]
+    fn inspect_behind_token_auth_and_redirects() {
+        let registry = StandIn::start();
+        let mut inspector = registry.inspector("linux/amd64");
+        let image = format!("{}/app:1.0", registry.addr);
+
+        let info = inspector.inspect(&image).unwrap();
+        assert_eq!(info.tag.as_deref(), Some("1.0"));
+        assert_eq!(info.repo_digests, [format!("{}/app@{}", registry.addr, registry.index_digest)]);
+        assert_eq!(info.layers.len(), 1);
+        assert_eq!(info.layers[0].digest, registry.diff_id);
+        assert_eq!(info.layers[0].created_by.as_deref(), Some("COPY hello /app/"));
+        let files = inspector.list_files(&info.layers[0]).unwrap();
+        assert_eq!(files.iter().map(|f| f.path.to_str().unwrap()).collect::<Vec<_>>(), ["app/hello"]);
+        assert!(inspector.verify_config(&image).unwrap().iter().all(DigestCheck::passed));
+
+        let requests = registry.requests.lock().unwrap();
+        let paths: Vec<&str> = requests.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths.iter().filter(|p| p.starts_with("/token?")).count(), 1, "{paths:?}");
+        for (path, authorization) in requests.iter() {
+            if path.starts_with("/cdn/") {
+                assert_eq!(*authorization, None, "credentials leaked to the redirect target for {path}");
+            }
+        }
+        // Only the first request went out before the challenge was answered
+        let unauthenticated = requests.iter().filter(|(p, a)| p.starts_with("/v2/") && a.is_none()).count();
+        assert_eq!(unauthenticated, 1, "{paths:?}");
+    }
+
+    #[test]
+    fn manifest_must_match_its_digest() {
+        let registry = StandIn::start();
+        let mut inspector = registry.inspector("linux/arm64");
+        let err = inspector.inspect(&format!("{}/app:1.0", registry.addr)).unwrap_err();
+        assert!(format!("{err:#}").contains("hashes to"), "{err:#}");
+    }
+
+    #[test]
+    fn missing_tag_suggests_close_ones() {
+        let registry = StandIn::start();
+        let mut inspector = registry.inspector("linux/amd64");
+        let err = inspector.inspect(&format!("{}/app:1.2", registry.addr)).unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.contains("Tag '1.2' not found"), "{message}");
+        assert!(message.contains("did you mean"), "{message}");
+        assert!(message.contains("app:1.1"), "{message}");
+        assert!(message.contains("manifest unknown"), "{message}");
+    }
+
+    #[test]
+    fn identity_token_is_traded_for_an_access_token() {
+        let registry = StandIn::start();
+        let mut client = RegistryClient::new(Reference::parse(&format!("{}/app:1.0", registry.addr)).unwrap());
+        client.credentials = Some(Credentials::IdentityToken("stand-in-refresh".to_string()));
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let tags = runtime
+            .block_on(async { client.get("/v2/app/tags/list", &[]).await?.bytes().await })
+            .unwrap();
+        assert!(String::from_utf8_lossy(&tags).contains("1.1"));
+    }
+
+    #[test]
+    fn credentials_from_the_docker_config() {
+        let config: DockerConfig = serde_json::from_value(json!({
+            "auths": {
+                "https://index.docker.io/v1/": {"auth": "aHViOnB3"},
+                "ghcr.io": {"identitytoken": "refresh"},
+                "quay.io": {},
+            },
+            "credHelpers": {"quay.io": "peel-test-missing"},
+        }))
+        .unwrap();
+        let mut warnings = Vec::new();
+        assert_eq!(
+            config_credentials(&config, DOCKER_HUB, &mut warnings),
+            Some(Credentials::Basic("aHViOnB3".to_string()))
+        );
+        assert_eq!(
+            config_credentials(&config, "ghcr.io", &mut warnings),
+            Some(Credentials::IdentityToken("refresh".to_string()))
+        );
+        assert!(warnings.is_empty(), "{warnings:?}");
+
+        // A helper that can't run leaves the pull anonymous, but says so
+        assert_eq!(config_credentials(&config, "quay.io", &mut warnings), None);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("docker-credential-peel-test-missing"), "{warnings:?}");
+    }
+}
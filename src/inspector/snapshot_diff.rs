@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::layer_dir::{self, SeenInodes};
+use super::FileEntry;
+
+/// Work out what a layer changed from two full snapshots of the filesystem:
+/// `upper` (the layer) and `lower` (its parent, `None` for a base layer).
+///
+/// Storage drivers like vfs and btrfs keep every layer as a complete copy,
+/// so the layer's own files have to be recovered by comparison. Files that
+/// are new or differ in size, mtime, mode, ownership or link target are
+/// reported as-is; paths that disappeared are reported as `.wh.` whiteouts,
+/// the same way they'd appear in the layer tarball.
+pub fn diff_dirs(lower: Option<&Path>, upper: &Path) -> Result<Vec<FileEntry>> {
+    let mut entries = Vec::new();
+    walk(lower, upper, Path::new(""), &mut SeenInodes::new(), &mut entries)?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+fn walk(
+    lower: Option<&Path>,
+    upper: &Path,
+    relative: &Path,
+    seen_inodes: &mut SeenInodes,
+    entries: &mut Vec<FileEntry>,
+) -> Result<()> {
+    let mut seen = HashSet::new();
+
+    for entry in fs::read_dir(upper).with_context(|| format!("Failed to read {}", upper.display()))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let path = entry.path();
+        let metadata = fs::symlink_metadata(&path)?;
+        let rel = relative.join(&name);
+        let lower_path = lower.map(|l| l.join(&name));
+        let lower_meta = lower_path.as_deref().and_then(|p| fs::symlink_metadata(p).ok());
+        seen.insert(name);
+
+        let changed = match (&lower_meta, &lower_path) {
+            // A directory's size changes with its entries, which are
+            // compared one by one below
+            (Some(old), Some(_)) if old.is_dir() && metadata.is_dir() => is_dir_modified(old, &metadata),
+            (Some(old), Some(old_path)) => is_modified(old, old_path, &metadata, &path),
+            _ => true,
+        };
+        if changed {
+            entries.push(layer_dir::entry_from_disk(&path, rel.clone(), &metadata, seen_inodes)?);
+        }
+
+        if metadata.is_dir() {
+            // Only recurse into the parent's copy if it's also a directory;
+            // otherwise everything below is new.
+            let lower_dir = lower_path.filter(|_| lower_meta.as_ref().is_some_and(Metadata::is_dir));
+            walk(lower_dir.as_deref(), &path, &rel, seen_inodes, entries)?;
+        }
+    }
+
+    let Some(lower) = lower else {
+        return Ok(());
+    };
+    for entry in fs::read_dir(lower).with_context(|| format!("Failed to read {}", lower.display()))? {
+        let name = entry?.file_name();
+        if seen.contains(&name) {
+            continue;
+        }
+        let mut whiteout = OsString::from(".wh.");
+        whiteout.push(&name);
+        entries.push(FileEntry::whiteout(relative.join(whiteout)));
+    }
+    Ok(())
+}
+
+fn is_dir_modified(old: &Metadata, new: &Metadata) -> bool {
+    old.mtime() != new.mtime()
+        || old.mtime_nsec() != new.mtime_nsec()
+        || old.mode() != new.mode()
+        || old.uid() != new.uid()
+        || old.gid() != new.gid()
+}
+
+fn is_modified(old: &Metadata, old_path: &Path, new: &Metadata, new_path: &Path) -> bool {
+    if old.file_type() != new.file_type()
+        || old.len() != new.len()
+        || old.mtime() != new.mtime()
+        || old.mtime_nsec() != new.mtime_nsec()
+        || old.mode() != new.mode()
+        || old.uid() != new.uid()
+        || old.gid() != new.gid()
+        || old.rdev() != new.rdev()
+    {
+        return true;
+    }
+    if new.file_type().is_symlink() {
+        return fs::read_link(old_path).ok() != fs::read_link(new_path).ok();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{File, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    /// A scratch directory removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("peel-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Create `dirs/` and `files` under `root`.
+    fn tree(root: &Path, dirs: &[&str], files: &[&str]) {
+        for dir in dirs {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in files {
+            fs::write(root.join(file), "contents").unwrap();
+        }
+    }
+
+    /// Give everything under `root` the same mode and mtime, children
+    /// first so creating them doesn't bump their directory's mtime again.
+    fn settle(root: &Path, mtime: SystemTime) {
+        for entry in fs::read_dir(root).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                settle(&path, mtime);
+            } else {
+                fs::set_permissions(&path, Permissions::from_mode(0o644)).unwrap();
+                File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+            }
+        }
+        File::open(root).unwrap().set_modified(mtime).unwrap();
+    }
+
+    #[test]
+    fn diff_against_parent_snapshot() {
+        let tmp = TempDir::new("snapshot-diff");
+        let (lower, upper) = (tmp.0.join("lower"), tmp.0.join("upper"));
+        tree(
+            &lower,
+            &["etc", "olddir/sub", "replaced"],
+            &["etc/keep", "etc/touched", "etc/chmod", "gone", "olddir/a", "olddir/sub/b", "replaced/c"],
+        );
+        tree(&upper, &["etc"], &["etc/keep", "etc/touched", "etc/chmod", "etc/new", "replaced"]);
+
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        settle(&lower, mtime);
+        settle(&upper, mtime);
+        let touched = File::options().write(true).open(upper.join("etc/touched")).unwrap();
+        touched.set_modified(mtime + Duration::from_secs(60)).unwrap();
+        fs::set_permissions(upper.join("etc/chmod"), Permissions::from_mode(0o600)).unwrap();
+
+        let entries = diff_dirs(Some(&lower), &upper).unwrap();
+        let listed: Vec<(&str, bool)> = entries
+            .iter()
+            .map(|e| (e.path.to_str().unwrap(), e.is_whiteout))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                (".wh.gone", true),
+                (".wh.olddir", true),
+                ("etc/chmod", false),
+                ("etc/new", false),
+                ("etc/touched", false),
+                ("replaced", false),
+            ]
+        );
+        let chmod = entries.iter().find(|e| e.path == Path::new("etc/chmod")).unwrap();
+        assert_eq!(chmod.mode & 0o7777, 0o600);
+        let replaced = entries.iter().find(|e| e.path == Path::new("replaced")).unwrap();
+        assert_eq!(replaced.kind, crate::inspector::FileKind::File);
+    }
+
+    #[test]
+    fn base_layer_lists_everything() {
+        let tmp = TempDir::new("snapshot-base");
+        tree(&tmp.0, &["etc"], &["etc/passwd", "root-file"]);
+
+        let entries = diff_dirs(None, &tmp.0).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["etc", "etc/passwd", "root-file"]);
+        assert!(entries.iter().all(|e| !e.is_whiteout));
+    }
+}
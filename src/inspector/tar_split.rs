@@ -0,0 +1,83 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::layer_tar;
+
+/// Entry holding the path and size of a file whose contents were unpacked.
+const FILE_TYPE: u8 = 1;
+
+/// Entry holding raw tar bytes (headers, padding, end-of-archive marker).
+const SEGMENT_TYPE: u8 = 2;
+
+/// One line of a tar-split stream.
+#[derive(Deserialize)]
+struct Entry {
+    #[serde(rename = "type")]
+    kind: u8,
+    #[serde(default)]
+    name: Option<String>,
+    /// Base64 file name, used instead of `name` when it isn't UTF-8
+    #[serde(default)]
+    name_raw: Option<String>,
+    #[serde(default)]
+    size: u64,
+    /// Base64 tar bytes for segments (a checksum for files)
+    #[serde(default)]
+    payload: Option<String>,
+}
+
+/// Rebuild a layer's original tar stream from its tar-split metadata
+/// (`tar-split.json.gz`, recorded when the layer was unpacked) and the file
+/// contents under `dir`, and return its `sha256:` digest. This is the diff
+/// ID the layer was pulled with, unless its files have changed since.
+pub fn diff_id(tar_split: &Path, dir: &Path) -> Result<String> {
+    let file = File::open(tar_split).with_context(|| format!("Failed to open {}", tar_split.display()))?;
+    let mut hasher = Sha256::new();
+
+    for line in BufReader::new(GzDecoder::new(file)).lines() {
+        let line = line.with_context(|| format!("Failed to read {}", tar_split.display()))?;
+        if line.is_empty() {
+            continue;
+        }
+        let entry: Entry =
+            serde_json::from_str(&line).with_context(|| format!("Failed to parse {}", tar_split.display()))?;
+
+        match entry.kind {
+            SEGMENT_TYPE => {
+                let payload = BASE64
+                    .decode(entry.payload.unwrap_or_default())
+                    .with_context(|| format!("Invalid segment in {}", tar_split.display()))?;
+                hasher.update(&payload);
+            }
+            FILE_TYPE if entry.size > 0 => {
+                let path = dir.join(layer_tar::normalize_path(&entry_path(&entry)?));
+                let mut file =
+                    File::open(&path).with_context(|| format!("Layer file {} is missing", path.display()))?;
+                io::copy(&mut file, &mut hasher).with_context(|| format!("Failed to read {}", path.display()))?;
+            }
+            _ => {}
+        }
+    }
+    Ok(format!("sha256:{:x}", hasher.finalize()))
+}
+
+/// Path of a file entry, as recorded in the original tar.
+fn entry_path(entry: &Entry) -> Result<PathBuf> {
+    if let Some(name) = &entry.name {
+        return Ok(PathBuf::from(name));
+    }
+    let raw = BASE64
+        .decode(entry.name_raw.as_deref().unwrap_or_default())
+        .context("Invalid file name in tar-split")?;
+    Ok(PathBuf::from(OsString::from_vec(raw)))
+}
@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use flate2::read::MultiGzDecoder;
+use oci_spec::image::Descriptor;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{layer_tar, timestamp, xattr_value, FileEntry, FileKind};
+
+/// Annotation eStargz layers carry with the digest of their TOC JSON.
+const ESTARGZ_TOC_DIGEST: &str = "containerd.io/snapshot/stargz/toc.digest";
+
+/// Annotation zstd:chunked layers carry with `offset:length:uncompressed:type`
+/// of their compressed manifest.
+const ZSTD_CHUNKED_MANIFEST_POSITION: &str = "io.github.containers.zstd-chunked.manifest-position";
+
+/// Annotation zstd:chunked layers carry with the digest of their compressed manifest.
+const ZSTD_CHUNKED_MANIFEST_CHECKSUM: &str = "io.github.containers.zstd-chunked.manifest-checksum";
+
+/// eStargz footer: an empty gzip member whose extra field holds the TOC offset.
+pub const ESTARGZ_FOOTER_SIZE: u64 = 51;
+
+/// Footer of the original stargz format, which eStargz readers still accept.
+const LEGACY_STARGZ_FOOTER_SIZE: u64 = 47;
+
+/// Name of the TOC entry inside an eStargz blob's final tar.
+const ESTARGZ_TOC_NAME: &str = "stargz.index.json";
+
+/// Where a seekable layer keeps its table of contents.
+pub enum TocLocation {
+    /// In a gzip member before the footer; the footer gives its offset
+    Estargz { toc_digest: String },
+    /// A zstd frame at a known position
+    ZstdChunked { offset: u64, length: u64, checksum: String },
+}
+
+impl TocLocation {
+    /// Recognise eStargz and zstd:chunked layers by their descriptor annotations.
+    pub fn of(descriptor: &Descriptor) -> Option<Self> {
+        let annotations = descriptor.annotations().as_ref()?;
+        if let Some(position) = annotations.get(ZSTD_CHUNKED_MANIFEST_POSITION) {
+            let mut fields = position.split(':').map(|f| f.parse::<u64>().ok());
+            let (Some(Some(offset)), Some(Some(length))) = (fields.next(), fields.next()) else {
+                return None;
+            };
+            let checksum = annotations.get(ZSTD_CHUNKED_MANIFEST_CHECKSUM)?.clone();
+            return Some(TocLocation::ZstdChunked { offset, length, checksum });
+        }
+        annotations
+            .get(ESTARGZ_TOC_DIGEST)
+            .map(|digest| TocLocation::Estargz { toc_digest: digest.clone() })
+    }
+}
+
+/// The TOC JSON shared by eStargz and zstd:chunked.
+#[derive(Deserialize)]
+struct Toc {
+    #[serde(default)]
+    entries: Vec<TocEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TocEntry {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    mode: u32,
+    #[serde(default)]
+    uid: u64,
+    #[serde(default)]
+    gid: u64,
+    /// RFC 3339 timestamp
+    #[serde(default)]
+    modtime: Option<String>,
+    #[serde(default)]
+    link_name: Option<String>,
+    /// Values are base64
+    #[serde(default)]
+    xattrs: BTreeMap<String, String>,
+}
+
+/// Find the TOC in the last `ESTARGZ_FOOTER_SIZE` bytes of an eStargz blob.
+/// Returns the TOC's offset and the length of the footer that follows it.
+pub fn estargz_toc_offset(footer: &[u8]) -> Option<(u64, u64)> {
+    let parse = |payload: &[u8]| {
+        let (hex, magic) = payload.split_at(16);
+        if magic != b"STARGZ" {
+            return None;
+        }
+        u64::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+    };
+
+    let footer = footer.get(footer.len().checked_sub(ESTARGZ_FOOTER_SIZE as usize)?..)?;
+    // eStargz wraps the payload in an `SG` extra subfield after the 10-byte
+    // gzip header and XLEN; legacy stargz puts it straight after XLEN. In
+    // the last 51 bytes a legacy payload lands where an eStargz one would,
+    // so only the subfield ID tells them apart.
+    if &footer[12..14] == b"SG"
+        && let Some(offset) = parse(&footer[16..38])
+    {
+        return Some((offset, ESTARGZ_FOOTER_SIZE));
+    }
+    let legacy = &footer[(ESTARGZ_FOOTER_SIZE - LEGACY_STARGZ_FOOTER_SIZE) as usize..];
+    parse(&legacy[12..34]).map(|offset| (offset, LEGACY_STARGZ_FOOTER_SIZE))
+}
+
+/// List files from an eStargz TOC section: a gzip member holding a tar
+/// with a single `stargz.index.json` entry.
+pub fn read_estargz_toc(data: &[u8], toc_digest: &str) -> Result<Vec<FileEntry>> {
+    let mut archive = tar::Archive::new(MultiGzDecoder::new(data));
+    for entry in archive.entries().context("Failed to read eStargz TOC")? {
+        let mut entry = entry.context("Failed to read eStargz TOC")?;
+        if entry.path()?.as_ref() != Path::new(ESTARGZ_TOC_NAME) {
+            continue;
+        }
+        let mut json = Vec::new();
+        entry.read_to_end(&mut json)?;
+
+        let actual = format!("sha256:{:x}", Sha256::digest(&json));
+        if actual != toc_digest {
+            anyhow::bail!("eStargz TOC digest mismatch: expected {toc_digest}, got {actual}");
+        }
+        return entries_from_toc(&json);
+    }
+    anyhow::bail!("eStargz blob has no {ESTARGZ_TOC_NAME}")
+}
+
+/// List files from a zstd:chunked manifest (a zstd-compressed TOC), once
+/// the compressed bytes match `checksum`.
+pub fn read_zstd_chunked_manifest(data: &[u8], checksum: &str) -> Result<Vec<FileEntry>> {
+    let actual = format!("sha256:{:x}", Sha256::digest(data));
+    if actual != checksum {
+        anyhow::bail!("zstd:chunked manifest checksum mismatch: expected {checksum}, got {actual}");
+    }
+    let json = zstd::stream::decode_all(data).context("Failed to decompress zstd:chunked manifest")?;
+    entries_from_toc(&json)
+}
+
+/// Turn TOC entries into the same listing the tar reader would produce:
+/// no chunk continuations, sizes only for regular files.
+fn entries_from_toc(json: &[u8]) -> Result<Vec<FileEntry>> {
+    let toc: Toc = serde_json::from_slice(json).context("Failed to parse layer TOC")?;
+    let mut entries: Vec<FileEntry> = toc
+        .entries
+        .into_iter()
+        .filter(|e| e.kind != "chunk")
+        .map(|e| {
+            let path = layer_tar::normalize_path(Path::new(&e.name));
+            let is_whiteout = path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with(".wh."));
+            let kind = match e.kind.as_str() {
+                "dir" => FileKind::Dir,
+                "symlink" => FileKind::Symlink,
+                "hardlink" => FileKind::Hardlink,
+                "char" => FileKind::CharDevice,
+                "block" => FileKind::BlockDevice,
+                "fifo" => FileKind::Fifo,
+                _ => FileKind::File,
+            };
+            let link_target = e.link_name.filter(|l| !l.is_empty()).map(|l| match kind {
+                FileKind::Hardlink => layer_tar::normalize_path(Path::new(&l)),
+                _ => PathBuf::from(l),
+            });
+            let xattrs = e
+                .xattrs
+                .into_iter()
+                .map(|(name, value)| {
+                    let value = BASE64.decode(&value).map_or(value, |raw| xattr_value(&raw));
+                    (name, value)
+                })
+                .collect();
+            FileEntry {
+                path,
+                size: if is_whiteout || e.kind != "reg" { 0 } else { e.size },
+                is_whiteout,
+                kind,
+                mode: e.mode & 0o7777,
+                uid: e.uid,
+                gid: e.gid,
+                mtime: e.modtime.as_deref().and_then(timestamp::parse_rfc3339).unwrap_or(0),
+                link_target,
+                xattrs,
+                change: None,
+                overwritten_in: None,
+                deleted_in: None,
+            }
+        })
+        // Like the tar reader, leave out the layer's root (`./`)
+        .filter(|e| !e.path.as_os_str().is_empty())
+        .collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::{Compression, GzBuilder};
+
+    use super::*;
+
+    /// An empty gzip member with `extra` as its FEXTRA field, the way
+    /// stargz writers produce their footer.
+    fn gzip_footer(extra: Vec<u8>) -> Vec<u8> {
+        GzBuilder::new()
+            .extra(extra)
+            .write(Vec::new(), Compression::none())
+            .finish()
+            .unwrap()
+    }
+
+    fn toc_json() -> Vec<u8> {
+        serde_json::json!({
+            "version": 1,
+            "entries": [
+                {"name": "usr/", "type": "dir", "mode": 493},
+                {"name": "usr/bin/tool", "type": "reg", "size": 9000, "mode": 33261,
+                 "modtime": "2024-01-02T03:04:05Z"},
+                {"name": "usr/bin/tool", "type": "chunk", "offset": 4096, "chunkOffset": 4096},
+                {"name": "usr/bin/alias", "type": "symlink", "linkName": "tool"},
+                {"name": "etc/.wh.old", "type": "reg", "size": 0},
+            ],
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn assert_toc_entries(entries: &[FileEntry]) {
+        let listed: Vec<(&str, FileKind, u64)> = entries
+            .iter()
+            .map(|e| (e.path.to_str().unwrap(), e.kind, e.size))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("etc/.wh.old", FileKind::File, 0),
+                ("usr", FileKind::Dir, 0),
+                ("usr/bin/alias", FileKind::Symlink, 0),
+                ("usr/bin/tool", FileKind::File, 9000),
+            ]
+        );
+        assert!(entries[0].is_whiteout);
+        assert_eq!(entries[3].mode, 0o755);
+        assert_eq!(entries[3].mtime, 1_704_164_645);
+        assert_eq!(entries[2].link_target.as_deref(), Some(Path::new("tool")));
+    }
+
+    #[test]
+    fn estargz_footer() {
+        let mut extra = b"SG".to_vec();
+        extra.extend_from_slice(&22u16.to_le_bytes());
+        extra.extend_from_slice(b"00000000000a1b2cSTARGZ");
+        let footer = gzip_footer(extra);
+        assert_eq!(footer.len() as u64, ESTARGZ_FOOTER_SIZE);
+        assert_eq!(estargz_toc_offset(&footer), Some((0xa1b2c, ESTARGZ_FOOTER_SIZE)));
+    }
+
+    #[test]
+    fn legacy_stargz_footer() {
+        let footer = gzip_footer(b"0000000000001234STARGZ".to_vec());
+        assert_eq!(footer.len() as u64, LEGACY_STARGZ_FOOTER_SIZE);
+        // The registry fetches the last 51 bytes whatever the footer's size
+        let mut tail = b"toc!".to_vec();
+        tail.extend_from_slice(&footer);
+        assert_eq!(estargz_toc_offset(&tail), Some((0x1234, LEGACY_STARGZ_FOOTER_SIZE)));
+    }
+
+    #[test]
+    fn not_a_stargz_footer() {
+        assert_eq!(estargz_toc_offset(&[0; 51]), None);
+        assert_eq!(estargz_toc_offset(&[0; 20]), None);
+    }
+
+    #[test]
+    fn estargz_toc_digest() {
+        let json = toc_json();
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, ESTARGZ_TOC_NAME, json.as_slice()).unwrap();
+        let data = tar.into_inner().unwrap().finish().unwrap();
+
+        let digest = format!("sha256:{:x}", Sha256::digest(&json));
+        assert_toc_entries(&read_estargz_toc(&data, &digest).unwrap());
+
+        let wrong = format!("sha256:{}", "0".repeat(64));
+        let err = read_estargz_toc(&data, &wrong).unwrap_err();
+        assert!(err.to_string().contains("digest mismatch"), "{err}");
+    }
+
+    #[test]
+    fn zstd_chunked_manifest() {
+        let mut data = Vec::new();
+        let mut encoder = zstd::stream::write::Encoder::new(&mut data, 0).unwrap();
+        encoder.write_all(&toc_json()).unwrap();
+        encoder.finish().unwrap();
+
+        let checksum = format!("sha256:{:x}", Sha256::digest(&data));
+        assert_toc_entries(&read_zstd_chunked_manifest(&data, &checksum).unwrap());
+
+        let err = read_zstd_chunked_manifest(&data[1..], &checksum).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
+    }
+}
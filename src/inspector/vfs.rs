@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use super::layerdb::{self, LayerDb};
+use super::{snapshot_diff, DigestCheck, FileEntry, ImageInfo, Inspector, LayerInfo, StoredImage};
+
+/// Reads layers from Docker's vfs storage driver. vfs has no copy-on-write,
+/// so each `vfs/dir/<cache-id>` is a full copy of the filesystem up to that
+/// layer; a layer's files are found by diffing it against its parent.
+pub struct VfsInspector {
+    storage_root: PathBuf,
+    layerdb: LayerDb,
+}
+
+impl VfsInspector {
+    pub fn new(storage_root: PathBuf) -> Self {
+        Self {
+            layerdb: LayerDb::new(&storage_root, "vfs"),
+            storage_root,
+        }
+    }
+
+    fn layer_dir(&self, chain_id: &str) -> Result<PathBuf> {
+        let cache_id = self.layerdb.get_cache_id(chain_id)?;
+        let dir = self.storage_root.join("vfs/dir").join(cache_id);
+        if !dir.is_dir() {
+            anyhow::bail!("Layer directory not found: {}", dir.display());
+        }
+        Ok(dir)
+    }
+
+    /// A layer's own files, diffed against its parent's directory.
+    fn layer_files(&self, chain_id: &str) -> Result<Vec<FileEntry>> {
+        let dir = self.layer_dir(chain_id)?;
+        let parent_dir = match self.layerdb.get_parent(chain_id)? {
+            Some(parent) => Some(self.layer_dir(&parent)?),
+            None => None,
+        };
+        snapshot_diff::diff_dirs(parent_dir.as_deref(), &dir)
+    }
+}
+
+impl Inspector for VfsInspector {
+    fn inspect(&mut self, image: &str) -> Result<ImageInfo> {
+        self.layerdb.inspect(image)
+    }
+
+    fn list_images(&mut self) -> Result<Vec<StoredImage>> {
+        self.layerdb.list_images(|chain_id| self.layer_files(chain_id))
+    }
+
+    fn container_layer(&mut self, container_id: &str) -> Result<LayerInfo> {
+        // A full copy of the container's filesystem; its changes are the
+        // difference from the init layer (or the image, if it has none)
+        let mount = self.layerdb.get_mount(container_id)?;
+        let dir = self.storage_root.join("vfs/dir").join(&mount.mount_id);
+        let base = match (mount.init_id, mount.parent) {
+            (Some(init_id), _) => Some(self.storage_root.join("vfs/dir").join(init_id)),
+            (None, Some(parent)) => Some(self.layer_dir(&parent)?),
+            (None, None) => None,
+        };
+        let files = snapshot_diff::diff_dirs(base.as_deref(), &dir)?;
+        Ok(layerdb::container_layer(container_id, files))
+    }
+
+    fn list_files(&mut self, layer: &LayerInfo) -> Result<Vec<FileEntry>> {
+        self.layer_files(&layer.digest)
+    }
+
+    fn verify_layer(&mut self, layer: &LayerInfo) -> Result<Vec<DigestCheck>> {
+        // Each directory holds the whole filesystem as of its layer, so
+        // the layer's own files read back as they were unpacked
+        self.layerdb.verify_layer(&layer.digest, &self.layer_dir(&layer.digest)?)
+    }
+
+    fn verify_config(&mut self, image: &str) -> Result<Vec<DigestCheck>> {
+        self.layerdb.verify_config(image)
+    }
+}
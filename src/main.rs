@@ -24,6 +24,10 @@ struct Cli {
     #[arg(long, global = true)]
     use_oci: bool,
 
+    /// Fetch the image from its registry instead of a local runtime
+    #[arg(long, global = true)]
+    remote: bool,
+
     /// Disable the interactive web report
     #[arg(long, global = true)]
     no_web: bool,
@@ -32,10 +36,26 @@ struct Cli {
     #[arg(long, global = true)]
     no_sudo: bool,
 
+    /// Platform to inspect in multi-platform images (os/arch[/variant], default: this machine's)
+    #[arg(long, global = true, value_name = "PLATFORM")]
+    platform: Option<inspector::Platform>,
+
+    /// Inspect every platform of a multi-platform image and compare them
+    #[arg(long, global = true, conflicts_with = "platform")]
+    all_platforms: bool,
+
+    /// Check every layer against the image's recorded digests (diff IDs, blob digests, chain IDs)
+    #[arg(long, global = true)]
+    verify: bool,
+
+    /// List the files each layer added, modified and deleted (text output)
+    #[arg(long, global = true)]
+    changes: bool,
+
     #[command(subcommand)]
     command: Option<Commands>,
 
-    /// Image name or path to a tar archive (shorthand for `peel inspect <image>`)
+    /// Image name, archive (`-` for stdin) or registry://<reference> (shorthand for `peel inspect <image>`)
     image: Option<String>,
 }
 
@@ -43,10 +63,25 @@ struct Cli {
 enum Commands {
     /// Inspect layers of a container image
     Inspect {
-        /// Image name or path to a tar archive
+        /// Image name, path to an archive (`-` for stdin), or registry://<reference>
+        image: String,
+    },
+
+    /// Report space wasted on files that later layers overwrite or delete
+    Analyze {
+        /// Image name, path to an archive (`-` for stdin), or registry://<reference>
         image: String,
     },
 
+    /// List images in each detected runtime's storage
+    Images,
+
+    /// Show what a Docker container changed on top of its image
+    Container {
+        /// Container name or ID (prefix)
+        container: String,
+    },
+
     /// Detect installed container runtimes
     Probe,
 }
@@ -67,8 +102,40 @@ fn main() -> Result<()> {
     }
 
     if let Some(image) = &image_to_inspect {
-        let web = !cli.no_web && cli.json.is_none();
-        cmd::inspect::run(image, cli.use_oci, cli.json.as_deref(), cli.runtime, web, cli.no_sudo)?;
+        let web = !cli.no_web && cli.json.is_none() && !cli.all_platforms;
+        cmd::inspect::run(
+            image,
+            cmd::inspect::InspectOptions {
+                use_oci: cli.use_oci,
+                remote: cli.remote,
+                json: cli.json.as_deref(),
+                runtime: cli.runtime,
+                web,
+                no_sudo: cli.no_sudo,
+                platform: cli.platform,
+                all_platforms: cli.all_platforms,
+                verify: cli.verify,
+                changes: cli.changes,
+            },
+        )?;
+    } else if let Some(Commands::Analyze { image }) = &cli.command {
+        cmd::analyze::run(
+            image,
+            cmd::analyze::AnalyzeOptions {
+                use_oci: cli.use_oci,
+                remote: cli.remote,
+                json: cli.json.as_deref(),
+                runtime: cli.runtime,
+                no_sudo: cli.no_sudo,
+                platform: cli.platform,
+                all_platforms: cli.all_platforms,
+                verify: cli.verify,
+            },
+        )?;
+    } else if matches!(cli.command, Some(Commands::Images)) {
+        cmd::images::run(cli.json.is_some(), cli.runtime, cli.no_sudo)?;
+    } else if let Some(Commands::Container { container }) = &cli.command {
+        cmd::container::run(container, cli.json.as_deref(), cli.runtime, cli.no_sudo)?;
     } else if matches!(cli.command, Some(Commands::Probe)) {
         cmd::probe::run(cli.json.is_some(), cli.runtime)?;
     }
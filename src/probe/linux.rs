@@ -1,6 +1,8 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use serde::Deserialize;
 
 use super::common::{check_daemon, check_read_access, command_output, find_binary};
 use super::{ProbeResult, RuntimeInfo, RuntimeKind, StorageDriver, RUNTIME_PREFERENCE};
@@ -26,19 +28,43 @@ pub fn probe() -> Result<ProbeResult> {
     Ok(ProbeResult { runtimes, default })
 }
 
+/// The parts of `docker info` the probe reads.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DockerInfo {
+    #[serde(default)]
+    driver: String,
+    /// (name, value) pairs describing the driver
+    #[serde(default)]
+    driver_status: Option<Vec<(String, String)>>,
+}
+
+/// A graph driver's `image/<driver>/repositories.json`.
+#[derive(Deserialize)]
+struct Repositories {
+    /// Tags and digests by repository name
+    #[serde(rename = "Repositories", default)]
+    repositories: HashMap<String, HashMap<String, String>>,
+}
+
 fn detect_docker() -> Option<RuntimeInfo> {
     let binary_path = find_binary("docker")?;
 
     // `docker info` needs docker group or root. Fall back to checking
     // if the daemon socket exists to avoid false "not running" reports.
-    let is_running = check_daemon("docker", &["info"])
-        || PathBuf::from("/var/run/docker.sock").exists();
+    let info = command_output("docker", &["info", "--format", "{{json .}}"])
+        .and_then(|output| serde_json::from_str::<DockerInfo>(&output).ok());
+    let is_running = info.is_some() || PathBuf::from("/var/run/docker.sock").exists();
 
     let storage_root = PathBuf::from("/var/lib/docker");
     let can_read = check_read_access(&storage_root);
 
-    let storage_driver = if is_running {
-        detect_docker_storage_driver()
+    let storage_driver = if let Some(info) = info {
+        docker_storage_driver(&info)
+    } else if is_running {
+        StorageDriver::Unknown
+    } else if uses_containerd_store(&storage_root) {
+        StorageDriver::ContainerdSnapshotter
     } else {
         guess_storage_driver(&storage_root)
     };
@@ -53,18 +79,47 @@ fn detect_docker() -> Option<RuntimeInfo> {
     })
 }
 
-fn detect_docker_storage_driver() -> StorageDriver {
-    // Ask the daemon directly
-    if let Some(driver) = command_output("docker", &["info", "--format", "{{.Driver}}"]) {
-        return parse_storage_driver(&driver);
+fn docker_storage_driver(info: &DockerInfo) -> StorageDriver {
+    // With the containerd image store, `.Driver` is the snapshotter name
+    // (e.g. "overlayfs") and DriverStatus reports the driver type
+    let snapshotter = info
+        .driver_status
+        .iter()
+        .flatten()
+        .any(|(_, value)| value.starts_with("io.containerd.snapshotter"));
+    if snapshotter {
+        return StorageDriver::ContainerdSnapshotter;
     }
-    StorageDriver::Unknown
+    parse_storage_driver(&info.driver)
+}
+
+/// Guess whether Docker keeps images in containerd: the graph driver's
+/// `repositories.json` is missing or empty while an embedded containerd
+/// metadata database exists.
+fn uses_containerd_store(storage_root: &Path) -> bool {
+    let embedded_meta = storage_root.join("containerd/daemon/io.containerd.metadata.v1.bolt/meta.db");
+    if !embedded_meta.is_file() {
+        return false;
+    }
+
+    let has_graph_images = std::fs::read_dir(storage_root.join("image"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .any(|driver_dir| {
+            std::fs::read_to_string(driver_dir.path().join("repositories.json"))
+                .ok()
+                .and_then(|data| serde_json::from_str::<Repositories>(&data).ok())
+                .is_some_and(|repos| !repos.repositories.is_empty())
+        });
+    !has_graph_images
 }
 
-fn guess_storage_driver(storage_root: &PathBuf) -> StorageDriver {
+fn guess_storage_driver(storage_root: &Path) -> StorageDriver {
     // Guess by checking which directories exist
     let candidates = [
         ("overlay2", StorageDriver::Overlay2),
+        ("overlay", StorageDriver::Overlay2), // containers/storage (Podman)
         ("fuse-overlayfs", StorageDriver::Fuse),
         ("btrfs", StorageDriver::Btrfs),
         ("zfs", StorageDriver::Zfs),
@@ -63,6 +63,8 @@ pub enum StorageDriver {
     Btrfs,
     Zfs,
     Vfs,
+    /// Docker's containerd image store (`containerd-snapshotter` feature)
+    ContainerdSnapshotter,
     Unknown,
 }
 
@@ -74,6 +76,7 @@ impl fmt::Display for StorageDriver {
             StorageDriver::Btrfs => write!(f, "btrfs"),
             StorageDriver::Zfs => write!(f, "zfs"),
             StorageDriver::Vfs => write!(f, "vfs"),
+            StorageDriver::ContainerdSnapshotter => write!(f, "containerd-snapshotter"),
             StorageDriver::Unknown => write!(f, "unknown"),
         }
     }
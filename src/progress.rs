@@ -28,4 +28,10 @@ impl Spinner {
         self.bar.finish_and_clear();
         eprintln!("{} {}", "✔".green(), message.into());
     }
+
+    /// Clear the spinner and print a `✘ message` line to stderr.
+    pub fn fail(self, message: impl Into<String>) {
+        self.bar.finish_and_clear();
+        eprintln!("{} {}", "✘".red(), message.into());
+    }
 }